@@ -0,0 +1,429 @@
+use crate::converter::{Converter, ReportKind};
+use crate::error::{Error, Result};
+use crate::modules::{
+    context::Context,
+    dividend_list::dividend_list::DividendList,
+    excel::lib::ExcelAccessor,
+    grouping::GroupBy,
+    profit_and_loss::profit_and_loss::ProfitAndLoss,
+    report::{
+        pipeline::{Hooks, PipelineHook},
+        record::ReportModel,
+    },
+    summary::{lib::SummaryWriter, summary::Summary},
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// 出力先の名前の既定値。レポートの種類ごとに1つのブックにまとめる
+pub const DEFAULT_NAMING: &str = "{report}.xlsx";
+/// 出力先の名前に使える置き換え文字
+const PLACEHOLDERS: &[&str] = &["{stem}", "{report}"];
+/// 実現損益と配当金を合わせたシートを書き込むブックの、置き換え文字に入れる名前
+const COMBINED_NAME: &str = "combined";
+
+/// 変換したCSVファイル
+#[derive(Debug)]
+pub struct BatchFile {
+    pub csv_filepath: PathBuf,  // CSVファイル
+    pub report: ReportKind,     // レポートの種類
+    pub rows: usize,            // 書き込んだ明細の行数
+    pub total: i64,             // 実現損益/配当金の合計
+    pub xlsx_filepath: PathBuf, // 書き込んだブック
+}
+
+/// 一括変換の結果
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub converted: Vec<BatchFile>,       // 変換したファイル
+    pub skipped: Vec<(PathBuf, String)>, // 対象外のファイルと理由
+    pub errors: Vec<(PathBuf, Error)>,   // 変換できなかったファイル
+    pub warnings: Vec<String>,           // 変換はしたが確認が必要な点
+    pub combined: Option<PathBuf>,       // 実現損益と配当金を合わせたシートを書き込んだブック
+}
+
+/// ディレクトリ内のCSVをまとめて変換する
+///
+/// ファイル名からレポートの種類を判定し、出力先の名前のテンプレートでブックを決める。
+/// 同じブック・同じ種類のCSVは1つのシートにまとめる
+pub struct Batch {
+    context: Context,
+    naming: String,
+    output_dir: Option<PathBuf>,
+    grouping: Vec<GroupBy>,
+    template_filepath: Option<PathBuf>,
+    hooks: Hooks,
+}
+
+impl Batch {
+    pub fn new(context: Context) -> Self {
+        Batch {
+            context,
+            naming: DEFAULT_NAMING.to_string(),
+            output_dir: None,
+            grouping: Vec::new(),
+            template_filepath: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// 出力先の名前のテンプレート。`{stem}`はCSVのファイル名(拡張子なし)、`{report}`はレポートの種類
+    ///
+    /// 置き換え文字を含まない場合は、すべてのCSVを1つのブックに書き込む
+    pub fn with_naming(mut self, naming: impl Into<String>) -> Self {
+        self.naming = naming.into();
+        self
+    }
+
+    /// 出力先のディレクトリ。省略時は入力のディレクトリ
+    pub fn with_output_dir(mut self, output_dir: Option<PathBuf>) -> Self {
+        self.output_dir = output_dir;
+        self
+    }
+
+    /// グループ化の単位を上書きする。空の場合は設定ファイルの値を使う
+    pub fn with_grouping(mut self, grouping: Vec<GroupBy>) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// 出力のレイアウトに使うテンプレートを上書きする
+    pub fn with_template(mut self, template_filepath: Option<PathBuf>) -> Self {
+        self.template_filepath = template_filepath;
+        self
+    }
+
+    /// 各CSVの変換に使うフックを追加する
+    pub fn with_hook(mut self, hook: Arc<dyn PipelineHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// `input_dir`直下のCSVファイルを変換する。1ファイルの失敗では止めずに結果にまとめる
+    pub fn run(&self, input_dir: &Path) -> Result<BatchReport> {
+        self.convert_files(&csv_files(input_dir)?, input_dir)
+    }
+
+    /// 指定したCSVファイルを変換する。出力先のディレクトリが未指定なら`default_output_dir`に書き込む
+    pub fn convert_files(
+        &self,
+        csv_filepaths: &[PathBuf],
+        default_output_dir: &Path,
+    ) -> Result<BatchReport> {
+        self.validate_naming()?;
+        let output_dir = self.output_dir.as_deref().unwrap_or(default_output_dir);
+        let mut report = BatchReport::default();
+
+        // 出力先のブック・レポートの種類ごとに振り分ける
+        let mut routes: BTreeMap<PathBuf, BTreeMap<String, (ReportKind, Vec<PathBuf>)>> =
+            BTreeMap::new();
+        for csv_filepath in csv_filepaths.iter().cloned() {
+            match ReportKind::detect(&csv_filepath, &self.context.settings) {
+                Ok(kind) => {
+                    let xlsx_filepath = self.route(&csv_filepath, &kind, output_dir);
+                    routes
+                        .entry(xlsx_filepath)
+                        .or_default()
+                        .entry(kind.key().to_string())
+                        .or_insert_with(|| (kind, Vec::new()))
+                        .1
+                        .push(csv_filepath);
+                }
+                Err(e) => report.skipped.push((csv_filepath, e.to_string())),
+            }
+        }
+
+        let settings = &self.context.settings;
+        let mut summaries: BTreeMap<String, Summary> = BTreeMap::new();
+        for (xlsx_filepath, kinds) in routes {
+            // 明細のシート名が同じ種類どうしは、後に書き込んだ方でシートが上書きされる
+            let mut sheet_titles = BTreeMap::<String, usize>::new();
+            for (kind, _) in kinds.values() {
+                let sheet_title = kind
+                    .definition(settings)
+                    .map(|definition| definition.sheet_title(settings).to_string())
+                    .unwrap_or_else(|_| settings.sheet_title.clone());
+                *sheet_titles.entry(sheet_title).or_default() += 1;
+            }
+            for (sheet_title, _) in sheet_titles.iter().filter(|(_, count)| **count > 1) {
+                report.warnings.push(format!(
+                    "'{}' receives several report kinds; each overwrites the sheet '{}'. Add {{report}} to the naming template to keep them apart.",
+                    xlsx_filepath.display(),
+                    sheet_title
+                ));
+            }
+            for (key, (kind, csv_filepaths)) in kinds {
+                if let Some(summary) =
+                    self.convert_group(kind, &csv_filepaths, &xlsx_filepath, &mut report)
+                {
+                    summaries.entry(key).or_default().merge(summary);
+                }
+            }
+        }
+
+        // 実現損益と配当金を両方変換した場合は、両方を合わせた月別・年別のシートを書き込む。
+        // 置き換え文字があれば`combined`に置き換えたブック、なければすべてを書き込んだブックに書き込む
+        if let (Some(sheet_title), Some(profit_and_loss), Some(dividends)) = (
+            &settings.combined_summary_sheet_title,
+            summaries.get(ProfitAndLoss::KEY),
+            summaries.get(DividendList::KEY),
+        ) {
+            let xlsx_filepath = output_dir.join(
+                self.naming
+                    .replace("{stem}", COMBINED_NAME)
+                    .replace("{report}", COMBINED_NAME),
+            );
+            match self.write_combined(&xlsx_filepath, sheet_title, profit_and_loss, dividends) {
+                Ok(()) => report.combined = Some(xlsx_filepath),
+                Err(e) => report
+                    .errors
+                    .push((xlsx_filepath.clone(), e.with_path(&xlsx_filepath))),
+            }
+        }
+        Ok(report)
+    }
+
+    /// 同じブック・同じ種類のCSVを1つの`TemplateManager`に読み込んで書き込む
+    ///
+    /// 書き込めた場合は、書き込んだ明細のサマリーを返す
+    fn convert_group(
+        &self,
+        kind: ReportKind,
+        csv_filepaths: &[PathBuf],
+        xlsx_filepath: &Path,
+        report: &mut BatchReport,
+    ) -> Option<Summary> {
+        let converter = self.converter(kind.clone());
+        let manager = match converter.manager() {
+            Ok(manager) => manager,
+            Err(e) => {
+                report.errors.extend(
+                    csv_filepaths
+                        .iter()
+                        .map(|csv_filepath| (csv_filepath.clone(), Error::Config(e.to_string()))),
+                );
+                return None;
+            }
+        };
+        let mut converted = Vec::new();
+        let mut rows = Vec::new();
+        for csv_filepath in csv_filepaths {
+            // 解釈できないファイルの途中までの明細を混ぜないよう、先に単独で変換する
+            let load = || -> Result<_> {
+                let file_rows = manager.get(csv_filepath)?;
+                let grouped = manager.build(file_rows.clone())?;
+                let count = grouped.transactions().len();
+                let total = grouped.summary().total();
+                Ok((file_rows, count, total, grouped.warnings()))
+            };
+            match load() {
+                Ok((file_rows, count, total, warnings)) => {
+                    let filename = csv_filepath
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy();
+                    report.warnings.extend(
+                        warnings
+                            .into_iter()
+                            .map(|warning| format!("{filename}: {warning}")),
+                    );
+                    converted.push(BatchFile {
+                        csv_filepath: csv_filepath.clone(),
+                        report: kind.clone(),
+                        rows: count,
+                        total,
+                        xlsx_filepath: xlsx_filepath.to_path_buf(),
+                    });
+                    rows.extend(file_rows);
+                }
+                Err(e) => report
+                    .errors
+                    .push((csv_filepath.clone(), e.with_path(csv_filepath))),
+            }
+        }
+        if converted.is_empty() {
+            return None;
+        }
+
+        // ブックに書き込めなかった場合は、まとめたCSVを変換済みにしない
+        match manager.build(rows).and_then(|grouped| {
+            grouped.write(xlsx_filepath)?;
+            Ok(grouped.summary())
+        }) {
+            Ok(summary) => {
+                report.converted.extend(converted);
+                Some(summary)
+            }
+            Err(e) => {
+                report
+                    .errors
+                    .push((xlsx_filepath.to_path_buf(), e.with_path(xlsx_filepath)));
+                None
+            }
+        }
+    }
+
+    /// 実現損益と配当金を合わせた月別・年別のシートを既存のブックに書き込む
+    fn write_combined(
+        &self,
+        xlsx_filepath: &Path,
+        sheet_title: &str,
+        profit_and_loss: &Summary,
+        dividends: &Summary,
+    ) -> Result<()> {
+        let mut excel_accessor =
+            ExcelAccessor::read_book(&self.context, sheet_title, xlsx_filepath)?;
+        SummaryWriter::new(&self.context.settings, profit_and_loss, &[]).write_combined(
+            &mut excel_accessor,
+            sheet_title,
+            dividends,
+        )?;
+        excel_accessor.save_book()
+    }
+
+    /// CSVファイルを書き込むブック。レポートの種類を判定できなければ`None`
+    pub(crate) fn xlsx_filepath(
+        &self,
+        csv_filepath: &Path,
+        default_output_dir: &Path,
+    ) -> Option<PathBuf> {
+        let kind = ReportKind::detect(csv_filepath, &self.context.settings).ok()?;
+        let output_dir = self.output_dir.as_deref().unwrap_or(default_output_dir);
+        Some(self.route(csv_filepath, &kind, output_dir))
+    }
+
+    fn route(&self, csv_filepath: &Path, kind: &ReportKind, output_dir: &Path) -> PathBuf {
+        output_dir.join(self.expand_naming(csv_filepath, kind))
+    }
+
+    fn converter(&self, kind: ReportKind) -> Converter {
+        self.hooks.iter().fold(
+            Converter::new(self.context.clone(), kind)
+                .with_grouping(self.grouping.clone())
+                .with_template(self.template_filepath.clone()),
+            |converter, hook| converter.with_hook(hook.clone()),
+        )
+    }
+
+    fn validate_naming(&self) -> Result<()> {
+        let mut rest = self.naming.clone();
+        for placeholder in PLACEHOLDERS {
+            rest = rest.replace(placeholder, "");
+        }
+        if self.naming.trim().is_empty() || rest.contains(['{', '}']) {
+            return Err(Error::Config(format!(
+                "Invalid naming template '{}'. Use a file name with {} such as \"{DEFAULT_NAMING}\".",
+                self.naming,
+                PLACEHOLDERS.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    fn expand_naming(&self, csv_filepath: &Path, kind: &ReportKind) -> String {
+        let stem = csv_filepath
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        self.naming
+            .replace("{stem}", &stem)
+            .replace("{report}", kind.key())
+    }
+}
+
+/// 拡張子が`.csv`のファイルか
+pub fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+/// ディレクトリ直下のCSVファイルを名前順に返す
+pub fn csv_files(input_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(input_dir).map_err(|e| Error::input(input_dir, e))?;
+    let mut csv_filepaths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| Error::input(input_dir, e))?.path();
+        if path.is_file() && is_csv(&path) {
+            csv_filepaths.push(path);
+        }
+    }
+    csv_filepaths.sort();
+    Ok(csv_filepaths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::settings::Settings;
+    use tempfile::TempDir;
+
+    fn write_csvs(input_dir: &Path) {
+        fs::write(
+            input_dir.join("realized_pl_2024.csv"),
+            "約定日,受渡日,銘柄コード,銘柄名,口座,信用区分,売却/決済,数量[株],売却/決済単価[円],売却/決済額[円],平均取得価額[円],実現損益[円]\n\
+             2024/01/10,2024/01/12,7203,トヨタ自動車,特定,,現物,100,\"3,000.0\",\"300,000\",\"2,900.0\",\"10,000\"\n",
+        )
+        .unwrap();
+        fs::write(
+            input_dir.join("dividendlist_2024.csv"),
+            "入金日(受渡日),商品,口座,銘柄コード,銘柄,受取通貨,単価[円/現地通貨],数量[株/口],配当・分配金（税引前）[円/現地通貨],税額[円/現地通貨],受取金額[円/現地通貨]\n\
+             2024/03/20,国内株式,特定,7203,トヨタ自動車,円,30,100,\"3,000\",609,\"2,391\"\n",
+        )
+        .unwrap();
+    }
+
+    fn sheet_titles(xlsx_filepath: &Path) -> Vec<String> {
+        let book = umya_spreadsheet::reader::xlsx::read(xlsx_filepath).unwrap();
+        book.get_sheet_collection()
+            .iter()
+            .map(|sheet| sheet.get_name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn one_workbook_keeps_both_detail_sheets_and_the_combined_sheet() {
+        let dir = TempDir::new().unwrap();
+        write_csvs(dir.path());
+        let settings = Settings::defaults();
+        let report = Batch::new(Context::from(Settings::defaults()))
+            .with_naming("all.xlsx")
+            .run(dir.path())
+            .unwrap();
+
+        let xlsx_filepath = dir.path().join("all.xlsx");
+        assert_eq!(report.converted.len(), 2);
+        assert!(report.errors.is_empty());
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(report.combined.as_deref(), Some(xlsx_filepath.as_path()));
+        let titles = sheet_titles(&xlsx_filepath);
+        for title in [
+            &settings.sheet_title,
+            &settings.sheet_titles[DividendList::KEY],
+            settings.combined_summary_sheet_title.as_ref().unwrap(),
+        ] {
+            assert!(titles.contains(title), "{title} is missing from {titles:?}");
+        }
+    }
+
+    #[test]
+    fn separate_workbooks_write_the_combined_sheet_to_its_own_workbook() {
+        let dir = TempDir::new().unwrap();
+        write_csvs(dir.path());
+        let settings = Settings::defaults();
+        let report = Batch::new(Context::from(Settings::defaults()))
+            .run(dir.path())
+            .unwrap();
+
+        let xlsx_filepath = dir.path().join("combined.xlsx");
+        assert_eq!(report.combined.as_deref(), Some(xlsx_filepath.as_path()));
+        assert_eq!(
+            sheet_titles(&xlsx_filepath),
+            [settings.combined_summary_sheet_title.unwrap()]
+        );
+        assert!(
+            sheet_titles(&dir.path().join("profit_and_loss.xlsx")).contains(&settings.sheet_title)
+        );
+    }
+}
@@ -0,0 +1,607 @@
+use crate::error::{Error, Result};
+use crate::modules::{
+    broker::Broker,
+    context::Context,
+    csv::lib::CSVAccessor,
+    dividend_list::dividend_list::DividendList,
+    excel::lib::ExcelAccessor,
+    grouping::GroupBy,
+    holdings::holdings::Holdings,
+    lot_matching::LotMatcher,
+    profit_and_loss::profit_and_loss::ProfitAndLoss,
+    report::{
+        custom::CustomRecord,
+        definition::ReportDefinition,
+        engine::ReportManager,
+        pipeline::{Hooks, PipelineHook},
+        record::{FieldRole, ReportModel, ReportRecord},
+    },
+    security_master::SecurityMaster,
+    settings::Settings,
+    summary::{
+        lib::label,
+        sheets::{DividendSheets, StatisticsSheet},
+        statistics::{Statistic, StatisticKind, TradeStatistics},
+        summary::{CombinedMonth, Summary},
+    },
+    table::{Cell, RowKind, Table},
+    template_pattern::{Report, TemplateManager, TemplateStruct},
+};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// 変換するレポートの種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportKind {
+    ProfitAndLoss,  // 実現損益
+    DividendList,   // 配当金
+    Holdings,       // 保有証券
+    Custom(String), // 設定ファイルの`reports`で定義したレポートのキー
+}
+
+impl ReportKind {
+    /// 設定ファイルでレポートを指定するキー
+    pub fn key(&self) -> &str {
+        match self {
+            ReportKind::ProfitAndLoss => ProfitAndLoss::KEY,
+            ReportKind::DividendList => DividendList::KEY,
+            ReportKind::Holdings => Holdings::KEY,
+            ReportKind::Custom(key) => key,
+        }
+    }
+
+    /// 組み込みのレポートと、設定ファイルで定義したレポートをすべて返す
+    pub fn all(settings: &Settings) -> Vec<Self> {
+        let mut kinds = vec![
+            ReportKind::ProfitAndLoss,
+            ReportKind::DividendList,
+            ReportKind::Holdings,
+        ];
+        kinds.extend(settings.reports.keys().cloned().map(ReportKind::Custom));
+        kinds
+    }
+
+    /// CSVファイル名の接頭辞からレポートの種類を判定する
+    pub fn detect(csv_filepath: &Path, settings: &Settings) -> Result<Self> {
+        let filename = csv_filepath
+            .file_name()
+            .and_then(|filename| filename.to_str())
+            .ok_or_else(|| Error::UnknownReport(csv_filepath.to_path_buf()))?;
+
+        if filename.starts_with(&settings.prefix_profit_and_loss) {
+            return Ok(ReportKind::ProfitAndLoss);
+        }
+        if filename.starts_with(&settings.prefix_dividendlist) {
+            return Ok(ReportKind::DividendList);
+        }
+        if filename.starts_with(&settings.prefix_holdings) {
+            return Ok(ReportKind::Holdings);
+        }
+        settings
+            .reports
+            .iter()
+            .find(|(_, report)| filename.starts_with(&report.prefix))
+            .map(|(key, _)| ReportKind::Custom(key.clone()))
+            .ok_or_else(|| Error::UnknownReport(csv_filepath.to_path_buf()))
+    }
+
+    /// 設定ファイル、既定値の順にグループ化の単位を決める
+    pub fn grouping(&self, settings: &Settings) -> Vec<GroupBy> {
+        if let Some(grouping) = settings.grouping.get(self.key()) {
+            return grouping.clone();
+        }
+        match self {
+            ReportKind::ProfitAndLoss => vec![GroupBy::Day],
+            ReportKind::DividendList => vec![GroupBy::Month],
+            ReportKind::Holdings => vec![GroupBy::Account],
+            ReportKind::Custom(key) => {
+                let Some(report) = settings.reports.get(key) else {
+                    return Vec::new();
+                };
+                if !report.grouping.is_empty() {
+                    return report.grouping.clone();
+                }
+                // 日付のフィールドがあれば月ごとに小計を入れる
+                let has_date = report
+                    .fields
+                    .iter()
+                    .any(|field| field.role == Some(FieldRole::Date));
+                if has_date {
+                    vec![GroupBy::Month]
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+
+    /// フィールド・グラフ・シート名の定義を返す
+    pub fn definition(&self, settings: &Settings) -> Result<ReportDefinition> {
+        match self {
+            ReportKind::ProfitAndLoss => Ok(ProfitAndLoss::definition()),
+            ReportKind::DividendList => Ok(DividendList::definition()),
+            ReportKind::Holdings => Ok(Holdings::definition()),
+            ReportKind::Custom(key) => settings
+                .reports
+                .get(key)
+                .map(|report| ReportDefinition::from_setting(key, report))
+                .ok_or_else(|| {
+                    Error::Config(format!(
+                        "Unknown report '{key}'. Define it under \"reports\" in the settings."
+                    ))
+                }),
+        }
+    }
+}
+
+/// `Converter::inspect`の結果
+#[derive(Debug)]
+pub struct Inspection {
+    pub report: ReportKind,     // レポートの種類
+    pub broker: Option<Broker>, // ヘッダーから判定した証券会社
+    pub encoding: &'static str, // 文字コード
+    pub columns: Vec<String>,   // CSVの列名
+    pub rows: usize,            // 明細の行数
+}
+
+/// `Converter::validate`の結果
+#[derive(Debug)]
+pub struct Validation {
+    pub rows: usize,           // 明細の行数
+    pub problems: Vec<Error>,  // 解釈できない行などの問題
+    pub warnings: Vec<String>, // 変換は続けるが確認が必要な点
+}
+
+/// CSVを読み込み、Excelのレポートに変換する
+///
+/// グループ化・列・テンプレートは設定ファイルの値を既定値にする
+pub struct Converter {
+    context: Context,
+    report: ReportKind,
+    template_filepath: Option<PathBuf>,
+    grouping: Vec<GroupBy>,
+    hooks: Hooks,
+}
+
+impl Converter {
+    pub fn new(context: Context, report: ReportKind) -> Self {
+        let settings = &context.settings;
+        let template_filepath = settings.template_path.as_ref().map(PathBuf::from);
+        let grouping = report.grouping(settings);
+        Converter {
+            context,
+            report,
+            template_filepath,
+            grouping,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// グループ化の単位を上書きする。空の場合は設定ファイルの値を使う
+    pub fn with_grouping(mut self, grouping: Vec<GroupBy>) -> Self {
+        if !grouping.is_empty() {
+            self.grouping = grouping;
+        }
+        self
+    }
+
+    /// 出力のレイアウトに使うテンプレートを上書きする
+    pub fn with_template(mut self, template_filepath: Option<PathBuf>) -> Self {
+        if template_filepath.is_some() {
+            self.template_filepath = template_filepath;
+        }
+        self
+    }
+
+    /// パイプラインの段階の間に処理を挟むフックを追加する。登録順に呼び出す
+    pub fn with_hook(mut self, hook: Arc<dyn PipelineHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn report(&self) -> &ReportKind {
+        &self.report
+    }
+
+    /// レポートを書き込む`TemplateManager`を返す
+    pub fn manager(&self) -> Result<Box<dyn TemplateManager>> {
+        let columns = self
+            .context
+            .settings
+            .columns
+            .get(self.report.key())
+            .cloned()
+            .unwrap_or_default();
+        let template_struct = TemplateStruct::new(
+            self.template_filepath.clone(),
+            self.grouping.clone(),
+            columns,
+        );
+        let definition = self.report.definition(&self.context.settings)?;
+        let context = self.context.clone();
+        // 取引統計・配当推移・配当予想のシートは、サマリーの直後に書き込む
+        let settings = &self.context.settings;
+        let key = self.report.key();
+        let mut hooks: Hooks = Vec::new();
+        if settings.statistics_sheet_titles.contains_key(key) {
+            hooks.push(Arc::new(StatisticsSheet));
+        }
+        if settings.dividend_history_sheet_titles.contains_key(key)
+            || settings.dividend_forecast_sheet_titles.contains_key(key)
+        {
+            hooks.push(Arc::new(DividendSheets::from_settings(settings)?));
+        }
+        // 銘柄マスターの項目でも絞り込めるよう、銘柄マスターを先に呼び出す
+        if let Some(security_master_path) = &self.context.settings.security_master_path {
+            hooks.push(Arc::new(SecurityMaster::load(Path::new(
+                security_master_path,
+            ))?));
+        }
+        // 保有期間は実現損益の売却にだけ対応付ける
+        if let (ReportKind::ProfitAndLoss, Some(execution_history_path)) =
+            (&self.report, &self.context.settings.execution_history_path)
+        {
+            hooks.push(Arc::new(LotMatcher::load(Path::new(
+                execution_history_path,
+            ))?));
+        }
+        hooks.extend(self.hooks.iter().cloned());
+        Ok(match self.report {
+            ReportKind::ProfitAndLoss => Box::new(
+                ReportManager::<ProfitAndLoss>::new(context, template_struct, definition)
+                    .with_hooks(hooks),
+            ),
+            ReportKind::DividendList => Box::new(
+                ReportManager::<DividendList>::new(context, template_struct, definition)
+                    .with_hooks(hooks),
+            ),
+            ReportKind::Holdings => Box::new(
+                ReportManager::<Holdings>::new(context, template_struct, definition)
+                    .with_hooks(hooks),
+            ),
+            ReportKind::Custom(_) => Box::new(
+                ReportManager::<CustomRecord>::new(context, template_struct, definition)
+                    .with_hooks(hooks),
+            ),
+        })
+    }
+
+    /// CSVファイルを読み込んで解釈し、グループ化したレポートを返す
+    fn load<'a>(
+        manager: &'a dyn TemplateManager,
+        csv_filepath: &Path,
+    ) -> Result<Box<dyn Report + 'a>> {
+        let rows = manager.get(csv_filepath)?;
+        manager.build(rows).map_err(|e| e.with_path(csv_filepath))
+    }
+
+    /// 文字コード・列・行数と、ヘッダーから判定した証券会社を返す
+    pub fn inspect(&self, csv_filepath: &Path) -> Result<Inspection> {
+        let info = CSVAccessor::inspect(csv_filepath)?;
+        let rows = self.manager()?.get(csv_filepath)?.len();
+        Ok(Inspection {
+            report: self.report.clone(),
+            broker: Broker::detect(self.report.key(), &info.headers),
+            encoding: info.encoding,
+            columns: info.headers.iter().map(String::from).collect(),
+            rows,
+        })
+    }
+
+    /// ファイルに書き込まずに全行を解釈し、見つかった問題をすべて返す
+    ///
+    /// 全行を解釈できた場合は、メモリ上のブックに書き込んで集計・税額の計算も確認する
+    pub fn validate(&self, csv_filepath: &Path) -> Result<Validation> {
+        let manager = self.manager()?;
+        let records = manager.get(csv_filepath)?;
+        let rows = records.len();
+        let mut problems: Vec<Error> = match manager.problems(&records) {
+            Ok(problems) => problems
+                .into_iter()
+                .map(|e| e.with_path(csv_filepath))
+                .collect(),
+            Err(e) => vec![e],
+        };
+        let mut warnings = Vec::new();
+        if problems.is_empty() {
+            let write = manager.build(records).and_then(|report| {
+                report.write_to(&mut io::sink())?;
+                Ok(report.warnings())
+            });
+            match write {
+                Ok(report_warnings) => warnings = report_warnings,
+                Err(e) => problems.push(e),
+            }
+        }
+        Ok(Validation {
+            rows,
+            problems,
+            warnings,
+        })
+    }
+
+    /// XLSXに書き込まずに、明細のシートと同じレイアウトの表を返す
+    pub fn preview(&self, csv_filepath: &Path) -> Result<Table> {
+        let manager = self.manager()?;
+        let report = Self::load(manager.as_ref(), csv_filepath)?;
+        let sheet_title = report.sheet_title();
+        let mut excel_accessor = ExcelAccessor::new_book(&self.context, sheet_title)?;
+        report.render(&mut excel_accessor)?;
+        Ok(excel_accessor.to_table(sheet_title).unwrap_or_default())
+    }
+
+    /// CSVファイルを読み込み、月別・銘柄別・口座別に集計する
+    pub fn summarize(&self, csv_filepath: &Path) -> Result<Summary> {
+        let manager = self.manager()?;
+        let report = Self::load(manager.as_ref(), csv_filepath)?;
+        Ok(report.summary())
+    }
+
+    /// 月ごとの合計と年初来累計を並べ、年の終わりに年間の合計を入れた表を返す
+    pub fn summary_table(&self, summary: &Summary) -> Table {
+        let mut table = Table::new();
+        table.push(
+            RowKind::Header,
+            vec![
+                Cell::left(label("year")),
+                Cell::right(label("month")),
+                Cell::right(label("total")),
+                Cell::right(label("year_to_date")),
+            ],
+        );
+
+        let monthly_totals = summary.monthly_totals();
+        for (index, monthly) in monthly_totals.iter().enumerate() {
+            table.push(
+                RowKind::Record,
+                vec![
+                    Cell::left(monthly.year.to_string()),
+                    Cell::right(monthly.month.to_string()),
+                    Cell::amount(monthly.total),
+                    Cell::amount(monthly.year_to_date),
+                ],
+            );
+            let is_year_end = monthly_totals
+                .get(index + 1)
+                .is_none_or(|next| next.year != monthly.year);
+            if is_year_end {
+                table.push(
+                    RowKind::Footer,
+                    vec![
+                        Cell::left(monthly.year.to_string()),
+                        Cell::right(label("total")),
+                        Cell::amount(monthly.year_to_date),
+                    ],
+                );
+            }
+        }
+        table
+    }
+
+    /// 実現損益と配当金を月ごとに並べ、両方の合計と年初来累計、年の終わりに年間の合計を入れた表を返す
+    ///
+    /// どちらもない月は表に入れない
+    pub fn combined_table(&self, profit_and_loss: &Summary, dividends: &Summary) -> Table {
+        let mut table = Table::new();
+        table.push(
+            RowKind::Header,
+            vec![
+                Cell::left(label("year")),
+                Cell::right(label("month")),
+                Cell::right(label("realized_profit_and_loss")),
+                Cell::right(label("dividends")),
+                Cell::right(label("total")),
+                Cell::right(label("year_to_date")),
+            ],
+        );
+
+        for (year, months) in Summary::combined_pivot(profit_and_loss, dividends) {
+            let mut year_to_date = CombinedMonth::default();
+            for (month, combined) in months.iter().enumerate() {
+                year_to_date.profit_and_loss += combined.profit_and_loss;
+                year_to_date.dividends += combined.dividends;
+                if *combined == CombinedMonth::default() {
+                    continue;
+                }
+                table.push(
+                    RowKind::Record,
+                    vec![
+                        Cell::left(year.to_string()),
+                        Cell::right((month + 1).to_string()),
+                        Cell::amount(combined.profit_and_loss),
+                        Cell::amount(combined.dividends),
+                        Cell::amount(combined.total()),
+                        Cell::amount(year_to_date.total()),
+                    ],
+                );
+            }
+            table.push(
+                RowKind::Footer,
+                vec![
+                    Cell::left(year.to_string()),
+                    Cell::right(label("total")),
+                    Cell::amount(year_to_date.profit_and_loss),
+                    Cell::amount(year_to_date.dividends),
+                    Cell::amount(year_to_date.total()),
+                ],
+            );
+        }
+        table
+    }
+
+    /// 年別と口座別の取引統計の表を返す。項目を行、年・口座と全期間を列に並べる
+    pub fn statistics_tables(&self, summary: &Summary) -> [Table; 2] {
+        let yearly = summary
+            .statistics_by_year()
+            .into_iter()
+            .map(|(year, statistics)| (year.to_string(), statistics));
+        [
+            self.statistics_table(yearly, summary),
+            self.statistics_table(summary.statistics_by_account(), summary),
+        ]
+    }
+
+    /// 取引統計の項目を行、`columns`と全期間を列に並べる
+    fn statistics_table(
+        &self,
+        columns: impl IntoIterator<Item = (String, TradeStatistics)>,
+        summary: &Summary,
+    ) -> Table {
+        let (labels, mut columns): (Vec<String>, Vec<TradeStatistics>) =
+            columns.into_iter().unzip();
+        columns.push(summary.statistics());
+
+        let mut table = Table::new();
+        let mut header = vec![Cell::left(label("statistic"))];
+        header.extend(labels.into_iter().map(Cell::right));
+        header.push(Cell::right(label("all_periods")));
+        table.push(RowKind::Header, header);
+
+        let values: Vec<Vec<Statistic>> = columns.iter().map(TradeStatistics::statistics).collect();
+        for (index, statistic) in values[values.len() - 1].iter().enumerate() {
+            let mut row = vec![Cell::left(statistic.label)];
+            row.extend(values.iter().map(|statistics| {
+                let Some(value) = statistics[index].value else {
+                    return Cell::right("-");
+                };
+                match statistic.kind {
+                    StatisticKind::Count => Cell::right(value.to_string()),
+                    StatisticKind::Amount => Cell::amount(value.round() as i64),
+                    StatisticKind::Percent => Cell::right(format!("{:.1}%", value * 100.0)),
+                    StatisticKind::Ratio => Cell::right(format!("{value:.2}")),
+                }
+            }));
+            table.push(RowKind::Record, row);
+        }
+        table
+    }
+
+    /// CSVファイルを読み込み、XLSXファイルに書き込む。既存のファイルは他のシートを残す
+    ///
+    /// 銘柄マスターにない銘柄コードなど、変換は続けるが確認が必要な点を返す
+    pub fn convert_file(&self, csv_filepath: &Path, xlsx_filepath: &Path) -> Result<Vec<String>> {
+        self.manager()?.execute(csv_filepath, xlsx_filepath)
+    }
+
+    /// CSVを読み込み、新しいブックを`writer`に書き込む
+    pub fn convert<R: Read, W: Write>(&self, mut reader: R, mut writer: W) -> Result<()> {
+        let manager = self.manager()?;
+        let report = manager.build(manager.read(&mut reader)?)?;
+        report.write_to(&mut writer)?;
+        Ok(())
+    }
+
+    /// CSVを読み込み、新しいブックをXLSX形式のバイト列で返す
+    pub fn convert_to_vec<R: Read>(&self, reader: R) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.convert(reader, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// 実現損益のCSVを読み込む
+pub fn parse_profit_and_loss<R: Read>(reader: R) -> Result<Vec<ProfitAndLoss>> {
+    CSVAccessor::read_from(reader)?
+        .into_iter()
+        .map(ProfitAndLoss::from_record)
+        .collect()
+}
+
+/// 配当金のCSVを読み込む
+pub fn parse_dividend_list<R: Read>(reader: R) -> Result<Vec<DividendList>> {
+    CSVAccessor::read_from(reader)?
+        .into_iter()
+        .map(DividendList::from_record)
+        .collect()
+}
+
+/// 実現損益を月別・銘柄別・口座別に集計する
+pub fn summarize_profit_and_loss(records: &[ProfitAndLoss]) -> Summary {
+    records
+        .iter()
+        .filter_map(ProfitAndLoss::to_summary_item)
+        .collect()
+}
+
+/// 配当金を月別・銘柄別・口座別に集計する
+pub fn summarize_dividend_list(records: &[DividendList]) -> Summary {
+    records
+        .iter()
+        .filter_map(DividendList::to_summary_item)
+        .collect()
+}
+
+/// 実現損益の合計・源泉徴収税額・税引後の損益を計算する
+///
+/// 源泉徴収税額は特定口座の利益にのみ`tax_rate`を掛ける
+pub fn compute_tax(records: &[ProfitAndLoss], tax_rate: f64) -> Result<ProfitAndLoss> {
+    let records: Vec<&ProfitAndLoss> = records.iter().collect();
+    let total = ProfitAndLoss::get_total(&records);
+    ProfitAndLoss::new_total_realized_profit_and_loss(total, tax_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::modules::{expression::Condition, filter::RowFilter};
+    use std::fs;
+    use tempfile::TempDir;
+
+    const HEADER: &str = "約定日,受渡日,銘柄コード,銘柄名,口座,信用区分,売却/決済,数量[株],売却/決済単価[円],売却/決済額[円],平均取得価額[円],実現損益[円]";
+
+    fn write_csv(dir: &TempDir, rows: &[&str]) -> PathBuf {
+        let csv_filepath = dir.path().join("realized_pl_2024.csv");
+        fs::write(&csv_filepath, format!("{HEADER}\n{}\n", rows.join("\n"))).unwrap();
+        csv_filepath
+    }
+
+    fn converter() -> Converter {
+        Converter::new(
+            Context::from(Settings::defaults()),
+            ReportKind::ProfitAndLoss,
+        )
+    }
+
+    #[test]
+    fn validate_reports_every_row_that_cannot_be_parsed() {
+        let dir = TempDir::new().unwrap();
+        let csv_filepath = write_csv(
+            &dir,
+            &[
+                "2024/01/10,2024/01/12,7203,トヨタ自動車,特定,,現物,100,3000,300000,2900,10000",
+                "2024/13/10,2024/01/12,7203,トヨタ自動車,特定,,現物,100,3000,300000,2900,10000",
+                "2024/01/11,2024/01/15,6758,ソニーＧ,特定,,現物,100,3000,300000,3100,abc",
+            ],
+        );
+        let validation = converter().validate(&csv_filepath).unwrap();
+        assert_eq!(validation.rows, 3);
+        let lines: Vec<_> = validation
+            .problems
+            .iter()
+            .map(|problem| match problem {
+                Error::Parse { path, line, .. } => {
+                    assert_eq!(path.as_deref(), Some(csv_filepath.as_path()));
+                    *line
+                }
+                problem => panic!("unexpected problem: {problem}"),
+            })
+            .collect();
+        assert_eq!(lines, [Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn validate_reports_a_hook_configuration_error_once() {
+        let dir = TempDir::new().unwrap();
+        let row = "2024/01/10,2024/01/12,7203,トヨタ自動車,特定,,現物,100,3000,300000,2900,10000";
+        let csv_filepath = write_csv(&dir, &[row, row, row]);
+        let filter = RowFilter::new().with_condition(Some(Condition::parse("amout < 0").unwrap()));
+        let validation = converter()
+            .with_hook(Arc::new(filter))
+            .validate(&csv_filepath)
+            .unwrap();
+        assert_eq!(validation.problems.len(), 1);
+        assert_eq!(validation.problems[0].kind(), ErrorKind::Config);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::modules::settings_validation::SettingsError;
+use csv::StringRecord;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// エラーの分類。分類ごとに終了コードが異なる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Input,    // 入力ファイルが読めない・レポートの種類が分からない
+    Encoding, // 文字コードが不正
+    Parse,    // CSVや値の解釈に失敗
+    Config,   // 設定の誤り
+    Workbook, // Excelのブックの読み書き
+    Tax,      // 税額の計算
+    Output,   // 出力先のファイル・ディレクトリに書き込めない
+    Other,    // その他
+}
+
+impl ErrorKind {
+    /// プロセスの終了コード。2はclapが引数の誤りに使う
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Input => 3,
+            ErrorKind::Encoding => 4,
+            ErrorKind::Parse => 5,
+            ErrorKind::Config => 6,
+            ErrorKind::Workbook => 7,
+            ErrorKind::Tax => 8,
+            ErrorKind::Output => 9,
+        }
+    }
+}
+
+/// ライブラリの公開APIが返すエラー
+#[derive(Debug)]
+pub enum Error {
+    /// 入力ファイル・ディレクトリの読み込み
+    Input {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// 出力先のファイル・ディレクトリへの書き込み・移動
+    Output { path: PathBuf, source: io::Error },
+    /// ファイル名からレポートの種類を判定できない
+    UnknownReport(PathBuf),
+    /// CSVが判定した文字コードとして解釈できない
+    Encoding {
+        path: Option<PathBuf>,
+        encoding: &'static str,
+        line: u64,
+    },
+    /// CSVの構造の誤り
+    Csv {
+        path: Option<PathBuf>,
+        source: csv::Error,
+    },
+    /// 日付・数値などの値の解釈
+    Parse {
+        path: Option<PathBuf>,
+        line: Option<u64>,
+        field: String,
+        value: String,
+        message: String,
+    },
+    /// 設定ファイルの読み込み
+    Config(String),
+    /// 設定値の誤り
+    Settings(SettingsError),
+    /// Excelのブックの読み書き
+    Workbook {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    /// 税額の計算
+    Tax(String),
+    /// 個々の問題を表示済みの処理の失敗。終了コードは`kind`に従う
+    Failed { kind: ErrorKind, message: String },
+    /// その他
+    Other(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// CSVの値を解釈できなかったときのエラー。行番号はレコードの位置から取る
+    pub fn parse(record: &StringRecord, field: &str, value: &str, message: impl ToString) -> Self {
+        Error::Parse {
+            path: None,
+            line: record.position().map(|position| position.line()),
+            field: field.to_string(),
+            value: value.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// 入力ファイル・ディレクトリを読み込めなかったときのエラー
+    pub fn input(path: &Path, source: io::Error) -> Self {
+        Error::Input {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+
+    /// 出力先に書き込めなかったときのエラー
+    pub fn output(path: &Path, source: io::Error) -> Self {
+        Error::Output {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn workbook(path: Option<&Path>, message: impl ToString) -> Self {
+        Error::Workbook {
+            path: path.map(Path::to_path_buf),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Input { .. } | Error::UnknownReport(_) => ErrorKind::Input,
+            Error::Encoding { .. } => ErrorKind::Encoding,
+            Error::Csv { .. } | Error::Parse { .. } => ErrorKind::Parse,
+            Error::Config(_) | Error::Settings(_) => ErrorKind::Config,
+            Error::Workbook { .. } => ErrorKind::Workbook,
+            Error::Tax(_) => ErrorKind::Tax,
+            Error::Output { .. } => ErrorKind::Output,
+            Error::Failed { kind, .. } => *kind,
+            Error::Other(_) => ErrorKind::Other,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.kind().exit_code()
+    }
+
+    /// ファイルのパスが未設定なら設定する
+    pub fn with_path(mut self, filepath: &Path) -> Self {
+        if let Error::Input { path, .. }
+        | Error::Encoding { path, .. }
+        | Error::Csv { path, .. }
+        | Error::Parse { path, .. }
+        | Error::Workbook { path, .. } = &mut self
+        {
+            path.get_or_insert_with(|| filepath.to_path_buf());
+        }
+        self
+    }
+}
+
+/// `'path': `の形でエラーの場所を表示する
+struct Location<'a>(&'a Option<PathBuf>);
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(path) => write!(f, "'{}': ", path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Input { path, source } => write!(f, "{}{source}", Location(path)),
+            Error::Output { path, source } => {
+                write!(f, "Failed to write '{}': {source}", path.display())
+            }
+            Error::UnknownReport(path) => write!(
+                f,
+                "Cannot tell the report kind from '{}'. Check the filename prefix.",
+                path.display()
+            ),
+            Error::Encoding {
+                path,
+                encoding,
+                line,
+            } => write!(
+                f,
+                "{}line {line} is not valid {encoding}. Save the CSV as UTF-8 or Shift_JIS.",
+                Location(path)
+            ),
+            Error::Csv { path, source } => {
+                write!(f, "{}failed to read CSV: {source}", Location(path))
+            }
+            Error::Parse {
+                path,
+                line,
+                field,
+                value,
+                message,
+            } => {
+                write!(f, "{}", Location(path))?;
+                if let Some(line) = line {
+                    write!(f, "line {line}: ")?;
+                }
+                write!(f, "cannot parse {field} '{value}': {message}")
+            }
+            Error::Config(message) => write!(f, "{message}"),
+            Error::Settings(e) => write!(f, "{e}"),
+            Error::Workbook { path, message } => write!(f, "{}{message}", Location(path)),
+            Error::Tax(message) => write!(f, "Failed to compute tax: {message}"),
+            Error::Failed { message, .. } => write!(f, "{message}"),
+            Error::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Input { source, .. } | Error::Output { source, .. } => Some(source),
+            Error::Csv { source, .. } => Some(source),
+            Error::Settings(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(source: csv::Error) -> Self {
+        Error::Csv { path: None, source }
+    }
+}
+
+impl From<SettingsError> for Error {
+    fn from(e: SettingsError) -> Self {
+        Error::Settings(e)
+    }
+}
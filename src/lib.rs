@@ -0,0 +1,66 @@
+//! 証券会社からダウンロードした実現損益・配当金のCSVを、Excelのレポートに変換する
+//!
+//! CSVの読み込み、集計、源泉徴収税額の計算、XLSXへの書き込みを個別に呼び出せる。
+//! 設定は`Context`で渡すため、1つのプロセスで異なる設定の変換を実行できる。
+//!
+//! ```no_run
+//! use profit_and_loss_converter::{Context, Converter, ReportKind, Settings};
+//! use std::fs::File;
+//!
+//! let context = Context::from(Settings::load(None)?);
+//! let converter = Converter::new(context, ReportKind::ProfitAndLoss);
+//! let xlsx = converter.convert_to_vec(File::open("realized_pl_2024.csv")?)?;
+//! std::fs::write("report.xlsx", xlsx)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod batch;
+mod converter;
+mod error;
+mod modules;
+mod watch;
+
+pub use batch::{Batch, BatchFile, BatchReport, DEFAULT_NAMING};
+pub use converter::{
+    compute_tax, parse_dividend_list, parse_profit_and_loss, summarize_dividend_list,
+    summarize_profit_and_loss, Converter, Inspection, ReportKind, Validation,
+};
+pub use error::{Error, ErrorKind, Result};
+pub use modules::{
+    broker::Broker,
+    context::Context,
+    csv::lib::CSVAccessor,
+    dividend_list::dividend_list::DividendList,
+    excel::lib::ExcelAccessor,
+    expression::Condition,
+    filter::RowFilter,
+    grouping::GroupBy,
+    holdings::{
+        holdings::Holdings,
+        positions::{Position, Positions},
+        price_table::PriceTable,
+    },
+    lot_matching::{ClosedTrade, Execution, LotMatcher, Side},
+    profit_and_loss::profit_and_loss::ProfitAndLoss,
+    report::{
+        custom::CustomRecord,
+        definition::{ReportDefinition, ReportSetting},
+        engine::{GroupedReport, ReportManager},
+        pipeline::{Normalized, PipelineHook, Transaction},
+        record::{
+            Aggregate, FieldRole, FieldSpec, FieldType, FieldValue, ReportModel, ReportRecord,
+        },
+    },
+    security_master::{SecurityInfo, SecurityMaster},
+    settings::{init as init_settings, Settings, SETTINGS},
+    summary::{
+        forecast::{DividendForecast, SecurityForecast},
+        statistics::TradeStatistics,
+        summary::{CombinedMonth, Summary},
+    },
+    table::{format_amount, Cell, RowKind, Table},
+    template_pattern::{Report, TemplateManager},
+};
+pub use watch::{
+    PendingFiles, Watch, WatchEvent, DEFAULT_ARCHIVE_DIR, DEFAULT_SETTLE, REPLACED_DIR,
+};
@@ -1,57 +1,551 @@
-use clap::Parser;
-use modules::dividend_list::lib::DividendListManager;
-use modules::profit_and_loss::lib::ProfitAndLossManager;
-use modules::settings::SETTINGS;
-use modules::template_pattern::TemplateManager;
-use std::error::Error;
-use std::path::PathBuf;
-
-mod modules;
+use chrono::{Local, NaiveDate};
+use clap::{Parser, Subcommand};
+use profit_and_loss_converter::{
+    format_amount, init_settings, Batch, BatchReport, Cell, Condition, Context, Converter, Error,
+    GroupBy, ReportKind, Result, RowFilter, RowKind, Settings, Table, Watch, DEFAULT_NAMING,
+    DEFAULT_SETTLE, SETTINGS,
+};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
+/// 終了コード: 0 成功 / 1 その他 / 2 引数の誤り / 3 入力ファイル / 4 文字コード /
+/// 5 CSVの解釈 / 6 設定 / 7 Excelのブック / 8 税額の計算 / 9 出力先への書き込み
 #[derive(Parser)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[clap(name = "CSVFILE")]
-    csv_filepath: PathBuf,
-    #[clap(name = "XLSXFILE")]
-    xlsx_filepath: PathBuf,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// サブコマンドを省略した場合は`convert`として実行する
+    #[clap(flatten)]
+    convert: ConvertArgs,
+    /// 設定ファイル。既定値・システム・ユーザー・カレントディレクトリの設定と環境変数より優先する
+    #[clap(long = "config", name = "CONFIG", global = true)]
+    config_path: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct ConvertArgs {
+    #[clap(name = "CSVFILE", required = true)]
+    csv_filepath: Option<PathBuf>,
+    #[clap(name = "XLSXFILE", required = true)]
+    xlsx_filepath: Option<PathBuf>,
+    /// 出力のレイアウトに使うテンプレートのXLSXファイル
+    #[clap(long = "template", name = "TEMPLATE")]
+    template_filepath: Option<PathBuf>,
+    /// グループ化の単位。カンマ区切りで上位の階層から指定する(例: year,month,security)
+    #[clap(long = "group-by", value_enum, value_delimiter = ',')]
+    grouping: Vec<GroupBy>,
+    /// XLSXに書き込まずに、明細のシートに書き込む内容を表示する
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+    #[clap(flatten)]
+    filter: FilterArgs,
 }
 
-enum FactoryID {
-    ProfitAndLoss,
-    DividendList,
+/// 明細の絞り込み。指定した条件をすべて満たす行だけを書き込む
+#[derive(clap::Args)]
+struct FilterArgs {
+    /// この日付以降の行だけを書き込む(例: 2024-01-01)
+    #[clap(long = "from", name = "FROM")]
+    from: Option<NaiveDate>,
+    /// この日付以前の行だけを書き込む(例: 2024-12-31)
+    #[clap(long = "to", name = "TO")]
+    to: Option<NaiveDate>,
+    /// 口座名にこの文字列を含む行だけを書き込む。複数指定できる
+    #[clap(long = "account", name = "ACCOUNT")]
+    accounts: Vec<String>,
+    /// 銘柄コードが一致するか、銘柄名にこの文字列を含む行だけを書き込む。複数指定できる
+    #[clap(long = "security", name = "SECURITY")]
+    securities: Vec<String>,
+    /// 実現損益/配当金がこの金額以上の行だけを書き込む
+    #[clap(
+        long = "min-amount",
+        name = "MIN_AMOUNT",
+        allow_negative_numbers = true
+    )]
+    min_amount: Option<i64>,
+    /// 実現損益/配当金がこの金額以下の行だけを書き込む
+    #[clap(
+        long = "max-amount",
+        name = "MAX_AMOUNT",
+        allow_negative_numbers = true
+    )]
+    max_amount: Option<i64>,
+    /// 条件式。比較(< <= > >= = != contains)を and・or・not と括弧で組み合わせる。
+    /// 変数はフィールド名と date・security_code・security_name・account・amount
+    /// (例: "amount < 0 and account contains 'NISA'")
+    #[clap(long = "filter", name = "EXPRESSION", value_parser = Condition::parse)]
+    condition: Option<Condition>,
 }
 
-fn create_factory(id: FactoryID, xlsx_filepath: PathBuf) -> Box<dyn TemplateManager> {
-    match id {
-        FactoryID::ProfitAndLoss => Box::new(ProfitAndLossManager::new(xlsx_filepath)),
-        FactoryID::DividendList => Box::new(DividendListManager::new(xlsx_filepath)),
+impl FilterArgs {
+    /// 条件が指定されていれば`RowFilter`を返す
+    fn row_filter(self) -> Result<Option<RowFilter>> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err(Error::Other(format!("--from {from} is after --to {to}.")));
+            }
+        }
+        let filter = RowFilter::new()
+            .with_period(self.from, self.to)
+            .with_accounts(self.accounts)
+            .with_securities(self.securities)
+            .with_amount_range(self.min_amount, self.max_amount)
+            .with_condition(self.condition);
+        Ok((!filter.is_empty()).then_some(filter))
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum Command {
+    /// CSVを読み込み、XLSXのレポートに書き込む
+    Convert(ConvertArgs),
+    /// 判定した証券会社・文字コード・列・行数を表示する
+    Inspect {
+        #[clap(name = "CSVFILE")]
+        csv_filepath: PathBuf,
+    },
+    /// XLSXに書き込まずにCSVを解釈し、問題をすべて表示する
+    Validate {
+        #[clap(name = "CSVFILE")]
+        csv_filepath: PathBuf,
+    },
+    /// 年別・月別の合計を表示する
+    Summary {
+        #[clap(name = "CSVFILE")]
+        csv_filepath: PathBuf,
+        /// 合わせて集計するCSV。実現損益と配当金を1つずつ指定すると、月ごとに両方とその合計を表示する
+        #[clap(
+            long = "combine",
+            name = "COMBINE_CSVFILE",
+            conflicts_with = "statistics"
+        )]
+        combine_filepath: Option<PathBuf>,
+        /// 月別の合計の代わりに、年別・口座別の取引統計(勝率・最大ドローダウンなど)を表示する
+        #[clap(long = "statistics")]
+        statistics: bool,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+    /// ディレクトリ内のCSVをまとめて変換し、結果の一覧を表示する
+    Batch(BatchArgs),
+    /// ディレクトリを監視し、置かれたCSVを変換してアーカイブに移す
+    Watch {
+        #[clap(flatten)]
+        batch: BatchArgs,
+        /// 変換したCSVを移すディレクトリ。省略時は DIR の archive
+        #[clap(long = "archive-dir", name = "ARCHIVE_DIR")]
+        archive_dir: Option<PathBuf>,
+        /// ファイルの変更が止まってから変換するまでの秒数
+        #[clap(long = "settle", name = "SECONDS", default_value_t = DEFAULT_SETTLE.as_secs_f64())]
+        settle: f64,
+    },
+    /// 設定ファイルを操作する
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+}
 
-    // CSVファイルとXLSXファイルのパスを取得する
-    let csv_filepath = args.csv_filepath;
-    let xlsx_filepath = args.xlsx_filepath;
-
-    let factroy_id = csv_filepath
-        .file_name()
-        .and_then(|x| x.to_str())
-        .map(|filename| {
-            if filename.starts_with(&SETTINGS.prefix_profit_and_loss) {
-                FactoryID::ProfitAndLoss
-            } else if filename.starts_with(&SETTINGS.prefix_dividendlist) {
-                FactoryID::DividendList
-            } else {
-                panic!("Invalid filename prefix.")
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// CSVを探すディレクトリ。省略時は実行ファイルと同じ場所の csv ディレクトリ
+    #[clap(name = "DIR")]
+    input_dir: Option<PathBuf>,
+    /// 出力先の名前。{stem} はCSVのファイル名、{report} はレポートの種類に置き換える。
+    /// 置き換え文字がなければすべてを1つのブックに書き込む。
+    /// 実現損益と配当金を合わせたシートは、置き換え文字を combined にしたブックに書き込む
+    #[clap(long = "naming", name = "NAMING", default_value = DEFAULT_NAMING)]
+    naming: String,
+    /// 出力先のディレクトリ。省略時は DIR
+    #[clap(long = "output-dir", name = "OUTPUT_DIR")]
+    output_dir: Option<PathBuf>,
+    /// 出力のレイアウトに使うテンプレートのXLSXファイル
+    #[clap(long = "template", name = "TEMPLATE")]
+    template_filepath: Option<PathBuf>,
+    /// グループ化の単位。カンマ区切りで上位の階層から指定する(例: year,month,security)
+    #[clap(long = "group-by", value_enum, value_delimiter = ',')]
+    grouping: Vec<GroupBy>,
+    #[clap(flatten)]
+    filter: FilterArgs,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// 説明のコメント付きの既定の設定ファイルを書き出す
+    Init {
+        /// 書き出す先。省略時はカレントディレクトリの settings.json
+        #[clap(name = "PATH", default_value = "settings.json")]
+        path: PathBuf,
+        /// 既存のファイルを上書きする
+        #[clap(long)]
+        force: bool,
+    },
+    /// 設定ファイルを各層の設定に重ねて検証する
+    Validate {
+        /// 検証する設定ファイル。省略時は`--config`、または各層の設定を検証する
+        #[clap(name = "PATH")]
+        path: Option<PathBuf>,
+    },
+    /// 有効な設定値と読み込み元を表示する
+    Show,
+}
+
+/// `config`サブコマンドを実行する
+fn run_config(command: ConfigCommand, config_path: Option<PathBuf>) -> Result<()> {
+    match command {
+        ConfigCommand::Init { path, force } => {
+            if path.exists() && !force {
+                return Err(Error::Config(format!(
+                    "'{}' already exists. Use --force to overwrite it.",
+                    path.display()
+                )));
+            }
+            fs::write(&path, Settings::commented_default()).map_err(|e| Error::output(&path, e))?;
+            println!("Wrote {}", path.display());
+        }
+        ConfigCommand::Validate { path } => {
+            let path = path.or(config_path);
+            let settings = Settings::load(path.as_deref())?;
+            print_warnings(&settings.warnings);
+            settings.validate()?;
+            match path {
+                Some(path) => println!("{}: OK", path.display()),
+                None => println!("OK"),
+            }
+        }
+        ConfigCommand::Show => {
+            let settings = Settings::load(config_path.as_deref())?;
+            print_warnings(&settings.warnings);
+            for line in settings.describe()? {
+                println!("{line}");
             }
-        })
-        .unwrap_or_else(|| panic!("Failed to extract filename."));
+            // 表示はするが、誤りがあれば警告する
+            if let Err(e) = settings.validate() {
+                eprintln!("{e}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ファイル名からレポートの種類を判定する
+fn converter(settings: &Arc<Settings>, csv_filepath: &Path) -> Result<Converter> {
+    let report = ReportKind::detect(csv_filepath, settings)?;
+    Ok(Converter::new(Context::new(settings.clone()), report))
+}
 
-    // ファクトリからTemplateManagerを生成して実行する
-    let factory = create_factory(factroy_id, xlsx_filepath);
-    factory.execute(csv_filepath)?;
+/// 絞り込みの条件を指定した`Converter`を返す
+fn filtered_converter(
+    settings: &Arc<Settings>,
+    csv_filepath: &Path,
+    row_filter: Option<RowFilter>,
+) -> Result<Converter> {
+    let converter = converter(settings, csv_filepath)?;
+    Ok(match row_filter {
+        Some(row_filter) => converter.with_hook(Arc::new(row_filter)),
+        None => converter,
+    })
+}
+
+fn run_convert(args: ConvertArgs, settings: &Arc<Settings>) -> Result<()> {
+    // CSVファイルとXLSXファイルのパスを取得する
+    let (Some(csv_filepath), Some(xlsx_filepath)) = (args.csv_filepath, args.xlsx_filepath) else {
+        return Err(Error::Other(
+            "CSVFILE and XLSXFILE are required.".to_string(),
+        ));
+    };
 
+    // 引数で設定を上書きして変換する
+    let converter = filtered_converter(settings, &csv_filepath, args.filter.row_filter()?)?
+        .with_grouping(args.grouping)
+        .with_template(args.template_filepath);
+    if args.dry_run {
+        let table = converter.preview(&csv_filepath)?;
+        println!("{}", table.render_with(use_color()));
+        eprintln!("Dry run: {} was not modified.", xlsx_filepath.display());
+        return Ok(());
+    }
+    for warning in converter.convert_file(&csv_filepath, &xlsx_filepath)? {
+        eprintln!("Warning: {warning}");
+    }
+    Ok(())
+}
+
+fn run_inspect(csv_filepath: &Path, settings: &Arc<Settings>) -> Result<()> {
+    let inspection = converter(settings, csv_filepath)?.inspect(csv_filepath)?;
+    let broker = inspection.broker.map_or("unknown", |broker| broker.name());
+    println!("file:     {}", csv_filepath.display());
+    println!("report:   {}", inspection.report.key());
+    println!("broker:   {broker}");
+    println!("encoding: {}", inspection.encoding);
+    println!("columns:  {}", inspection.columns.len());
+    for (index, column) in inspection.columns.iter().enumerate() {
+        println!("  {:>2}. {column}", index + 1);
+    }
+    println!("rows:     {}", inspection.rows);
     Ok(())
 }
+
+fn run_validate(csv_filepath: &Path, settings: &Arc<Settings>) -> Result<()> {
+    let validation = converter(settings, csv_filepath)?.validate(csv_filepath)?;
+    for problem in &validation.problems {
+        eprintln!("Error: {problem}");
+    }
+    for warning in &validation.warnings {
+        eprintln!("Warning: {warning}");
+    }
+    println!(
+        "{}: {} row(s), {} problem(s)",
+        csv_filepath.display(),
+        validation.rows,
+        validation.problems.len()
+    );
+    // 最初の問題の種類を終了コードにする
+    match validation.problems.first() {
+        Some(problem) => Err(Error::Failed {
+            kind: problem.kind(),
+            message: format!(
+                "{} has {} problem(s).",
+                csv_filepath.display(),
+                validation.problems.len()
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn run_summary(
+    csv_filepath: &Path,
+    combine_filepath: Option<&Path>,
+    statistics: bool,
+    filter: FilterArgs,
+    settings: &Arc<Settings>,
+) -> Result<()> {
+    let row_filter = filter.row_filter()?;
+    let converter = filtered_converter(settings, csv_filepath, row_filter.clone())?;
+    if let Some(combine_filepath) = combine_filepath {
+        let other = filtered_converter(settings, combine_filepath, row_filter)?;
+        let (profit_and_loss, dividends) = match (converter.report(), other.report()) {
+            (ReportKind::ProfitAndLoss, ReportKind::DividendList) => (
+                converter.summarize(csv_filepath)?,
+                other.summarize(combine_filepath)?,
+            ),
+            (ReportKind::DividendList, ReportKind::ProfitAndLoss) => (
+                other.summarize(combine_filepath)?,
+                converter.summarize(csv_filepath)?,
+            ),
+            _ => {
+                return Err(Error::Other(
+                    "--combine needs one realized P&L CSV and one dividend CSV.".to_string(),
+                ))
+            }
+        };
+        println!(
+            "{}",
+            converter
+                .combined_table(&profit_and_loss, &dividends)
+                .render_with(use_color())
+        );
+        return Ok(());
+    }
+    let summary = converter.summarize(csv_filepath)?;
+    if statistics {
+        let tables = converter.statistics_tables(&summary);
+        let rendered: Vec<String> = tables
+            .iter()
+            .map(|table| table.render_with(use_color()))
+            .collect();
+        println!("{}", rendered.join("\n\n"));
+        return Ok(());
+    }
+    println!(
+        "{}",
+        converter.summary_table(&summary).render_with(use_color())
+    );
+    Ok(())
+}
+
+/// `batch`・`watch`の入力のディレクトリ。省略時は実行ファイルと同じ場所の csv
+fn input_dir(input_dir: Option<PathBuf>) -> Result<PathBuf> {
+    match input_dir {
+        Some(input_dir) => Ok(input_dir),
+        None => Ok(env::current_exe()
+            .map_err(|e| Error::Other(format!("Cannot locate the executable: {e}")))?
+            .parent()
+            .map(|dir| dir.join("csv"))
+            .unwrap_or_else(|| PathBuf::from("csv"))),
+    }
+}
+
+fn batch(args: BatchArgs, settings: &Arc<Settings>) -> Result<Batch> {
+    let batch = Batch::new(Context::new(settings.clone()))
+        .with_naming(args.naming)
+        .with_output_dir(args.output_dir)
+        .with_grouping(args.grouping)
+        .with_template(args.template_filepath);
+    Ok(match args.filter.row_filter()? {
+        Some(row_filter) => batch.with_hook(Arc::new(row_filter)),
+        None => batch,
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().to_string(),
+    )
+}
+
+fn run_batch(args: BatchArgs, settings: &Arc<Settings>) -> Result<()> {
+    let input_dir = input_dir(args.input_dir.clone())?;
+    let report = batch(args, settings)?.run(&input_dir)?;
+
+    let mut table = Table::new();
+    table.push(
+        RowKind::Header,
+        ["file", "report", "rows", "total", "workbook"]
+            .into_iter()
+            .map(Cell::left)
+            .collect(),
+    );
+    for file in &report.converted {
+        table.push(
+            RowKind::Record,
+            vec![
+                Cell::left(file_name(&file.csv_filepath)),
+                Cell::left(file.report.key()),
+                Cell::right(file.rows.to_string()),
+                Cell::amount(file.total),
+                Cell::left(file.xlsx_filepath.display().to_string()),
+            ],
+        );
+    }
+    if !report.converted.is_empty() {
+        println!("{}", table.render_with(use_color()));
+    }
+    if let Some(combined) = &report.combined {
+        println!("Combined P&L and dividends: {}", combined.display());
+    }
+    print_problems(&report);
+    println!(
+        "{} file(s) converted ({} row(s)), {} skipped, {} error(s)",
+        report.converted.len(),
+        report.converted.iter().map(|file| file.rows).sum::<usize>(),
+        report.skipped.len(),
+        report.errors.len()
+    );
+    // 最初のエラーの種類を終了コードにする
+    match report.errors.first() {
+        Some((_, e)) => Err(Error::Failed {
+            kind: e.kind(),
+            message: format!("{} file(s) failed to convert.", report.errors.len()),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// 対象外のファイル・警告・エラーを表示する
+fn print_problems(report: &BatchReport) {
+    for (path, reason) in &report.skipped {
+        println!("Skipped {}: {reason}", file_name(path));
+    }
+    for warning in &report.warnings {
+        eprintln!("Warning: {warning}");
+    }
+    for (_, e) in &report.errors {
+        eprintln!("Error: {e}");
+    }
+}
+
+fn run_watch(
+    args: BatchArgs,
+    archive_dir: Option<PathBuf>,
+    settle: f64,
+    settings: &Arc<Settings>,
+) -> Result<()> {
+    let settle = Duration::try_from_secs_f64(settle)
+        .map_err(|e| Error::Other(format!("Invalid --settle '{settle}': {e}")))?;
+    let input_dir = input_dir(args.input_dir.clone())?;
+    let watch = Watch::new(batch(args, settings)?, &input_dir)
+        .with_archive_dir(archive_dir)
+        .with_settle(settle);
+
+    println!("Watching {} (Ctrl+C to stop)", input_dir.display());
+    watch.run(|event| {
+        let time = Local::now().format("%Y-%m-%d %H:%M:%S");
+        for file in &event.report.converted {
+            println!(
+                "[{time}] {} -> {} ({} row(s), total {})",
+                file_name(&file.csv_filepath),
+                file.xlsx_filepath.display(),
+                file.rows,
+                format_amount(file.total)
+            );
+        }
+        if let Some(archived) = &event.archived {
+            println!("[{time}] archived to {}", archived.display());
+        }
+        print_problems(&event.report);
+        if let Some(e) = &event.error {
+            eprintln!("[{time}] Error: {}: {e}", file_name(&event.csv_filepath));
+        }
+    })
+}
+
+/// 読み込みは続けられた設定の問題を表示する
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+}
+
+/// 標準出力が端末で、`NO_COLOR`が未設定の場合に色を付ける
+fn use_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+fn run(args: Args) -> Result<()> {
+    // `config`以外は設定を読み込んで検証してから実行する
+    let config_path = args.config_path;
+    let settings = || -> Result<Arc<Settings>> {
+        // 読み込みは1回だけ。以降は`SETTINGS`で参照できる
+        init_settings(config_path.as_deref())?;
+        print_warnings(&SETTINGS.warnings);
+        Ok(SETTINGS.clone())
+    };
+    match args.command.unwrap_or(Command::Convert(args.convert)) {
+        Command::Convert(convert) => run_convert(convert, &settings()?),
+        Command::Inspect { csv_filepath } => run_inspect(&csv_filepath, &settings()?),
+        Command::Validate { csv_filepath } => run_validate(&csv_filepath, &settings()?),
+        Command::Summary {
+            csv_filepath,
+            combine_filepath,
+            statistics,
+            filter,
+        } => run_summary(
+            &csv_filepath,
+            combine_filepath.as_deref(),
+            statistics,
+            filter,
+            &settings()?,
+        ),
+        Command::Batch(batch) => run_batch(batch, &settings()?),
+        Command::Watch {
+            batch,
+            archive_dir,
+            settle,
+        } => run_watch(batch, archive_dir, settle, &settings()?),
+        Command::Config(command) => run_config(command, config_path.clone()),
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("Error: {e}");
+        process::exit(e.exit_code());
+    }
+}
@@ -1,6 +1,21 @@
+pub mod broker;
+pub mod columns;
+pub mod config_layers;
+pub mod context;
 pub mod csv;
 pub mod dividend_list;
 pub mod excel;
+pub mod expression;
+pub mod filter;
+pub mod grouping;
+pub mod holdings;
+pub mod lot_matching;
 pub mod profit_and_loss;
+pub mod report;
+pub mod security_master;
 pub mod settings;
+pub mod settings_validation;
+pub mod summary;
+pub mod table;
+pub mod tax;
 pub mod template_pattern;
@@ -0,0 +1,94 @@
+use csv::StringRecord;
+
+/// CSVを出力した証券会社
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Broker {
+    Rakuten, // 楽天証券
+}
+
+/// 証券会社・レポートごとに、CSVのヘッダーで判定に使う列
+///
+/// 列がすべてあれば判定する。列の並びと、他の列の有無は問わない
+const KEY_COLUMNS: &[(Broker, &str, &[&str])] = &[
+    (
+        Broker::Rakuten,
+        "profit_and_loss",
+        &["約定日", "銘柄コード", "売却/決済", "実現損益[円]"],
+    ),
+    (
+        Broker::Rakuten,
+        "dividend_list",
+        &[
+            "入金日(受渡日)",
+            "銘柄コード",
+            "配当・分配金（税引前）[円/現地通貨]",
+            "受取金額[円/現地通貨]",
+        ],
+    ),
+    (
+        Broker::Rakuten,
+        "holdings",
+        &["銘柄コード", "保有数量[株/口]", "平均取得価額[円]"],
+    ),
+];
+
+impl Broker {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Broker::Rakuten => "楽天証券",
+        }
+    }
+
+    /// レポートのキーとCSVのヘッダーから証券会社を判定する
+    pub fn detect(report: &str, headers: &StringRecord) -> Option<Broker> {
+        KEY_COLUMNS
+            .iter()
+            .find(|(_, key_report, columns)| {
+                *key_report == report
+                    && columns
+                        .iter()
+                        .all(|column| headers.iter().any(|header| header.trim() == *column))
+            })
+            .map(|(broker, _, _)| *broker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_key_columns_in_any_order() {
+        let headers = StringRecord::from(vec![
+            "銘柄コード",
+            "約定日",
+            "受渡日",
+            " 実現損益[円] ",
+            "売却/決済",
+            "新しい列",
+        ]);
+        assert_eq!(
+            Broker::detect("profit_and_loss", &headers),
+            Some(Broker::Rakuten)
+        );
+        assert_eq!(Broker::detect("dividend_list", &headers), None);
+    }
+
+    #[test]
+    fn missing_key_column_is_unknown() {
+        let headers = StringRecord::from(vec!["約定日", "銘柄コード", "実現損益[円]"]);
+        assert_eq!(Broker::detect("profit_and_loss", &headers), None);
+    }
+
+    #[test]
+    fn detects_holdings() {
+        let headers = StringRecord::from(vec![
+            "銘柄コード",
+            "銘柄名",
+            "口座",
+            "保有数量[株/口]",
+            "平均取得価額[円]",
+        ]);
+        assert_eq!(Broker::detect("holdings", &headers), Some(Broker::Rakuten));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::error::{Error, Result};
+use crate::modules::{
+    excel::cell_style::CellStyle, expression::Expression, report::record::FieldSpec,
+    settings::Settings, template_pattern::Fields,
+};
+use serde::{Deserialize, Serialize};
+
+/// 設定ファイルで指定する1列分の設定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ColumnSetting {
+    pub field: String, // フィールド名、または派生列の名前
+    #[serde(default)]
+    pub label: Option<String>, // ヘッダー。省略時はフィールドのラベル
+    #[serde(default)]
+    pub width: Option<f64>, // 列幅。省略時は自動調整
+    #[serde(default)]
+    pub format: Option<String>, // `formats`のキー、または表示形式
+    #[serde(default)]
+    pub formula: Option<String>, // 派生列の計算式
+    #[serde(default)]
+    pub footer_field: Option<String>, // 小計の行に表示するフィールド名
+}
+
+/// 出力する列の並び・ヘッダー・書式を管理する
+pub struct ColumnSelector<'a> {
+    settings: &'a Settings,
+    fields: &'a [FieldSpec],
+    columns: Vec<(ColumnSetting, Option<Expression>)>,
+}
+
+impl<'a> ColumnSelector<'a> {
+    /// `columns`が空の場合はモデルの全フィールドを順に出力する
+    pub fn new(
+        settings: &'a Settings,
+        columns: &[ColumnSetting],
+        fields: &'a [FieldSpec],
+    ) -> Result<Self> {
+        let columns = if columns.is_empty() {
+            fields
+                .iter()
+                .map(|field| ColumnSetting {
+                    field: field.name.clone(),
+                    ..Default::default()
+                })
+                .collect()
+        } else {
+            columns.to_vec()
+        };
+
+        // 存在しないフィールドは設定の誤りとして扱う
+        let exists =
+            |name: &str| name == "tax_rate" || fields.iter().any(|field| field.name == name);
+        let columns = columns
+            .into_iter()
+            .map(|column| {
+                let expression = column
+                    .formula
+                    .as_deref()
+                    .map(Expression::parse)
+                    .transpose()
+                    .map_err(|e| Error::Config(e.to_string()))?;
+                let unknown = match &expression {
+                    Some(expression) => expression
+                        .variables()
+                        .into_iter()
+                        .find(|name| !exists(name))
+                        .map(str::to_string),
+                    None => Some(column.field.clone()).filter(|name| !exists(name)),
+                };
+                if let Some(name) = unknown {
+                    return Err(Error::Config(format!(
+                        "Unknown field '{name}' in column '{}'.",
+                        column.field
+                    )));
+                }
+                Ok((column, expression))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(ColumnSelector {
+            settings,
+            fields,
+            columns,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn column_settings(&self) -> impl Iterator<Item = &ColumnSetting> {
+        self.columns.iter().map(|(column, _)| column)
+    }
+
+    /// ヘッダーのフィールド名とラベルを返す。派生列でラベルがなければ名前を使う
+    pub fn header(&self) -> Fields {
+        self.column_settings()
+            .map(|column| {
+                let label = self
+                    .label(&column.field)
+                    .unwrap_or_else(|| column.field.clone());
+                (column.field.clone(), Some(label))
+            })
+            .collect()
+    }
+
+    /// フィールドのラベル。列の指定がフィールドの定義より優先する
+    pub fn label(&self, name: &str) -> Option<String> {
+        self.column_settings()
+            .find(|column| column.field == name)
+            .and_then(|column| column.label.clone())
+            .or_else(|| {
+                self.fields
+                    .iter()
+                    .find(|field| field.name == name)
+                    .map(|field| field.header().to_string())
+            })
+    }
+
+    /// 明細の行を設定した列の並びで返す
+    pub fn select(&self, fields: &Fields) -> Fields {
+        self.select_fields(fields, false)
+    }
+
+    /// 小計の行を設定した列の並びで返す
+    pub fn select_footer(&self, fields: &Fields) -> Fields {
+        self.select_fields(fields, true)
+    }
+
+    fn select_fields(&self, fields: &Fields, is_footer: bool) -> Fields {
+        let get = |field_name: &str| {
+            fields
+                .iter()
+                .find(|(name, _)| name == field_name)
+                .and_then(|(_, value)| value.clone())
+        };
+        let lookup = |name: &str| match name {
+            "tax_rate" => Some(self.settings.tax_rate),
+            name => get(name).and_then(|value| value.parse::<f64>().ok()),
+        };
+
+        self.columns
+            .iter()
+            .map(|(column, expression)| {
+                let value = match (expression, is_footer, &column.footer_field) {
+                    // 小計の行は小計用のフィールドを表示する
+                    (_, true, Some(footer_field)) => get(footer_field),
+                    (Some(expression), _, _) => {
+                        expression.evaluate(&lookup).map(Self::format_number)
+                    }
+                    (None, _, _) => get(&column.field),
+                };
+                (column.field.clone(), value)
+            })
+            .collect()
+    }
+
+    /// 列に表示形式の指定があれば書式を上書きする
+    pub fn apply_format(&self, col_index: usize, cell_style: &mut CellStyle) {
+        if let Some(format) = self
+            .columns
+            .get(col_index)
+            .and_then(|(column, _)| column.format.as_ref())
+        {
+            cell_style.font_format = self.settings.formats.get(format).or(Some(format)).cloned();
+        }
+    }
+
+    /// 列幅の指定がある列の(列番号, 列幅)を返す
+    pub fn widths(&self) -> Vec<(u32, f64)> {
+        self.column_settings()
+            .enumerate()
+            .filter_map(|(col_index, column)| column.width.map(|width| (col_index as u32, width)))
+            .collect()
+    }
+
+    fn format_number(value: f64) -> String {
+        if value.fract() == 0.0 {
+            format!("{}", value as i64)
+        } else {
+            format!("{}", (value * 100.0).round() / 100.0)
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use crate::error::{Error, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 設定ファイルを置くディレクトリ名
+const APP_NAME: &str = "profit-and-loss-converter";
+/// 設定ファイル名
+const FILE_NAME: &str = "settings.json";
+/// 設定を上書きする環境変数の接頭辞
+const ENV_PREFIX: &str = "PL_CONVERTER_";
+
+/// 設定値の読み込み元
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    Default,             // 組み込みの既定値
+    System(PathBuf),     // システム全体の設定ファイル
+    User(PathBuf),       // ユーザーの設定ファイル
+    Project(PathBuf),    // カレントディレクトリの設定ファイル
+    Environment(String), // 環境変数
+    Argument(PathBuf),   // `--config`で指定した設定ファイル
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::System(path) => write!(f, "system ({})", path.display()),
+            ConfigSource::User(path) => write!(f, "user ({})", path.display()),
+            ConfigSource::Project(path) => write!(f, "project ({})", path.display()),
+            ConfigSource::Environment(name) => write!(f, "env ({name})"),
+            ConfigSource::Argument(path) => write!(f, "--config ({})", path.display()),
+        }
+    }
+}
+
+/// 既定値、システム、ユーザー、プロジェクト、環境変数、`--config`の順に設定を重ねる
+///
+/// オブジェクトはキーごとにマージし、値ごとに読み込み元を記録する
+pub struct ConfigLayers {
+    value: Value,
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl ConfigLayers {
+    pub fn load(defaults: &str, config_path: Option<&Path>) -> Result<Self> {
+        let mut layers = ConfigLayers {
+            value: Value::Object(Map::new()),
+            sources: BTreeMap::new(),
+        };
+        let defaults = serde_json::from_str(defaults)
+            .map_err(|e| Error::Config(format!("Failed to parse the default settings: {e}")))?;
+        layers.merge(defaults, &ConfigSource::Default);
+
+        for path in Self::system_paths() {
+            layers.merge_file(&path, ConfigSource::System(path.clone()))?;
+        }
+        if let Some(path) = Self::user_path() {
+            layers.merge_file(&path, ConfigSource::User(path.clone()))?;
+        }
+        let path = PathBuf::from(FILE_NAME);
+        layers.merge_file(&path, ConfigSource::Project(path.clone()))?;
+
+        layers.merge_env();
+
+        // 明示的に指定した設定ファイルは存在しなければエラーにする
+        if let Some(path) = config_path {
+            if !path.is_file() {
+                return Err(Error::Config(format!(
+                    "Config file '{}' not found.",
+                    path.display()
+                )));
+            }
+            layers.merge_file(path, ConfigSource::Argument(path.to_path_buf()))?;
+        }
+
+        Ok(layers)
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// 設定値の読み込み元。オブジェクトの場合は配下の値の読み込み元を返す
+    pub fn source(&self, key: &str) -> Option<&ConfigSource> {
+        self.sources.get(key).or_else(|| {
+            self.sources
+                .iter()
+                .find(|(path, _)| path.starts_with(&format!("{key}.")))
+                .map(|(_, source)| source)
+        })
+    }
+
+    pub fn into_sources(self) -> BTreeMap<String, ConfigSource> {
+        self.sources
+    }
+
+    /// インストール先の設定ファイル。実行ファイルと同じディレクトリのファイルを後に読む
+    fn system_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if cfg!(windows) {
+            if let Some(program_data) = env::var_os("PROGRAMDATA") {
+                paths.push(PathBuf::from(program_data).join(APP_NAME).join(FILE_NAME));
+            }
+        } else {
+            paths.push(Path::new("/etc").join(APP_NAME).join(FILE_NAME));
+        }
+        if let Some(dir) = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            paths.push(dir.join(FILE_NAME));
+        }
+        paths
+    }
+
+    /// `$XDG_CONFIG_HOME`(Windowsは`%APPDATA%`)以下の設定ファイル
+    fn user_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_NAME).join(FILE_NAME))
+    }
+
+    fn merge_file(&mut self, path: &Path, source: ConfigSource) -> Result<()> {
+        if !path.is_file() {
+            return Ok(());
+        }
+        let text = fs::read_to_string(path).map_err(|e| {
+            Error::Config(format!(
+                "Failed to read config file '{}': {e}",
+                path.display()
+            ))
+        })?;
+        let value = serde_json::from_str(&strip_comments(&text)).map_err(|e| {
+            Error::Config(format!(
+                "Failed to parse config file '{}': {e}",
+                path.display()
+            ))
+        })?;
+        self.merge(value, &source);
+        Ok(())
+    }
+
+    /// `PL_CONVERTER_TAX_RATE`・`PL_CONVERTER_SHEET_TITLES__HOLDINGS`のように`__`で階層を区切る
+    fn merge_env(&mut self) {
+        self.merge_vars(env::vars());
+    }
+
+    fn merge_vars(&mut self, vars: impl Iterator<Item = (String, String)>) {
+        let mut vars: Vec<(String, String)> = vars
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        vars.sort();
+
+        for (name, text) in vars {
+            let keys: Vec<String> = name[ENV_PREFIX.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+
+            // 文字列の設定はそのまま、それ以外はJSONとして解釈する
+            let is_string = matches!(self.get(&keys), Some(Value::String(_)));
+            let value = match serde_json::from_str::<Value>(&text) {
+                Ok(value) if !is_string => value,
+                _ => Value::String(text),
+            };
+
+            let value = keys.iter().rev().fold(value, |value, key| {
+                Value::Object(Map::from_iter([(key.clone(), value)]))
+            });
+            self.merge(value, &ConfigSource::Environment(name));
+        }
+    }
+
+    fn get(&self, keys: &[String]) -> Option<&Value> {
+        keys.iter()
+            .try_fold(&self.value, |value, key| value.get(key))
+    }
+
+    fn merge(&mut self, value: Value, source: &ConfigSource) {
+        Self::merge_value(&mut self.value, value, "", source, &mut self.sources);
+    }
+
+    fn merge_value(
+        target: &mut Value,
+        value: Value,
+        path: &str,
+        source: &ConfigSource,
+        sources: &mut BTreeMap<String, ConfigSource>,
+    ) {
+        match (target, value) {
+            (Value::Object(target), Value::Object(map)) => {
+                for (key, value) in map {
+                    let path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    let target = target.entry(key).or_insert(Value::Null);
+                    Self::merge_value(target, value, &path, source, sources);
+                }
+            }
+            (target, value) => {
+                // 下位の層の値を置き換える
+                sources.retain(|key, _| !key.starts_with(&format!("{path}.")));
+                Self::record_sources(&value, path, source, sources);
+                *target = value;
+            }
+        }
+    }
+
+    fn record_sources(
+        value: &Value,
+        path: &str,
+        source: &ConfigSource,
+        sources: &mut BTreeMap<String, ConfigSource>,
+    ) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (key, value) in map {
+                    Self::record_sources(value, &format!("{path}.{key}"), source, sources);
+                }
+            }
+            _ => {
+                sources.insert(path.to_string(), source.clone());
+            }
+        }
+    }
+}
+
+/// `//`から行末までのコメントを取り除く。文字列の中の`//`はそのまま残す
+pub fn strip_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                result.push(c);
+                if let Some(c) = chars.next() {
+                    result.push(c);
+                }
+                continue;
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            _ => {}
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DEFAULTS: &str = r#"{
+        "tax_rate": 0.20315,
+        "sheet_title": "株取引",
+        "sheet_titles": {"dividend_list": "配当金", "holdings": "保有証券"},
+        "columns": {}
+    }"#;
+
+    fn layers() -> ConfigLayers {
+        let mut layers = ConfigLayers {
+            value: Value::Object(Map::new()),
+            sources: BTreeMap::new(),
+        };
+        layers.merge(
+            serde_json::from_str(DEFAULTS).unwrap(),
+            &ConfigSource::Default,
+        );
+        layers
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn strip_comments_keeps_slashes_inside_strings() {
+        let text = r#"{
+            // 説明
+            "url": "https://example.com/a//b", // 末尾のコメント
+            "quote": "\"// not a comment\"",
+            "path": "C:\\temp\\" // バックスラッシュで終わる文字列
+        }"#;
+        let value: Value = serde_json::from_str(&strip_comments(text)).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "url": "https://example.com/a//b",
+                "quote": "\"// not a comment\"",
+                "path": "C:\\temp\\"
+            })
+        );
+        assert_eq!(strip_comments("1 / 2 // half"), "1 / 2 ");
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones_key_by_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("user.json");
+        let project = dir.path().join("project.json");
+        fs::write(
+            &user,
+            r#"{"tax_rate": 0.1, "sheet_titles": {"holdings": "保有"}}"#,
+        )
+        .unwrap();
+        fs::write(&project, r#"{"tax_rate": 0.2}"#).unwrap();
+
+        let mut layers = layers();
+        layers
+            .merge_file(&user, ConfigSource::User(user.clone()))
+            .unwrap();
+        layers
+            .merge_file(&project, ConfigSource::Project(project.clone()))
+            .unwrap();
+        // 存在しないファイルは読み飛ばす
+        layers
+            .merge_file(
+                &dir.path().join("missing.json"),
+                ConfigSource::System(PathBuf::new()),
+            )
+            .unwrap();
+
+        assert_eq!(layers.value()["tax_rate"], json!(0.2));
+        assert_eq!(
+            layers.value()["sheet_titles"],
+            json!({"dividend_list": "配当金", "holdings": "保有"})
+        );
+        assert_eq!(
+            layers.source("tax_rate"),
+            Some(&ConfigSource::Project(project))
+        );
+        assert_eq!(
+            layers.source("sheet_titles.holdings"),
+            Some(&ConfigSource::User(user))
+        );
+        assert_eq!(
+            layers.source("sheet_titles.dividend_list"),
+            Some(&ConfigSource::Default)
+        );
+    }
+
+    #[test]
+    fn an_explicit_config_file_wins_and_must_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "{\"sheet_title\": \"取引\" // 明細\n}").unwrap();
+        let layers = ConfigLayers::load(DEFAULTS, Some(&path)).unwrap();
+        assert_eq!(layers.value()["sheet_title"], json!("取引"));
+        assert_eq!(
+            layers.source("sheet_title"),
+            Some(&ConfigSource::Argument(path))
+        );
+
+        let missing = dir.path().join("missing.json");
+        let error = ConfigLayers::load(DEFAULTS, Some(&missing)).err().unwrap();
+        assert!(error.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn environment_variables_nest_with_double_underscores() {
+        let mut layers = layers();
+        layers.merge_vars(vars(&[
+            ("PL_CONVERTER_SHEET_TITLES__HOLDINGS", "保有"),
+            ("PL_CONVERTER_TAX_RATE", "0.2"),
+            ("PL_CONVERTER_SHEET_TITLE", "123"),
+            (
+                "PL_CONVERTER_COLUMNS__HOLDINGS",
+                r#"[{"field": "security_code"}]"#,
+            ),
+            ("OTHER_TAX_RATE", "0.5"),
+        ]));
+
+        assert_eq!(layers.value()["sheet_titles"]["holdings"], json!("保有"));
+        assert_eq!(
+            layers.value()["sheet_titles"]["dividend_list"],
+            json!("配当金")
+        );
+        assert_eq!(layers.value()["tax_rate"], json!(0.2));
+        // 文字列の設定はJSONとして解釈しない
+        assert_eq!(layers.value()["sheet_title"], json!("123"));
+        assert_eq!(
+            layers.value()["columns"]["holdings"],
+            json!([{"field": "security_code"}])
+        );
+        assert_eq!(
+            layers.source("sheet_titles.holdings"),
+            Some(&ConfigSource::Environment(
+                "PL_CONVERTER_SHEET_TITLES__HOLDINGS".to_string()
+            ))
+        );
+    }
+}
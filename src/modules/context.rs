@@ -0,0 +1,22 @@
+use crate::modules::settings::Settings;
+use std::sync::Arc;
+
+/// 変換1回分の実行環境。レポートの書き込みやExcelの操作に渡す
+///
+/// 設定を値で持つため、1つのプロセスで異なる設定の変換を実行できる
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub settings: Arc<Settings>,
+}
+
+impl Context {
+    pub fn new(settings: Arc<Settings>) -> Self {
+        Context { settings }
+    }
+}
+
+impl From<Settings> for Context {
+    fn from(settings: Settings) -> Self {
+        Context::new(Arc::new(settings))
+    }
+}
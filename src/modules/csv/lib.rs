@@ -1,43 +1,245 @@
+use crate::error::Error as ConvertError;
+use crate::error::Result;
+use crate::modules::report::record::FieldValue;
 use csv::StringRecord;
-use encoding_rs::{Encoding, SHIFT_JIS};
-use encoding_rs_io::DecodeReaderBytesBuilder;
-use std::error::Error;
-use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8};
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
 pub struct CSVAccessor;
 
+/// 判定した文字コードとヘッダー
+pub struct CsvInfo {
+    pub encoding: &'static str,
+    pub headers: StringRecord,
+}
+
 impl CSVAccessor {
-    pub fn read(filepath: &Path) -> Result<Vec<StringRecord>, Box<dyn Error>> {
-        let file = File::open(filepath)?;
-        let mut reader = BufReader::new(file);
+    pub fn read(filepath: &Path) -> Result<Vec<StringRecord>> {
+        Ok(Self::read_with_headers(filepath)?.1)
+    }
+
+    /// ヘッダーと明細を読み込む
+    pub fn read_with_headers(filepath: &Path) -> Result<(StringRecord, Vec<StringRecord>)> {
+        let file = File::open(filepath).map_err(|e| ConvertError::input(filepath, e))?;
+        Self::parse(file).map_err(|e| e.with_path(filepath))
+    }
+
+    /// 文字コードとヘッダーだけを読み込む
+    pub fn inspect(filepath: &Path) -> Result<CsvInfo> {
+        let inspect = || -> Result<CsvInfo, ConvertError> {
+            let bytes = fs::read(filepath).map_err(|e| ConvertError::input(filepath, e))?;
+            let encoding = Self::detect_encoding(&bytes).name();
+            let text = Self::decode(&bytes)?;
+            let headers = csv::Reader::from_reader(text.as_bytes()).headers()?.clone();
+            Ok(CsvInfo { encoding, headers })
+        };
+        inspect().map_err(|e| e.with_path(filepath))
+    }
 
-        let encoding = Self::detect_encoding(&mut reader);
-        // ファイルの先頭に戻す
-        reader.seek(SeekFrom::Start(0))?;
+    /// 任意の入力から読み込む。文字コードを判定するため、全体をメモリに読み込む
+    pub fn read_from<R: Read>(reader: R) -> Result<Vec<StringRecord>> {
+        Ok(Self::read_from_with_headers(reader)?.1)
+    }
+
+    /// 任意の入力からヘッダーと明細を読み込む
+    pub fn read_from_with_headers<R: Read>(reader: R) -> Result<(StringRecord, Vec<StringRecord>)> {
+        Self::parse(reader)
+    }
 
-        let transcoded_reader = DecodeReaderBytesBuilder::new()
-            .encoding(encoding)
-            .build(reader);
+    fn parse<R: Read>(mut reader: R) -> Result<(StringRecord, Vec<StringRecord>), ConvertError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|source| ConvertError::Input { path: None, source })?;
 
-        let mut csv_reader = csv::Reader::from_reader(transcoded_reader);
+        let text = Self::decode(&bytes)?;
+        let mut csv_reader = csv::Reader::from_reader(text.as_bytes());
+        let headers = csv_reader.headers()?.clone();
         let mut result = Vec::new();
         for record in csv_reader.records() {
             result.push(record?);
         }
-        Ok(result)
+        Ok((headers, result))
+    }
+
+    /// 判定した文字コードで変換する。解釈できないバイト列は置き換えずにエラーにする
+    fn decode(bytes: &[u8]) -> Result<String, ConvertError> {
+        let encoding = Self::detect_encoding(bytes);
+        let bytes = if encoding == UTF_8 {
+            bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes)
+        } else {
+            bytes
+        };
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            return Ok(text.into_owned());
+        }
+
+        // 改行はどちらの文字コードでも1バイトなので、行ごとに変換して誤りの行を探す
+        let line = bytes
+            .split(|byte| *byte == b'\n')
+            .position(|line| {
+                encoding
+                    .decode_without_bom_handling_and_without_replacement(line)
+                    .is_none()
+            })
+            .unwrap_or(0);
+        Err(ConvertError::Encoding {
+            path: None,
+            encoding: encoding.name(),
+            line: line as u64 + 1,
+        })
     }
 
-    fn detect_encoding(reader: &mut BufReader<File>) -> Option<&'static Encoding> {
-        // ファイルの先頭から最大3バイト読み込み、UTF-8として正しく解釈できるかを判断する
-        let mut buf = [0; 3];
-        if let Ok(bytes_read) = reader.read(&mut buf) {
-            if let Ok(_) = std::str::from_utf8(&buf[..bytes_read]) {
-                // UTF-8として正常に解釈できる場合、UTF-8と判定する
-                return None;
-            }
+    fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+        // 全体がUTF-8として正しく解釈できる場合はUTF-8、それ以外はShift_JISと判定する
+        if std::str::from_utf8(bytes).is_ok() {
+            return UTF_8;
         }
-        Some(SHIFT_JIS)
+        SHIFT_JIS
+    }
+}
+
+/// 列名で列を引くCSV。約定履歴・終値・銘柄マスターなど、列名を決めた補助ファイルに使う
+///
+/// ファイルは1回だけ読み込む。内容の誤りはファイルの説明とパスを付けた設定の誤りとして返す
+pub struct CsvTable {
+    filepath: PathBuf,
+    description: &'static str, // エラーに使うファイルの説明(`price file`など)
+    headers: StringRecord,
+    records: Vec<StringRecord>,
+}
+
+/// `CsvTable`の1行
+pub struct CsvRow<'a> {
+    table: &'a CsvTable,
+    pub number: usize,            // 行番号。ヘッダーを1行目とする
+    pub record: &'a StringRecord, // 行の値
+}
+
+impl CsvTable {
+    pub fn load(filepath: &Path, description: &'static str) -> Result<Self> {
+        let (headers, records) = CSVAccessor::read_with_headers(filepath)?;
+        Ok(CsvTable {
+            filepath: filepath.to_path_buf(),
+            description,
+            headers,
+            records,
+        })
+    }
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    /// 列名の位置。ヘッダーの前後の空白は無視する
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|header| header.trim() == name)
+    }
+
+    /// 必須の列の位置。列がなければエラーを返す
+    pub fn required(&self, name: &str) -> Result<usize> {
+        self.column(name)
+            .ok_or_else(|| self.invalid(format!("missing the column '{name}'.")))
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = CsvRow<'_>> {
+        self.records
+            .iter()
+            .enumerate()
+            .map(|(index, record)| CsvRow {
+                table: self,
+                number: index + 2,
+                record,
+            })
+    }
+
+    /// ファイルの内容の誤り
+    pub fn invalid(&self, message: impl Display) -> ConvertError {
+        ConvertError::Config(format!(
+            "Invalid {} '{}': {message}",
+            self.description,
+            self.filepath.display()
+        ))
+    }
+}
+
+impl CsvRow<'_> {
+    /// 前後の空白を除いた値。列がなければ空文字列
+    pub fn get(&self, column: usize) -> &str {
+        self.record.get(column).map(str::trim).unwrap_or_default()
+    }
+
+    /// 空でない値。列がないか値が空なら`None`
+    pub fn value(&self, column: Option<usize>) -> Option<&str> {
+        column
+            .map(|column| self.get(column))
+            .filter(|value| !value.is_empty())
+    }
+
+    /// 値をフィールドの型に変換する
+    pub fn parse<T: FieldValue>(&self, column: usize) -> Result<T> {
+        let value = self.get(column);
+        T::parse(value).map_err(|e| self.invalid(format!("'{value}' {e}")))
+    }
+
+    /// 空でない値をフィールドの型に変換する
+    pub fn parse_value<T: FieldValue>(&self, column: Option<usize>) -> Result<Option<T>> {
+        self.value(column)
+            .map(|value| T::parse(value).map_err(|e| self.invalid(format!("'{value}' {e}"))))
+            .transpose()
+    }
+
+    /// 行番号を付けたファイルの内容の誤り
+    pub fn invalid(&self, message: impl Display) -> ConvertError {
+        self.table
+            .invalid(format!("row {}: {message}", self.number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(content: &[u8]) -> (tempfile::TempDir, CsvTable) {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("prices.csv");
+        fs::write(&filepath, content).unwrap();
+        let table = CsvTable::load(&filepath, "price file").unwrap();
+        (dir, table)
+    }
+
+    #[test]
+    fn columns_are_found_by_trimmed_header() {
+        let (_dir, table) = table(b"date, code ,close\n2024-12-30,7203,3000\n");
+        assert_eq!(table.column("code"), Some(1));
+        assert_eq!(table.column("name"), None);
+        let error = table.required("name").unwrap_err().to_string();
+        assert!(error.starts_with("Invalid price file '"));
+        assert!(error.ends_with("missing the column 'name'."));
+    }
+
+    #[test]
+    fn rows_parse_values_with_row_numbers() {
+        let (_dir, table) =
+            table(b"date,code,close\n2024-12-30, 7203 ,\"3,000\"\n2024-12-30,6758,abc\n");
+        let rows: Vec<CsvRow> = table.rows().collect();
+        assert_eq!(rows[0].get(1), "7203");
+        assert_eq!(rows[0].parse::<f64>(2).unwrap(), 3000.0);
+        assert_eq!(rows[0].value(Some(5)), None);
+        assert_eq!(rows[0].parse_value::<f64>(None).unwrap(), None);
+
+        let error = rows[1].parse::<f64>(2).unwrap_err().to_string();
+        assert!(error.contains("row 3: 'abc'"), "{error}");
+    }
+
+    #[test]
+    fn shift_jis_headers_are_decoded() {
+        let (bytes, _, _) = SHIFT_JIS.encode("銘柄コード,数量\n7203,100\n");
+        let (_dir, table) = table(&bytes);
+        assert_eq!(table.column("数量"), Some(1));
+        assert_eq!(table.rows().count(), 1);
     }
 }
@@ -1,2 +1,2 @@
+#[allow(clippy::module_inception)]
 pub mod dividend_list;
-pub mod lib;
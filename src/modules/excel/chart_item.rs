@@ -0,0 +1,31 @@
+use super::coordinate::CoordinateItem;
+use umya_spreadsheet::ChartType;
+
+#[derive(Clone, Debug)]
+pub struct ChartItem {
+    pub chart_type: ChartType,
+    pub title: Option<String>,
+    pub from: CoordinateItem,                         // 配置の左上
+    pub to: CoordinateItem,                           // 配置の右下
+    pub values: (CoordinateItem, CoordinateItem),     // 値の範囲
+    pub categories: (CoordinateItem, CoordinateItem), // 項目名の範囲
+}
+
+impl ChartItem {
+    pub fn new(
+        chart_type: ChartType,
+        title: Option<&String>,
+        (from, to): (CoordinateItem, CoordinateItem),
+        values: (CoordinateItem, CoordinateItem),
+        categories: (CoordinateItem, CoordinateItem),
+    ) -> Self {
+        ChartItem {
+            chart_type,
+            title: title.cloned(),
+            from,
+            to,
+            values,
+            categories,
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use umya_spreadsheet::helper::coordinate::{
+    coordinate_from_index, coordinate_from_index_with_lock,
+};
+
 #[derive(Clone, Debug)]
 pub struct CoordinateItem {
     pub col: u32,
@@ -43,3 +47,15 @@ impl Coordinate for (&u32, &u32) {
         }
     }
 }
+
+impl CoordinateItem {
+    /// `B2`形式のセル番地を返す
+    pub fn to_address(&self) -> String {
+        coordinate_from_index(&self.col, &self.row)
+    }
+
+    /// `$B$2`形式の絶対参照を返す
+    pub fn to_absolute(&self) -> String {
+        coordinate_from_index_with_lock(&self.col, &self.row, &true, &true)
+    }
+}
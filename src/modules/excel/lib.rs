@@ -1,33 +1,98 @@
-use crate::modules::excel::{cell_style::CellStyle, coordinate::CoordinateItem};
-use crate::modules::settings::SETTINGS;
+use crate::error::Error as ConvertError;
+use crate::error::Result;
+use crate::modules::context::Context;
+use crate::modules::excel::{
+    cell_style::CellStyle, chart_item::ChartItem, coordinate::CoordinateItem,
+    number_format::format_number,
+};
+use crate::modules::table::{Cell as TableCell, RowKind, Table};
 use std::cell::RefCell;
-use std::error::Error;
-use std::path::PathBuf;
-use umya_spreadsheet::{self, new_file_empty_worksheet, reader, writer, Border, Spreadsheet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use umya_spreadsheet::{
+    self,
+    drawing::charts::{CategoryAxisData, StringReference},
+    drawing::spreadsheet::MarkerType,
+    new_file_empty_worksheet, reader, writer, Border, Chart, Color, ConditionalFormatValueObject,
+    ConditionalFormatValueObjectValues, ConditionalFormatValues, ConditionalFormatting,
+    ConditionalFormattingOperatorValues, ConditionalFormattingRule, DataBar, Formula, Pane,
+    PaneStateValues, PaneValues, SheetView, Spreadsheet, Style, Worksheet,
+};
 
 pub struct ExcelAccessor {
+    context: Context,
     book: RefCell<Spreadsheet>,
     sheet_title: String,
-    xlsx_filepath: PathBuf,
+    xlsx_filepath: Option<PathBuf>,
 }
 
 impl ExcelAccessor {
-    pub fn read_book(sheet_title: &str, xlsx_filepath: &PathBuf) -> Result<Self, Box<dyn Error>> {
-        let book = reader::xlsx::read(xlsx_filepath);
-        let mut book = match book {
-            Ok(book) => book,
-            Err(_) => new_file_empty_worksheet(),
+    pub fn read_book(context: &Context, sheet_title: &str, xlsx_filepath: &Path) -> Result<Self> {
+        // 読めない既存のファイルは上書きせずにエラーにする
+        let book = if xlsx_filepath.exists() {
+            reader::xlsx::read(xlsx_filepath).map_err(|e| {
+                ConvertError::workbook(
+                    Some(xlsx_filepath),
+                    format!("cannot read the existing workbook ({e}). It was left untouched; repair or move it and try again."),
+                )
+            })?
+        } else {
+            new_file_empty_worksheet()
         };
+        Self::from_book(
+            context,
+            book,
+            sheet_title,
+            Some(xlsx_filepath.to_path_buf()),
+        )
+    }
 
-        if book.get_sheet_by_name(sheet_title).is_some() {
-            book.remove_sheet_by_name(sheet_title)?;
-        }
-        book.new_sheet(sheet_title)?;
-        Ok(ExcelAccessor {
+    /// 保存先のない新しいブックを作る。`write_to`で出力する
+    pub fn new_book(context: &Context, sheet_title: &str) -> Result<Self> {
+        Self::from_book(context, new_file_empty_worksheet(), sheet_title, None)
+    }
+
+    fn from_book(
+        context: &Context,
+        book: Spreadsheet,
+        sheet_title: &str,
+        xlsx_filepath: Option<PathBuf>,
+    ) -> Result<Self> {
+        let mut excel_accessor = ExcelAccessor {
+            context: context.clone(),
             book: RefCell::new(book),
             sheet_title: sheet_title.to_string(),
-            xlsx_filepath: xlsx_filepath.clone(),
-        })
+            xlsx_filepath,
+        };
+        excel_accessor.new_sheet(sheet_title)?;
+        Ok(excel_accessor)
+    }
+
+    /// シートを作り直し、以降の書き込み先をそのシートに切り替える
+    pub fn new_sheet(&mut self, sheet_title: &str) -> Result<()> {
+        let mut book = self.book.borrow_mut();
+        if book.get_sheet_by_name(sheet_title).is_some() {
+            book.remove_sheet_by_name(sheet_title)
+                .map_err(|e| ConvertError::workbook(None, e))?;
+        }
+        book.new_sheet(sheet_title)
+            .map_err(|e| ConvertError::workbook(None, e))?;
+        self.sheet_title = sheet_title.to_string();
+        Ok(())
+    }
+
+    /// 作成済みのシートで置き換え、以降の書き込み先をそのシートに切り替える
+    pub fn add_worksheet(&mut self, worksheet: Worksheet) -> Result<()> {
+        let sheet_title = worksheet.get_name().to_string();
+        let mut book = self.book.borrow_mut();
+        if book.get_sheet_by_name(&sheet_title).is_some() {
+            book.remove_sheet_by_name(&sheet_title)
+                .map_err(|e| ConvertError::workbook(None, e))?;
+        }
+        book.add_sheet(worksheet)
+            .map_err(|e| ConvertError::workbook(None, e))?;
+        self.sheet_title = sheet_title;
+        Ok(())
     }
 
     pub fn write_cell(
@@ -75,9 +140,9 @@ impl ExcelAccessor {
         }
     }
 
-    pub fn adjust_column_widths(&mut self, len: u32) -> Result<(), Box<dyn Error>> {
-        let start_col = SETTINGS.start_col;
-        let end_col = SETTINGS.start_col + len;
+    pub fn adjust_column_widths(&mut self, len: u32) -> Result<()> {
+        let start_col = self.context.settings.start_col;
+        let end_col = start_col + len;
 
         if let Some(sheet) = self
             .book
@@ -94,8 +159,283 @@ impl ExcelAccessor {
         Ok(())
     }
 
-    pub fn save_book(&self) -> Result<(), Box<dyn Error>> {
-        writer::xlsx::write(&self.book.borrow_mut(), &self.xlsx_filepath)?;
+    /// 列幅を指定した幅に固定する
+    pub fn set_column_width(&mut self, col_index: u32, width: f64) {
+        if let Some(sheet) = self
+            .book
+            .borrow_mut()
+            .get_sheet_by_name_mut(&self.sheet_title)
+        {
+            sheet
+                .get_column_dimension_by_number_mut(&col_index)
+                .set_auto_width(false)
+                .set_width(width);
+        }
+    }
+
+    /// グラフを描く。シート名に`'`を含むシートには描かない
+    ///
+    /// 範囲のシート名は`''`と書くが、umya-spreadsheetは`''`のままシートを探して保存に失敗する
+    pub fn add_chart(&mut self, chart_item: &ChartItem) {
+        if self.sheet_title.contains('\'') {
+            return;
+        }
+        let mut from_marker = MarkerType::default();
+        from_marker.set_coordinate(chart_item.from.to_address());
+        let mut to_marker = MarkerType::default();
+        to_marker.set_coordinate(chart_item.to.to_address());
+
+        let values = self.range_address(&chart_item.values);
+        let mut categories = StringReference::default();
+        categories
+            .get_formula_mut()
+            .set_address_str(self.range_address(&chart_item.categories));
+
+        let mut chart = Chart::default();
+        chart.new_chart(
+            chart_item.chart_type.clone(),
+            from_marker,
+            to_marker,
+            vec![values.as_str()],
+        );
+        if let Some(title) = &chart_item.title {
+            chart.set_title(title);
+        }
+        for series in chart
+            .get_area_chart_series_list_mut()
+            .get_area_chart_series_mut()
+        {
+            let mut category_axis_data = CategoryAxisData::default();
+            category_axis_data.set_string_reference(categories.clone());
+            series.set_category_axis_data(category_axis_data);
+        }
+
+        if let Some(sheet) = self
+            .book
+            .borrow_mut()
+            .get_sheet_by_name_mut(&self.sheet_title)
+        {
+            sheet.add_chart(chart);
+        }
+    }
+
+    pub fn set_auto_filter(&mut self, (start, end): (CoordinateItem, CoordinateItem)) {
+        if let Some(sheet) = self
+            .book
+            .borrow_mut()
+            .get_sheet_by_name_mut(&self.sheet_title)
+        {
+            sheet.set_auto_filter(format!("{}:{}", start.to_address(), end.to_address()));
+        }
+    }
+
+    /// `row`行目までを固定する
+    pub fn freeze_rows(&mut self, row: u32) {
+        let mut pane = Pane::default();
+        pane.set_vertical_split(row as f64);
+        pane.get_top_left_cell_mut()
+            .set_coordinate(format!("A{}", row + 1));
+        pane.set_active_pane(PaneValues::BottomLeft);
+        pane.set_state(PaneStateValues::Frozen);
+
+        if let Some(sheet) = self
+            .book
+            .borrow_mut()
+            .get_sheet_by_name_mut(&self.sheet_title)
+        {
+            let sheet_views = sheet.get_sheet_views_mut();
+            if sheet_views.get_sheet_view_list().is_empty() {
+                sheet_views.add_sheet_view_list_mut(SheetView::default());
+            }
+            for sheet_view in sheet_views.get_sheet_view_list_mut() {
+                sheet_view.set_pane(pane.clone());
+            }
+        }
+    }
+
+    /// 負の値のフォント色を条件付き書式で設定する
+    pub fn add_negative_font_rule(
+        &mut self,
+        range: (CoordinateItem, CoordinateItem),
+        font_color: &str,
+    ) {
+        let mut formula = Formula::default();
+        formula.set_string_value("0");
+
+        let mut style = Style::default();
+        style.get_font_mut().get_color_mut().set_argb(font_color);
+
+        let mut rule = ConditionalFormattingRule::default();
+        rule.set_type(ConditionalFormatValues::CellIs)
+            .set_operator(ConditionalFormattingOperatorValues::LessThan)
+            .set_formula(formula)
+            .set_style(style);
+
+        self.add_conditional_formatting(range, rule);
+    }
+
+    /// データバーを条件付き書式で設定する
+    pub fn add_data_bar(&mut self, range: (CoordinateItem, CoordinateItem), color: &str) {
+        let mut min = ConditionalFormatValueObject::default();
+        min.set_type(ConditionalFormatValueObjectValues::Min);
+        let mut max = ConditionalFormatValueObject::default();
+        max.set_type(ConditionalFormatValueObjectValues::Max);
+        let mut bar_color = Color::default();
+        bar_color.set_argb(color);
+
+        let mut data_bar = DataBar::default();
+        data_bar
+            .add_cfvo_collection(min)
+            .add_cfvo_collection(max)
+            .add_color_collection(bar_color);
+
+        let mut rule = ConditionalFormattingRule::default();
+        rule.set_type(ConditionalFormatValues::DataBar)
+            .set_data_bar(data_bar);
+
+        self.add_conditional_formatting(range, rule);
+    }
+
+    fn add_conditional_formatting(
+        &mut self,
+        (start, end): (CoordinateItem, CoordinateItem),
+        mut rule: ConditionalFormattingRule,
+    ) {
+        if let Some(sheet) = self
+            .book
+            .borrow_mut()
+            .get_sheet_by_name_mut(&self.sheet_title)
+        {
+            // 優先順位はシート内で一意にする
+            let priority = sheet.get_conditional_formatting_collection().len() as i32 + 1;
+            rule.set_priority(priority);
+
+            let mut conditional_formatting = ConditionalFormatting::default();
+            conditional_formatting
+                .get_sequence_of_references_mut()
+                .set_sqref(format!("{}:{}", start.to_address(), end.to_address()));
+            conditional_formatting.add_conditional_collection(rule);
+            sheet.add_conditional_formatting_collection(conditional_formatting);
+        }
+    }
+
+    /// `'シート名'!$B$2:$B$10`形式の範囲を返す。シート名の`'`は`''`と重ねる
+    fn range_address(&self, (start, end): &(CoordinateItem, CoordinateItem)) -> String {
+        format!(
+            "'{}'!{}:{}",
+            self.sheet_title.replace('\'', "''"),
+            start.to_absolute(),
+            end.to_absolute()
+        )
+    }
+
+    /// シートの内容を端末に表示する表に変換する。値のない行と列は詰める
+    ///
+    /// 表示形式を適用した値を使い、背景色でヘッダーと小計の行を判定する
+    pub fn to_table(&self, sheet_title: &str) -> Option<Table> {
+        let book = self.book.borrow();
+        let sheet = book.get_sheet_by_name(sheet_title)?;
+        let (max_col, max_row) = sheet.get_highest_column_and_row();
+        let has_value = |col: u32, row: u32| {
+            sheet
+                .get_cell((col, row))
+                .is_some_and(|cell| !cell.get_value().is_empty())
+        };
+        let cols: Vec<u32> = (1..=max_col)
+            .filter(|col| (1..=max_row).any(|row| has_value(*col, row)))
+            .collect();
+
+        let colors = &self.context.settings.colors;
+        let mut table = Table::new();
+        for row in (1..=max_row).filter(|row| cols.iter().any(|col| has_value(*col, *row))) {
+            let background = cols.iter().find_map(|col| {
+                let color = sheet
+                    .get_cell((*col, row))?
+                    .get_style()
+                    .get_background_color()?;
+                Some(color.get_argb().to_string())
+            });
+            let kind = match background {
+                Some(color) if colors.get("header_background") == Some(&color) => RowKind::Header,
+                Some(color) if colors.get("footer_background") == Some(&color) => RowKind::Footer,
+                // 背景色のないテンプレートでは先頭の行をヘッダーとする
+                _ if table.is_empty() => RowKind::Header,
+                _ => RowKind::Record,
+            };
+            let cells = cols
+                .iter()
+                .map(|col| {
+                    let Some(cell) = sheet.get_cell((*col, row)) else {
+                        return TableCell::left("");
+                    };
+                    let Some(number) = cell.get_value_number() else {
+                        return TableCell::left(cell.get_value());
+                    };
+                    let format = cell
+                        .get_style()
+                        .get_number_format()
+                        .map_or("General", |format| format.get_format_code());
+                    TableCell {
+                        is_loss: number < 0.0,
+                        ..TableCell::right(format_number(number, format))
+                    }
+                })
+                .collect();
+            table.push(kind, cells);
+        }
+        Some(table)
+    }
+
+    pub fn save_book(&self) -> Result<()> {
+        let xlsx_filepath = self
+            .xlsx_filepath
+            .as_deref()
+            .ok_or_else(|| ConvertError::workbook(None, "the workbook has no file to save to."))?;
+        writer::xlsx::write(&self.book.borrow_mut(), xlsx_filepath).map_err(|e| {
+            ConvertError::workbook(Some(xlsx_filepath), format!("failed to save: {e}"))
+        })?;
+        Ok(())
+    }
+
+    pub fn write_to(&self, writer: &mut dyn Write) -> Result<()> {
+        writer::xlsx::write_writer(&self.book.borrow(), writer)
+            .map_err(|e| ConvertError::workbook(None, format!("failed to write: {e}")))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::{excel::coordinate::Coordinate, settings::Settings};
+
+    #[test]
+    fn range_address_doubles_quotes_in_sheet_titles() {
+        let context = Context::from(Settings::defaults());
+        let excel_accessor = ExcelAccessor::new_book(&context, "Tom's 損益").unwrap();
+        let range = (
+            (2u32, 3u32).new_coordinate(),
+            (2u32, 10u32).new_coordinate(),
+        );
+        assert_eq!(
+            excel_accessor.range_address(&range),
+            "'Tom''s 損益'!$B$3:$B$10"
+        );
+    }
+
+    #[test]
+    fn charts_are_skipped_on_sheets_with_quotes() {
+        let context = Context::from(Settings::defaults());
+        let mut excel_accessor = ExcelAccessor::new_book(&context, "Tom's 損益").unwrap();
+        let range = ((2u32, 3u32).new_coordinate(), (2u32, 4u32).new_coordinate());
+        excel_accessor.add_chart(&ChartItem::new(
+            umya_spreadsheet::ChartType::BarChart,
+            None,
+            range.clone(),
+            range.clone(),
+            range,
+        ));
+        // 描いていれば保存でパニックする
+        excel_accessor.write_to(&mut std::io::sink()).unwrap();
+    }
+}
@@ -1,3 +1,6 @@
 pub mod cell_style;
+pub mod chart_item;
 pub mod coordinate;
 pub mod lib;
+pub mod number_format;
+pub mod template;
@@ -0,0 +1,186 @@
+/// 数値に Excel の表示形式を適用した文字列を返す
+///
+/// 正・負・ゼロのセクション、引用符・`\`の文字、`#,##0.00`の桁と桁区切り、`%`に対応する。
+/// 色・条件などの角括弧と、`_`・`*`による幅の調整は無視する
+pub fn format_number(value: f64, format: &str) -> String {
+    let sections = split_sections(format);
+    let (section, value, minus) = match sections.as_slice() {
+        [_, negative, ..] if value < 0.0 => (negative.as_str(), -value, ""),
+        [_, _, zero, ..] if value == 0.0 => (zero.as_str(), value, ""),
+        [positive, ..] => (
+            positive.as_str(),
+            value.abs(),
+            if value < 0.0 { "-" } else { "" },
+        ),
+        [] => ("", value.abs(), if value < 0.0 { "-" } else { "" }),
+    };
+    if section.is_empty() || section.eq_ignore_ascii_case("general") {
+        return format!("{minus}{}", value.abs());
+    }
+
+    let (prefix, pattern, suffix) = parse_section(section);
+    let value = if format!("{prefix}{suffix}").contains('%') {
+        value * 100.0
+    } else {
+        value
+    };
+    if pattern.is_empty() {
+        return format!("{minus}{prefix}{suffix}");
+    }
+    format!("{minus}{prefix}{}{suffix}", format_digits(value, &pattern))
+}
+
+/// `;`でセクションに分ける。引用符・角括弧・`\`の中の`;`は区切りとしない
+fn split_sections(format: &str) -> Vec<String> {
+    let mut sections = Vec::new();
+    let mut section = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => sections.push(std::mem::take(&mut section)),
+            '"' | '[' => {
+                let close = if c == '"' { '"' } else { ']' };
+                section.push(c);
+                for c in chars.by_ref() {
+                    section.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            '\\' => {
+                section.push(c);
+                section.extend(chars.next());
+            }
+            _ => section.push(c),
+        }
+    }
+    sections.push(section);
+    sections
+}
+
+/// セクションを数値の前の文字・桁の指定・数値の後の文字に分ける
+fn parse_section(section: &str) -> (String, String, String) {
+    let mut prefix = String::new();
+    let mut pattern = String::new();
+    let mut suffix = String::new();
+    let mut chars = section.chars();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '0' | '#' | '?' | '.' if suffix.is_empty() => {
+                pattern.push(c);
+                continue;
+            }
+            ',' if !pattern.is_empty() && suffix.is_empty() => {
+                pattern.push(c);
+                continue;
+            }
+            '"' => chars.by_ref().take_while(|c| *c != '"').collect(),
+            '\\' => chars.next().map(String::from).unwrap_or_default(),
+            '[' => {
+                chars.by_ref().take_while(|c| *c != ']').for_each(drop);
+                continue;
+            }
+            '_' => {
+                chars.next();
+                " ".to_string()
+            }
+            '*' => {
+                chars.next();
+                continue;
+            }
+            _ => c.to_string(),
+        };
+        if pattern.is_empty() {
+            prefix.push_str(&literal);
+        } else {
+            suffix.push_str(&literal);
+        }
+    }
+    (prefix, pattern, suffix)
+}
+
+/// `#,##0.00`のような桁の指定で数値を整形する
+fn format_digits(value: f64, pattern: &str) -> String {
+    let (integer_pattern, decimal_pattern) = pattern.split_once('.').unwrap_or((pattern, ""));
+    let decimals = decimal_pattern
+        .chars()
+        .filter(|c| matches!(c, '0' | '#' | '?'))
+        .count();
+    let min_integer_digits = integer_pattern.chars().filter(|c| *c == '0').count();
+    let use_separator = integer_pattern.contains(',');
+
+    let text = format!("{value:.decimals$}");
+    let (integer, decimal) = text.split_once('.').unwrap_or((&text, ""));
+    let integer = integer.trim_start_matches('0');
+    let integer = format!("{integer:0>min_integer_digits$}");
+
+    let mut result = String::new();
+    for (index, c) in integer.chars().enumerate() {
+        if use_separator && index > 0 && (integer.len() - index).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(c);
+    }
+
+    // `#`の小数部は末尾の0を表示しない
+    let required_decimals = decimal_pattern.chars().filter(|c| *c == '0').count();
+    let mut decimal = decimal.to_string();
+    while decimal.len() > required_decimals && decimal.ends_with('0') {
+        decimal.pop();
+    }
+    if !decimal.is_empty() || (decimal_pattern.is_empty() && pattern.contains('.')) {
+        result.push('.');
+        result.push_str(&decimal);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YEN: &str = r##""¥"#,##0;"¥"-#,##0"##;
+
+    #[test]
+    fn negative_section_replaces_the_sign() {
+        assert_eq!(format_number(1234567.0, YEN), "¥1,234,567");
+        assert_eq!(format_number(-1500.0, YEN), "¥-1,500");
+        assert_eq!(format_number(0.0, YEN), "¥0");
+    }
+
+    #[test]
+    fn single_section_prefixes_the_minus_sign() {
+        assert_eq!(format_number(-1500.0, r##""¥"#,##0"##), "-¥1,500");
+        assert_eq!(format_number(-2.5, "0.00"), "-2.50");
+    }
+
+    #[test]
+    fn zero_section_is_used_for_zero() {
+        assert_eq!(format_number(0.0, r#"#,##0;-#,##0;"-""#), "-");
+        assert_eq!(format_number(-3.0, "#,##0;(#,##0);0"), "(3)");
+        assert_eq!(format_number(3.0, "#,##0;(#,##0);0"), "3");
+    }
+
+    #[test]
+    fn semicolons_in_literals_do_not_split_sections() {
+        assert_eq!(format_number(1.0, r#""a;b"0;\;0"#), "a;b1");
+        assert_eq!(format_number(-1.0, r#""a;b"0;\;0"#), ";1");
+    }
+
+    #[test]
+    fn brackets_and_padding_are_ignored() {
+        assert_eq!(format_number(-1000.0, "#,##0;[Red]-#,##0"), "-1,000");
+        assert_eq!(format_number(1000.0, "#,##0_);(#,##0)"), "1,000 ");
+    }
+
+    #[test]
+    fn percent_and_decimals() {
+        assert_eq!(format_number(0.1234, "0.0%"), "12.3%");
+        assert_eq!(format_number(1.5, "0.00"), "1.50");
+        assert_eq!(format_number(1.5, "0.##"), "1.5");
+        assert_eq!(format_number(2.0, "0.##"), "2");
+        assert_eq!(format_number(0.25, "#,##0.00"), "0.25");
+        assert_eq!(format_number(12.0, "General"), "12");
+    }
+}
@@ -0,0 +1,256 @@
+use crate::error::{Error as ConvertError, Result};
+use crate::modules::columns::ColumnSelector;
+use std::collections::HashMap;
+use std::path::Path;
+use umya_spreadsheet::{helper::address::split_address, reader, Spreadsheet, Style, Worksheet};
+
+/// テンプレートに流し込む1行分のデータ
+pub enum TemplateRow {
+    Record(Vec<(String, Option<String>)>), // 明細
+    Footer(Vec<(String, Option<String>)>), // 小計
+}
+
+/// テンプレートの1セル分の内容と書式
+#[derive(Clone)]
+struct TemplateCell {
+    col: u32,
+    value: String,
+    style: Style,
+}
+
+/// 名前付き範囲`header`・`row`・`footer`、または`{{header.*}}`・`{{row.*}}`・`{{footer.*}}`の
+/// プレースホルダーで行を指定したテンプレートのシート
+pub struct ExcelTemplate {
+    worksheet: Worksheet,
+    header_row: Option<u32>,
+    record_row: u32,
+    footer_row: Option<u32>,
+}
+
+impl ExcelTemplate {
+    pub fn read(template_filepath: &Path, sheet_title: &str) -> Result<Self> {
+        let book = reader::xlsx::read(template_filepath).map_err(|e| {
+            ConvertError::workbook(
+                Some(template_filepath),
+                format!("failed to read the template: {e}"),
+            )
+        })?;
+
+        // 出力先と同じ名前のシート、なければ先頭のシートをテンプレートにする
+        let worksheet = book
+            .get_sheet_by_name(sheet_title)
+            .or_else(|| book.get_sheet(&0))
+            .ok_or_else(|| {
+                ConvertError::workbook(Some(template_filepath), "the template has no worksheet.")
+            })?
+            .clone();
+
+        let header_row = Self::find_row(&book, &worksheet, "header");
+        let footer_row = Self::find_row(&book, &worksheet, "footer");
+        let record_row = Self::find_row(&book, &worksheet, "row").ok_or_else(|| {
+            ConvertError::workbook(
+                Some(template_filepath),
+                "the template has no 'row' range or '{{row.*}}' placeholder.",
+            )
+        })?;
+
+        Ok(ExcelTemplate {
+            worksheet,
+            header_row,
+            record_row,
+            footer_row,
+        })
+    }
+
+    /// 名前付き範囲の先頭行、なければ`{{name.*}}`を含む最初の行を返す
+    fn find_row(book: &Spreadsheet, worksheet: &Worksheet, name: &str) -> Option<u32> {
+        let defined_name = book
+            .get_defined_names()
+            .iter()
+            .chain(worksheet.get_defined_names().iter())
+            .find(|defined_name| defined_name.get_name().eq_ignore_ascii_case(name));
+        if let Some(defined_name) = defined_name {
+            let (_, range) = split_address(&defined_name.get_address());
+            let row = range
+                .split(':')
+                .next()
+                .map(|start| {
+                    start
+                        .chars()
+                        .filter(char::is_ascii_digit)
+                        .collect::<String>()
+                })
+                .and_then(|row| row.parse::<u32>().ok());
+            if row.is_some() {
+                return row;
+            }
+        }
+
+        let prefix = format!("{{{{{name}.");
+        worksheet
+            .get_cell_collection()
+            .iter()
+            .filter(|cell| cell.get_value().contains(&prefix))
+            .map(|cell| *cell.get_coordinate().get_row_num())
+            .min()
+    }
+
+    /// テンプレートのシートを複製し、ヘッダー・明細・小計・サマリーを埋め込んで返す
+    pub fn render(
+        &self,
+        sheet_title: &str,
+        columns: &ColumnSelector,
+        rows: &[TemplateRow],
+    ) -> Worksheet {
+        let mut worksheet = self.worksheet.clone();
+        worksheet.set_name(sheet_title);
+
+        // ヘッダーはラベルに置き換える
+        if let Some(header_row) = self.header_row {
+            for cell in worksheet.get_cell_collection_mut() {
+                let value = cell.get_value();
+                if *cell.get_coordinate().get_row_num() == header_row && value.contains("{{") {
+                    let value = Self::substitute(&value, |key| columns.label(key));
+                    cell.set_value(value.unwrap_or_default());
+                }
+            }
+        }
+
+        let record_cells = Self::get_row_cells(&worksheet, self.record_row);
+        let footer_cells = self
+            .footer_row
+            .map(|footer_row| Self::get_row_cells(&worksheet, footer_row))
+            .unwrap_or_default();
+        let record_height = Self::get_row_height(&worksheet, self.record_row);
+        let footer_height = self
+            .footer_row
+            .and_then(|footer_row| Self::get_row_height(&worksheet, footer_row));
+
+        // テンプレート行を取り除き、データ行を挿入する
+        let mut template_rows = vec![self.record_row];
+        template_rows.extend(self.footer_row);
+        template_rows.sort_unstable();
+        template_rows.dedup();
+        for row in template_rows.iter().rev() {
+            worksheet.remove_row(row, &1);
+        }
+        let start_row = template_rows[0];
+        if !rows.is_empty() {
+            worksheet.insert_new_row(&start_row, &(rows.len() as u32));
+        }
+
+        let mut summary: HashMap<String, f64> = HashMap::new();
+        let mut count = 0;
+        for (index, row) in rows.iter().enumerate() {
+            let row_index = start_row + index as u32;
+            let (cells, height, fields) = match row {
+                TemplateRow::Record(fields) => {
+                    count += 1;
+                    (&record_cells, record_height, fields)
+                }
+                TemplateRow::Footer(fields) => {
+                    // 小計の合計をサマリーに使う
+                    for (field_name, value) in fields {
+                        if let Some(value) = value.as_ref().and_then(|v| v.parse::<f64>().ok()) {
+                            *summary.entry(field_name.clone()).or_default() += value;
+                        }
+                    }
+                    (&footer_cells, footer_height, fields)
+                }
+            };
+
+            let fields: HashMap<&str, &Option<String>> = fields
+                .iter()
+                .map(|(field_name, value)| (field_name.as_str(), value))
+                .collect();
+            for template_cell in cells {
+                let value = Self::substitute(&template_cell.value, |key| {
+                    fields.get(key).and_then(|value| (*value).clone())
+                });
+                let cell = worksheet.get_cell_mut((template_cell.col, row_index));
+                if let Some(value) = value {
+                    cell.set_value(value);
+                }
+                cell.set_style(template_cell.style.clone());
+            }
+
+            if let Some(height) = height {
+                worksheet
+                    .get_row_dimension_mut(&row_index)
+                    .set_height(height)
+                    .set_custom_height(true);
+            }
+        }
+
+        // サマリーのプレースホルダーを置き換える
+        for cell in worksheet.get_cell_collection_mut() {
+            let value = cell.get_value();
+            if value.contains("{{summary.") {
+                let value = Self::substitute(&value, |key| match key {
+                    "count" => Some(count.to_string()),
+                    key => summary.get(key).map(|value| value.to_string()),
+                });
+                cell.set_value(value.unwrap_or_default());
+            }
+        }
+
+        worksheet
+    }
+
+    fn get_row_cells(worksheet: &Worksheet, row: u32) -> Vec<TemplateCell> {
+        worksheet
+            .get_collection_by_row(&row)
+            .iter()
+            .map(|cell| TemplateCell {
+                col: *cell.get_coordinate().get_col_num(),
+                value: cell.get_value().to_string(),
+                style: cell.get_style().clone(),
+            })
+            .collect()
+    }
+
+    fn get_row_height(worksheet: &Worksheet, row: u32) -> Option<f64> {
+        worksheet
+            .get_row_dimension(&row)
+            .filter(|row| *row.get_custom_height())
+            .map(|row| *row.get_height())
+    }
+
+    /// `{{key}}`・`{{prefix.key}}`を`lookup`の結果に置き換える
+    ///
+    /// セル全体がプレースホルダーの場合は値をそのまま返し、値がなければ`None`を返す
+    fn substitute<F>(text: &str, lookup: F) -> Option<String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let key = |placeholder: &str| {
+            let placeholder = placeholder.trim();
+            ["header.", "row.", "footer.", "summary."]
+                .iter()
+                .find_map(|prefix| placeholder.strip_prefix(prefix))
+                .unwrap_or(placeholder)
+                .to_string()
+        };
+
+        if let Some(placeholder) = text
+            .strip_prefix("{{")
+            .and_then(|text| text.strip_suffix("}}"))
+            .filter(|placeholder| !placeholder.contains("{{"))
+        {
+            return lookup(&key(placeholder));
+        }
+
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            result.push_str(&rest[..start]);
+            result.push_str(&lookup(&key(&rest[start + 2..start + end])).unwrap_or_default());
+            rest = &rest[start + end + 2..];
+        }
+        result.push_str(rest);
+        Some(result)
+    }
+}
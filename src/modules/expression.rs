@@ -0,0 +1,552 @@
+use std::error::Error;
+use std::fmt;
+
+/// 派生列などに使う四則演算の式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// 比較の演算子
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,          // =, ==
+    NotEqual,       // !=
+    Less,           // <
+    LessOrEqual,    // <=
+    Greater,        // >
+    GreaterOrEqual, // >=
+    Contains,       // contains(文字列を含む)
+}
+
+/// 比較の左辺・右辺
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Text(String),           // 引用符で囲んだ文字列
+    Expression(Expression), // 変数・数値・四則演算
+}
+
+/// 行の絞り込みなどに使う条件式
+///
+/// 比較を`and`・`or`・`not`と括弧で組み合わせる。両辺が数値なら数値として、
+/// それ以外は文字列として比べる(例: `amount < 0 and account = '特定'`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare(Comparison, Operand, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Symbol(char),
+    Text(String),
+    Comparison(Comparison),
+}
+
+#[derive(Debug)]
+pub struct ExpressionError {
+    expression: String,
+    message: String,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to parse expression '{}': {}",
+            self.expression, self.message
+        )
+    }
+}
+
+impl Error for ExpressionError {}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, ExpressionError> {
+        let error = |message: String| ExpressionError {
+            expression: text.to_string(),
+            message,
+        };
+
+        let tokens = Self::tokenize(text).map_err(error)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let expression = parser.parse_additive().map_err(error)?;
+        if let Some(token) = parser.peek() {
+            return Err(error(format!("unexpected token {token:?}")));
+        }
+        Ok(expression)
+    }
+
+    /// 変数を`lookup`で解決して計算する。解決できない変数があれば`None`を返す
+    pub fn evaluate<F>(&self, lookup: &F) -> Option<f64>
+    where
+        F: Fn(&str) -> Option<f64>,
+    {
+        match self {
+            Expression::Number(value) => Some(*value),
+            Expression::Variable(name) => lookup(name),
+            Expression::Negate(expression) => expression.evaluate(lookup).map(|value| -value),
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(lookup)?;
+                let right = right.evaluate(lookup)?;
+                match operator {
+                    Operator::Add => Some(left + right),
+                    Operator::Subtract => Some(left - right),
+                    Operator::Multiply => Some(left * right),
+                    Operator::Divide if right == 0.0 => None,
+                    Operator::Divide => Some(left / right),
+                }
+            }
+        }
+    }
+
+    /// 式で使われている変数名を返す
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expression::Number(_) => vec![],
+            Expression::Variable(name) => vec![name.as_str()],
+            Expression::Negate(expression) => expression.variables(),
+            Expression::Binary(_, left, right) => {
+                let mut variables = left.variables();
+                variables.extend(right.variables());
+                variables
+            }
+        }
+    }
+
+    fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        let mut chars = text.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_digit() || c == '.' {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(c);
+                    chars.next();
+                }
+                let value = number
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid number '{number}': {e}"))?;
+                tokens.push(Token::Number(value));
+            } else if c.is_alphabetic() || c == '_' {
+                let mut identifier = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                    identifier.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Identifier(identifier));
+            } else if "+-*/()".contains(c) {
+                tokens.push(Token::Symbol(c));
+                chars.next();
+            } else if c == '"' || c == '\'' {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(next) if next == c => break,
+                        Some(next) => text.push(next),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            } else if "<>=!".contains(c) {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                let comparison = match (c, equals) {
+                    ('<', false) => Comparison::Less,
+                    ('<', true) => Comparison::LessOrEqual,
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    ('=', _) => Comparison::Equal,
+                    ('!', true) => Comparison::NotEqual,
+                    _ => return Err("unexpected character '!'. Use '!=' or 'not'".to_string()),
+                };
+                tokens.push(Token::Comparison(comparison));
+            } else {
+                return Err(format!("unexpected character '{c}'"));
+            }
+        }
+        Ok(tokens)
+    }
+}
+
+/// 比べる値。数値として解釈できる場合は数値も持つ
+struct Value {
+    text: String,
+    number: Option<f64>,
+}
+
+impl Value {
+    fn from_text(text: String) -> Self {
+        let number = text.replace(",", "").parse().ok();
+        Value { text, number }
+    }
+}
+
+impl Operand {
+    fn evaluate<F>(&self, lookup: &F) -> Option<Value>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        match self {
+            Operand::Text(text) => Some(Value::from_text(text.clone())),
+            Operand::Expression(Expression::Variable(name)) => lookup(name).map(Value::from_text),
+            Operand::Expression(expression) => {
+                let number = expression.evaluate(&|name: &str| {
+                    lookup(name).and_then(|value| Value::from_text(value).number)
+                })?;
+                Some(Value {
+                    text: number.to_string(),
+                    number: Some(number),
+                })
+            }
+        }
+    }
+
+    fn variables(&self) -> Vec<&str> {
+        match self {
+            Operand::Text(_) => vec![],
+            Operand::Expression(expression) => expression.variables(),
+        }
+    }
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, ExpressionError> {
+        let error = |message: String| ExpressionError {
+            expression: text.to_string(),
+            message,
+        };
+
+        let tokens = Expression::tokenize(text).map_err(error)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let condition = parser.parse_or().map_err(error)?;
+        if let Some(token) = parser.peek() {
+            return Err(error(format!("unexpected token {token:?}")));
+        }
+        Ok(condition)
+    }
+
+    /// 変数を`lookup`で解決して判定する。解決できない変数を含む比較は偽とする
+    pub fn evaluate<F>(&self, lookup: &F) -> bool
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        match self {
+            Condition::Compare(comparison, left, right) => {
+                let (Some(left), Some(right)) = (left.evaluate(lookup), right.evaluate(lookup))
+                else {
+                    return false;
+                };
+                let ordering = match (left.number, right.number) {
+                    (Some(left), Some(right)) => left.partial_cmp(&right),
+                    _ => Some(left.text.cmp(&right.text)),
+                };
+                match comparison {
+                    Comparison::Contains => left.text.contains(&right.text),
+                    Comparison::Equal => ordering.is_some_and(|o| o.is_eq()),
+                    Comparison::NotEqual => ordering.is_some_and(|o| o.is_ne()),
+                    Comparison::Less => ordering.is_some_and(|o| o.is_lt()),
+                    Comparison::LessOrEqual => ordering.is_some_and(|o| o.is_le()),
+                    Comparison::Greater => ordering.is_some_and(|o| o.is_gt()),
+                    Comparison::GreaterOrEqual => ordering.is_some_and(|o| o.is_ge()),
+                }
+            }
+            Condition::And(left, right) => left.evaluate(lookup) && right.evaluate(lookup),
+            Condition::Or(left, right) => left.evaluate(lookup) || right.evaluate(lookup),
+            Condition::Not(condition) => !condition.evaluate(lookup),
+        }
+    }
+
+    /// 条件式で使われている変数名を返す
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Condition::Compare(_, left, right) => {
+                let mut variables = left.variables();
+                variables.extend(right.variables());
+                variables
+            }
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                let mut variables = left.variables();
+                variables.extend(right.variables());
+                variables
+            }
+            Condition::Not(condition) => condition.variables(),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(name)) if name.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // or := and ('or' and)*
+    fn parse_or(&mut self) -> Result<Condition, String> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("or") {
+            let right = self.parse_and()?;
+            left = Condition::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // and := not ('and' not)*
+    fn parse_and(&mut self) -> Result<Condition, String> {
+        let mut left = self.parse_not()?;
+        while self.eat_keyword("and") {
+            let right = self.parse_not()?;
+            left = Condition::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // not := 'not' not | comparison | '(' or ')'
+    fn parse_not(&mut self) -> Result<Condition, String> {
+        if self.eat_keyword("not") {
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+        // `(amount + fee) > 0`と`(amount > 0 or ...)`を区別するため、比較から試す
+        let start = self.position;
+        match self.parse_comparison() {
+            Ok(condition) => Ok(condition),
+            Err(message) => {
+                self.position = start;
+                if !self.eat('(') {
+                    return Err(message);
+                }
+                let condition = self.parse_or()?;
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(condition)
+            }
+        }
+    }
+
+    // comparison := operand (comparison_operator | 'contains') operand
+    fn parse_comparison(&mut self) -> Result<Condition, String> {
+        let left = self.parse_operand()?;
+        let comparison = if self.eat_keyword("contains") {
+            Comparison::Contains
+        } else {
+            match self.next() {
+                Some(Token::Comparison(comparison)) => comparison,
+                Some(token) => return Err(format!("expected a comparison, found {token:?}")),
+                None => return Err("expected a comparison".to_string()),
+            }
+        };
+        let right = self.parse_operand()?;
+        Ok(Condition::Compare(comparison, left, right))
+    }
+
+    // operand := text | additive
+    fn parse_operand(&mut self) -> Result<Operand, String> {
+        if let Some(Token::Text(text)) = self.peek() {
+            let text = text.clone();
+            self.position += 1;
+            return Ok(Operand::Text(text));
+        }
+        Ok(Operand::Expression(self.parse_additive()?))
+    }
+
+    // additive := multiplicative (('+' | '-') multiplicative)*
+    fn parse_additive(&mut self) -> Result<Expression, String> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let operator = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Subtract
+            } else {
+                return Ok(left);
+            };
+            let right = self.parse_multiplicative()?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+    }
+
+    // multiplicative := unary (('*' | '/') unary)*
+    fn parse_multiplicative(&mut self) -> Result<Expression, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let operator = if self.eat('*') {
+                Operator::Multiply
+            } else if self.eat('/') {
+                Operator::Divide
+            } else {
+                return Ok(left);
+            };
+            let right = self.parse_unary()?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+    }
+
+    // unary := '-' unary | primary
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        if self.eat('-') {
+            return Ok(Expression::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    // primary := number | identifier | '(' additive ')'
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::Identifier(name)) => Ok(Expression::Variable(name)),
+            Some(Token::Symbol('(')) => {
+                let expression = self.parse_additive()?;
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(expression)
+            }
+            Some(token) => Err(format!("unexpected token {token:?}")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn evaluate(condition: &str, values: &[(&str, &str)]) -> bool {
+        let values: HashMap<&str, &str> = values.iter().copied().collect();
+        Condition::parse(condition)
+            .unwrap()
+            .evaluate(&|name: &str| values.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let condition = Condition::parse("a = 1 or b = 1 and c = 1").unwrap();
+        assert!(
+            matches!(condition, Condition::Or(_, right) if matches!(*right, Condition::And(_, _)))
+        );
+
+        let values = [("a", "1"), ("b", "0"), ("c", "0")];
+        assert!(evaluate("a = 1 or b = 1 and c = 1", &values));
+        assert!(!evaluate("(a = 1 or b = 1) and c = 1", &values));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_comparison_or_group() {
+        let values = [("a", "1"), ("b", "0")];
+        assert!(!evaluate("not a = 1 and b = 0", &values));
+        assert!(evaluate("not (a = 1 and b = 1)", &values));
+        assert!(evaluate("NOT not a = 1", &values));
+    }
+
+    #[test]
+    fn parentheses_group_arithmetic_inside_comparisons() {
+        let values = [("amount", "-300"), ("fee", "500")];
+        assert!(evaluate("(amount + fee) > 0", &values));
+        assert!(evaluate(
+            "(amount + fee) * 2 = 400 and (amount < 0)",
+            &values
+        ));
+        assert!(Condition::parse("(amount > 0").is_err());
+    }
+
+    #[test]
+    fn contains_matches_substrings() {
+        let values = [("account", "特定口座"), ("security_name", "トヨタ自動車")];
+        assert!(evaluate("account contains '特定'", &values));
+        assert!(!evaluate("account contains \"NISA\"", &values));
+        assert!(evaluate("not security_name contains 'ソニー'", &values));
+    }
+
+    #[test]
+    fn numbers_are_compared_as_numbers_and_others_as_text() {
+        // 数値として比べれば 9 < 10、文字列として比べれば "9" > "10"
+        assert!(evaluate("a < 10", &[("a", "9")]));
+        assert!(evaluate("a < b", &[("a", "9"), ("b", "10")]));
+        // 桁区切りの付いた数値も数値として比べる
+        assert!(evaluate("a > 999", &[("a", "1,000")]));
+        // 片方が数値でなければ文字列として比べる
+        assert!(evaluate("a > '10'", &[("a", "9x")]));
+        assert!(evaluate("date >= '2024-04-01'", &[("date", "2024-12-01")]));
+        assert!(evaluate("account = '特定'", &[("account", "特定")]));
+        assert!(evaluate("account != '一般'", &[("account", "特定")]));
+    }
+
+    #[test]
+    fn missing_variables_are_false() {
+        assert!(!evaluate("amount < 0", &[]));
+        assert!(!evaluate("amount >= 0", &[]));
+        assert!(!evaluate("amount + 1 > 0", &[]));
+        assert!(!evaluate("account contains ''", &[]));
+        // 否定すれば真になる
+        assert!(evaluate("not amount < 0", &[]));
+    }
+
+    #[test]
+    fn invalid_conditions_fail_to_parse() {
+        assert!(Condition::parse("amount").is_err());
+        assert!(Condition::parse("amount ! 0").is_err());
+        assert!(Condition::parse("account = 'unterminated").is_err());
+        assert!(Condition::parse("a = 1 b = 2").is_err());
+    }
+
+    #[test]
+    fn variables_lists_names_on_both_sides() {
+        let condition = Condition::parse("a + b > c or not d contains 'x'").unwrap();
+        assert_eq!(condition.variables(), vec!["a", "b", "c", "d"]);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::error::{Error as ConvertError, Result};
+use crate::modules::{
+    expression::Condition,
+    report::{
+        pipeline::{PipelineHook, Transaction},
+        record::FieldSpec,
+    },
+};
+use chrono::NaiveDate;
+
+/// 条件式でフィールド名のほかに使える、正規化した項目の名前
+const NORMALIZED_NAMES: &[&str] = &[
+    "date",
+    "security_code",
+    "security_name",
+    "account",
+    "amount",
+];
+
+/// 日付・口座・銘柄・金額・条件式で取引を絞り込むフック
+///
+/// 指定した条件をすべて満たす取引だけを残す。条件に使う値がない取引は除く
+#[derive(Debug, Clone, Default)]
+pub struct RowFilter {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    accounts: Vec<String>,
+    securities: Vec<String>,
+    min_amount: Option<i64>,
+    max_amount: Option<i64>,
+    condition: Option<Condition>,
+}
+
+impl RowFilter {
+    pub fn new() -> Self {
+        RowFilter::default()
+    }
+
+    /// 日付の範囲。両端の日付を含む
+    pub fn with_period(mut self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Self {
+        self.from = from;
+        self.to = to;
+        self
+    }
+
+    /// 口座名にいずれかの文字列を含む取引だけを残す
+    pub fn with_accounts(mut self, accounts: Vec<String>) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// 銘柄コードが一致するか、銘柄名にいずれかの文字列を含む取引だけを残す
+    pub fn with_securities(mut self, securities: Vec<String>) -> Self {
+        self.securities = securities;
+        self
+    }
+
+    /// 実現損益/配当金の範囲。両端の金額を含む
+    pub fn with_amount_range(mut self, min_amount: Option<i64>, max_amount: Option<i64>) -> Self {
+        self.min_amount = min_amount;
+        self.max_amount = max_amount;
+        self
+    }
+
+    /// 条件式。変数にはフィールド名と`date`・`security_code`・`security_name`・`account`・`amount`を使える
+    pub fn with_condition(mut self, condition: Option<Condition>) -> Self {
+        self.condition = condition;
+        self
+    }
+
+    /// 条件が1つも指定されていない
+    pub fn is_empty(&self) -> bool {
+        self.from.is_none()
+            && self.to.is_none()
+            && self.accounts.is_empty()
+            && self.securities.is_empty()
+            && self.min_amount.is_none()
+            && self.max_amount.is_none()
+            && self.condition.is_none()
+    }
+
+    /// 条件式の変数がすべて`fields`か正規化した項目の名前か確かめる
+    ///
+    /// 知らない変数は値がなく比較が常に偽になるため、綴りの誤りで全行が消えないようエラーにする
+    pub fn check_variables(&self, fields: &[FieldSpec]) -> Result<()> {
+        let Some(condition) = &self.condition else {
+            return Ok(());
+        };
+        let unknown = condition.variables().into_iter().find(|name| {
+            !NORMALIZED_NAMES.contains(name) && !fields.iter().any(|field| field.name == *name)
+        });
+        match unknown {
+            Some(name) => {
+                let mut names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+                for name in NORMALIZED_NAMES {
+                    if !names.contains(name) {
+                        names.push(name);
+                    }
+                }
+                Err(ConvertError::Config(format!(
+                    "Unknown variable '{name}' in the filter condition. Use one of {}.",
+                    names.join(", ")
+                )))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// 取引が指定した条件をすべて満たすか
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        let account_matches = self.accounts.is_empty()
+            || transaction.account.as_deref().is_some_and(|account| {
+                self.accounts
+                    .iter()
+                    .any(|pattern| account.contains(pattern.as_str()))
+            });
+        let security_matches = self.securities.is_empty()
+            || self.securities.iter().any(|security| {
+                transaction.security_code.as_deref() == Some(security.as_str())
+                    || transaction
+                        .security_name
+                        .as_deref()
+                        .is_some_and(|name| name.contains(security.as_str()))
+            });
+
+        in_range(transaction.date, self.from, self.to)
+            && in_range(transaction.amount, self.min_amount, self.max_amount)
+            && account_matches
+            && security_matches
+            && self.condition.as_ref().is_none_or(|condition| {
+                condition.evaluate(&|name: &str| Self::lookup(transaction, name))
+            })
+    }
+
+    /// 条件式の変数の値。フィールド名を優先し、なければ正規化した項目を使う
+    fn lookup(transaction: &Transaction, name: &str) -> Option<String> {
+        if let Some(value) = transaction.field(name) {
+            return Some(value.to_string());
+        }
+        match name {
+            "date" => transaction.date.map(|date| date.to_string()),
+            "security_code" => transaction.security_code.clone(),
+            "security_name" => transaction.security_name.clone(),
+            "account" => transaction.account.clone(),
+            "amount" => transaction.amount.map(|amount| amount.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// 値が範囲に含まれるか。範囲の指定がなければ値がなくても含まれるとする
+fn in_range<T: PartialOrd>(value: Option<T>, min: Option<T>, max: Option<T>) -> bool {
+    if min.is_none() && max.is_none() {
+        return true;
+    }
+    value.is_some_and(|value| {
+        min.is_none_or(|min| min <= value) && max.is_none_or(|max| value <= max)
+    })
+}
+
+impl PipelineHook for RowFilter {
+    fn check(&self, fields: &[FieldSpec]) -> Result<()> {
+        self.check_variables(fields)
+    }
+
+    fn keep(&self, transaction: &Transaction) -> Result<bool> {
+        Ok(self.matches(transaction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::report::record::FieldType;
+
+    fn transaction(date: Option<&str>, account: &str, amount: Option<i64>) -> Transaction {
+        Transaction {
+            date: date.map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()),
+            security_code: Some("7203".to_string()),
+            security_name: Some("トヨタ自動車".to_string()),
+            account: Some(account.to_string()),
+            amount,
+            fields: vec![("product".to_string(), Some("国内株式".to_string()))],
+            ..Default::default()
+        }
+    }
+
+    fn date(text: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()
+    }
+
+    #[test]
+    fn period_and_amount_include_both_ends() {
+        let filter = RowFilter::new()
+            .with_period(date("2024-01-01"), date("2024-12-31"))
+            .with_amount_range(Some(-1000), Some(1000));
+        assert!(filter.matches(&transaction(Some("2024-01-01"), "特定", Some(-1000))));
+        assert!(filter.matches(&transaction(Some("2024-12-31"), "特定", Some(1000))));
+        assert!(!filter.matches(&transaction(Some("2025-01-01"), "特定", Some(0))));
+        assert!(!filter.matches(&transaction(Some("2024-06-01"), "特定", Some(1001))));
+    }
+
+    #[test]
+    fn missing_values_are_dropped_only_when_a_bound_is_set() {
+        let no_date = transaction(None, "特定", None);
+        assert!(RowFilter::new().matches(&no_date));
+        assert!(RowFilter::new()
+            .with_accounts(vec!["特定".to_string()])
+            .matches(&no_date));
+
+        assert!(!RowFilter::new()
+            .with_period(None, date("2024-12-31"))
+            .matches(&no_date));
+        assert!(!RowFilter::new()
+            .with_amount_range(Some(0), None)
+            .matches(&no_date));
+    }
+
+    #[test]
+    fn accounts_and_securities_match_any_pattern() {
+        let transaction = transaction(Some("2024-06-01"), "特定口座", Some(100));
+        let filter = RowFilter::new().with_accounts(vec!["NISA".to_string(), "特定".to_string()]);
+        assert!(filter.matches(&transaction));
+        assert!(!RowFilter::new()
+            .with_accounts(vec!["NISA".to_string()])
+            .matches(&transaction));
+
+        // 銘柄コードは一致、銘柄名は部分一致
+        for security in ["7203", "トヨタ"] {
+            assert!(RowFilter::new()
+                .with_securities(vec![security.to_string()])
+                .matches(&transaction));
+        }
+        assert!(!RowFilter::new()
+            .with_securities(vec!["720".to_string()])
+            .matches(&transaction));
+    }
+
+    #[test]
+    fn condition_uses_fields_before_normalized_values() {
+        let transaction = transaction(Some("2024-06-01"), "特定", Some(-500));
+        let filter = |condition: &str| {
+            RowFilter::new().with_condition(Some(Condition::parse(condition).unwrap()))
+        };
+        assert!(filter("amount < 0 and account = '特定'").matches(&transaction));
+        assert!(filter("product contains '国内'").matches(&transaction));
+        assert!(filter("date >= '2024-04-01'").matches(&transaction));
+        assert!(!filter("unknown_field = 1").matches(&transaction));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let fields = [FieldSpec::new("product", FieldType::Text)];
+        let filter = |condition: &str| {
+            RowFilter::new().with_condition(Some(Condition::parse(condition).unwrap()))
+        };
+        assert!(filter("product contains '国内' and amount < 0")
+            .check_variables(&fields)
+            .is_ok());
+        assert!(RowFilter::new().check_variables(&fields).is_ok());
+
+        for condition in [
+            "amout < 0",
+            "not (amout < 0)",
+            "date >= '2024-01-01' or prodcut = 1",
+        ] {
+            let error = filter(condition).check_variables(&fields).unwrap_err();
+            assert_eq!(error.kind(), crate::error::ErrorKind::Config);
+            assert!(error.to_string().contains("Unknown variable"), "{error}");
+        }
+    }
+
+    #[test]
+    fn is_empty_without_conditions() {
+        assert!(RowFilter::new().is_empty());
+        assert!(!RowFilter::new().with_amount_range(None, Some(0)).is_empty());
+    }
+}
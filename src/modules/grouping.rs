@@ -0,0 +1,113 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// グループ化の単位
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Day,      // 日
+    Week,     // 週(月曜始まり)
+    Month,    // 月
+    Year,     // 年
+    Security, // 銘柄
+    Account,  // 口座
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GroupKey {
+    Day(NaiveDate),
+    Week(NaiveDate),
+    Month(i32, u32),
+    Year(i32),
+    Text(String),
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupKey::Day(date) => write!(f, "{date}"),
+            GroupKey::Week(date) => write!(f, "{date}~"),
+            GroupKey::Month(year, month) => write!(f, "{year}/{month:02}"),
+            GroupKey::Year(year) => write!(f, "{year}"),
+            GroupKey::Text(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+impl GroupBy {
+    /// レコードのグループのキーを返す。日付が必要な単位で日付がなければ`None`を返す
+    pub fn key(
+        &self,
+        date: Option<NaiveDate>,
+        security_code: Option<&str>,
+        account: Option<&str>,
+    ) -> Option<GroupKey> {
+        match self {
+            GroupBy::Day => date.map(GroupKey::Day),
+            GroupBy::Week => date.map(|date| {
+                GroupKey::Week(date - Duration::days(date.weekday().num_days_from_monday() as i64))
+            }),
+            GroupBy::Month => date.map(|date| GroupKey::Month(date.year(), date.month())),
+            GroupBy::Year => date.map(|date| GroupKey::Year(date.year())),
+            GroupBy::Security => Some(GroupKey::Text(
+                security_code.unwrap_or_default().to_string(),
+            )),
+            GroupBy::Account => Some(GroupKey::Text(account.unwrap_or_default().to_string())),
+        }
+    }
+
+    /// 全単位のキーを返す。いずれかの単位でキーがなければ`None`を返す
+    pub fn keys(
+        grouping: &[GroupBy],
+        date: Option<NaiveDate>,
+        security_code: Option<&str>,
+        account: Option<&str>,
+    ) -> Option<Vec<GroupKey>> {
+        grouping
+            .iter()
+            .map(|group_by| group_by.key(date, security_code, account))
+            .collect()
+    }
+}
+
+/// グループ化したレコードを書き込む順に並べた1行分
+pub enum GroupRow<'a, T> {
+    Records(&'a [T]),           // 最下位のグループの明細
+    Footer(String, Vec<&'a T>), // グループ名、グループに含まれる明細
+}
+
+/// グループの明細と、各階層の終わりの小計を書き込む順に返す
+///
+/// 下位の階層の小計から順に並べる
+pub fn flatten<T>(groups: &BTreeMap<Vec<GroupKey>, Vec<T>>) -> Vec<GroupRow<'_, T>> {
+    let mut rows = Vec::new();
+    let mut pending: Vec<Vec<&T>> = Vec::new();
+
+    let mut iter = groups.iter().peekable();
+    while let Some((key, list)) = iter.next() {
+        rows.push(GroupRow::Records(list));
+
+        pending.resize_with(key.len(), Vec::new);
+        for records in pending.iter_mut() {
+            records.extend(list.iter());
+        }
+
+        // 次のグループと共通しない階層の小計を書き込む
+        let common = iter.peek().map_or(0, |(next, _)| {
+            key.iter()
+                .zip(next.iter())
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        for level in (common..key.len()).rev() {
+            rows.push(GroupRow::Footer(
+                key[level].to_string(),
+                std::mem::take(&mut pending[level]),
+            ));
+        }
+    }
+    rows
+}
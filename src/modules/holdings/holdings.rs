@@ -0,0 +1,317 @@
+use super::price_table::PriceTable;
+use crate::error::Result;
+use crate::modules::{
+    report::record::{report_record, FieldSpec},
+    settings::Settings,
+    summary::lib::SummaryChart,
+    tax,
+    template_pattern::Fields,
+};
+use chrono::NaiveDate;
+use std::path::Path;
+
+report_record! {
+    /// 保有証券一覧の1銘柄分
+    ///
+    /// CSVは`銘柄コード,銘柄名,口座,保有数量[株/口],平均取得価額[円],現在値[円],時価評価額[円]`の
+    /// 列を列名で対応付けるため、並びは問わない。現在値・時価評価額の列は省略できる
+    pub struct Holdings {
+        security_code: String => column(0).label("銘柄コード"),
+        security_name: String => column(1).label("銘柄名"),
+        account: String => column(2).label("口座"),
+        shares: i32 => column(3).label("数量[株]"),              // 保有数量[株/口]
+        purchase_price: f64 =>
+            column(4).label("平均取得価額").format("yen_decimal"), // 平均取得価額[円]
+        current_price: f64 => column(5).label("現在値").format("yen_decimal"), // 現在値[円]
+        market_value: i64 => column(6).label("時価評価額").format("yen"), // 時価評価額[円]
+        price_date: NaiveDate => label("終値の日付"),            // 評価に使った終値の日付
+        valuation_price: f64 => label("評価単価").format("yen_decimal"),
+        acquisition_cost: i64 => label("取得額").format("yen"),
+        valuation: i64 => label("評価額").format("yen"),
+        unrealized_profit_and_loss: i64 =>
+            label("評価損益").format("yen").negative_font().data_bar(),
+        unrealized_rate: f64 => label("評価損益率").format("percent").negative_font(),
+        tax_if_sold: i64 => label("売却時の税額").format("yen"), // 売却した場合の源泉徴収税額
+    }
+
+    impl ReportModel {
+        const KEY: &'static str = "holdings";
+        const CHARTS: &'static [SummaryChart] = &[];
+        const CSV_HEADERS: &'static [&'static str] = &[
+            "銘柄コード",
+            "銘柄名",
+            "口座",
+            "保有数量[株/口]",
+            "平均取得価額[円]",
+            "現在値[円]",
+            "時価評価額[円]",
+        ];
+    }
+
+    impl ReportRecord {
+        fn date(&self) -> Option<NaiveDate> {
+            self.price_date
+        }
+
+        fn security_code(&self) -> Option<&str> {
+            self.security_code.as_deref()
+        }
+
+        fn security_name(&self) -> Option<&str> {
+            self.security_name.as_deref()
+        }
+
+        fn account(&self) -> Option<&str> {
+            self.account.as_deref()
+        }
+
+        fn amount(&self) -> Option<i64> {
+            self.unrealized_profit_and_loss
+        }
+
+        fn shares(&self) -> Option<f64> {
+            self.shares.map(f64::from)
+        }
+
+        /// `price_path`の終値か証券会社の評価額で評価し、評価損益と売却した場合の税額を計算する
+        fn complete(records: &mut [Self], settings: &Settings) -> Result<()> {
+            tax::check_tax_rate(settings.tax_rate)?;
+            let prices = match &settings.price_path {
+                Some(price_path) => PriceTable::load(Path::new(price_path))?,
+                None => PriceTable::default(),
+            };
+            for record in records {
+                record.value(&prices, settings.tax_rate);
+            }
+            Ok(())
+        }
+
+        /// 取得額・評価額・評価損益の合計と、特定口座の評価損益の合計に対する税額を返す
+        fn total(
+            records: &[&Self],
+            _fields: &[FieldSpec],
+            settings: &Settings,
+        ) -> Result<Fields> {
+            Ok(Self::get_total(records, settings.tax_rate).get_all_fields())
+        }
+    }
+}
+
+impl Holdings {
+    /// 評価単価と評価額を決め、評価損益・評価損益率・売却した場合の税額を計算する
+    ///
+    /// 終値のファイルにある銘柄はその終値、なければ証券会社の時価評価額、現在値の順に使う
+    pub fn value(&mut self, prices: &PriceTable, tax_rate: f64) {
+        let shares = self.shares.map(f64::from);
+        let close = self
+            .security_code
+            .as_deref()
+            .and_then(|security_code| prices.get(security_code));
+        (self.price_date, self.valuation_price, self.valuation) = match (close, shares) {
+            (Some((date, close)), Some(shares)) => (
+                Some(date),
+                Some(close),
+                Some((shares * close).round() as i64),
+            ),
+            _ => match (self.market_value, self.current_price, shares) {
+                (Some(market_value), current_price, shares) => (
+                    None,
+                    current_price.or(shares
+                        .filter(|shares| *shares != 0.0)
+                        .map(|shares| market_value as f64 / shares)),
+                    Some(market_value),
+                ),
+                (None, Some(current_price), Some(shares)) => (
+                    None,
+                    Some(current_price),
+                    Some((shares * current_price).round() as i64),
+                ),
+                _ => (None, None, None),
+            },
+        };
+
+        self.acquisition_cost = shares
+            .zip(self.purchase_price)
+            .map(|(shares, purchase_price)| (shares * purchase_price).round() as i64);
+        self.unrealized_profit_and_loss = self
+            .valuation
+            .zip(self.acquisition_cost)
+            .map(|(valuation, acquisition_cost)| valuation - acquisition_cost);
+        self.unrealized_rate = Self::rate(self.unrealized_profit_and_loss, self.acquisition_cost);
+        self.tax_if_sold = self.unrealized_profit_and_loss.map(|unrealized| {
+            match self.account.as_deref().is_some_and(tax::is_taxable_account) {
+                true => tax::withholding_tax(unrealized, tax_rate),
+                false => 0,
+            }
+        });
+    }
+
+    /// 合計の行を返す。税額は特定口座の評価損益を通算してから計算する
+    pub fn get_total(records: &[&Self], tax_rate: f64) -> Self {
+        let sum = |value: fn(&Self) -> Option<i64>| {
+            records
+                .iter()
+                .filter_map(|record| value(record))
+                .sum::<i64>()
+        };
+        let acquisition_cost = sum(|record| record.acquisition_cost);
+        let unrealized_profit_and_loss = sum(|record| record.unrealized_profit_and_loss);
+        let taxable_total = records
+            .iter()
+            .filter(|record| {
+                record
+                    .account
+                    .as_deref()
+                    .is_some_and(tax::is_taxable_account)
+            })
+            .filter_map(|record| record.unrealized_profit_and_loss)
+            .sum();
+        Holdings {
+            acquisition_cost: Some(acquisition_cost),
+            valuation: Some(sum(|record| record.valuation)),
+            unrealized_profit_and_loss: Some(unrealized_profit_and_loss),
+            unrealized_rate: Self::rate(Some(unrealized_profit_and_loss), Some(acquisition_cost)),
+            tax_if_sold: Some(tax::withholding_tax(taxable_total, tax_rate)),
+            ..Default::default()
+        }
+    }
+
+    /// 取得額に対する評価損益の割合。取得額がなければ`None`
+    fn rate(unrealized: Option<i64>, acquisition_cost: Option<i64>) -> Option<f64> {
+        match (unrealized, acquisition_cost) {
+            (Some(unrealized), Some(acquisition_cost)) if acquisition_cost > 0 => {
+                Some(((unrealized as f64 / acquisition_cost as f64) * 10000.0).round() / 10000.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::modules::report::record::ReportModel;
+    use csv::StringRecord;
+
+    const TAX_RATE: f64 = 0.20315;
+
+    fn holding(account: &str, shares: i32, purchase_price: f64) -> Holdings {
+        Holdings {
+            security_code: Some("7203".to_string()),
+            security_name: Some("トヨタ自動車".to_string()),
+            account: Some(account.to_string()),
+            shares: Some(shares),
+            purchase_price: Some(purchase_price),
+            ..Default::default()
+        }
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn columns_are_mapped_by_header_name() {
+        let headers = StringRecord::from(vec![
+            "口座",
+            "銘柄名",
+            "保有数量[株/口]",
+            "銘柄コード",
+            "時価評価額[円]",
+            "平均取得価額[円]",
+            "現在値[円]",
+        ]);
+        let row = StringRecord::from(vec![
+            "特定",
+            "トヨタ自動車",
+            "100",
+            "7203",
+            "300,000",
+            "2,500.0",
+            "3,000.0",
+        ]);
+        let rows = Holdings::definition().arrange(&headers, vec![row]).unwrap();
+        let holdings = Holdings::from_record(rows[0].clone()).unwrap();
+        assert_eq!(holdings.security_code.as_deref(), Some("7203"));
+        assert_eq!(holdings.account.as_deref(), Some("特定"));
+        assert_eq!(holdings.shares, Some(100));
+        assert_eq!(holdings.purchase_price, Some(2500.0));
+        assert_eq!(holdings.current_price, Some(3000.0));
+        assert_eq!(holdings.market_value, Some(300_000));
+    }
+
+    #[test]
+    fn only_trailing_columns_may_be_missing() {
+        let definition = Holdings::definition();
+        let headers = StringRecord::from(vec![
+            "銘柄コード",
+            "銘柄名",
+            "口座",
+            "保有数量[株/口]",
+            "平均取得価額[円]",
+        ]);
+        let row = StringRecord::from(vec!["7203", "トヨタ自動車", "特定", "100", "2500"]);
+        let rows = definition.arrange(&headers, vec![row]).unwrap();
+        let holdings = Holdings::from_record(rows[0].clone()).unwrap();
+        assert_eq!(holdings.current_price, None);
+        assert_eq!(holdings.market_value, None);
+
+        let headers = StringRecord::from(vec!["銘柄コード", "保有数量[株/口]", "現在値[円]"]);
+        let error = definition.arrange(&headers, Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Parse);
+        assert!(error.to_string().contains("missing the column '銘柄名'"));
+    }
+
+    #[test]
+    fn the_price_file_overrides_the_broker_valuation() {
+        let mut prices = PriceTable::default();
+        prices.insert("7203", date("2024-12-30"), 3200.0);
+        let mut holdings = Holdings {
+            current_price: Some(3000.0),
+            market_value: Some(300_000),
+            ..holding("特定", 100, 2500.0)
+        };
+        holdings.value(&prices, TAX_RATE);
+        assert_eq!(holdings.price_date, Some(date("2024-12-30")));
+        assert_eq!(holdings.valuation_price, Some(3200.0));
+        assert_eq!(holdings.valuation, Some(320_000));
+        assert_eq!(holdings.acquisition_cost, Some(250_000));
+        assert_eq!(holdings.unrealized_profit_and_loss, Some(70_000));
+        assert_eq!(holdings.unrealized_rate, Some(0.28));
+
+        // 終値のない銘柄は証券会社の時価評価額、それもなければ現在値で評価する
+        holdings.value(&PriceTable::default(), TAX_RATE);
+        assert_eq!(holdings.price_date, None);
+        assert_eq!(holdings.valuation, Some(300_000));
+        let mut holdings = Holdings {
+            current_price: Some(2800.0),
+            ..holding("特定", 100, 2500.0)
+        };
+        holdings.value(&PriceTable::default(), TAX_RATE);
+        assert_eq!(holdings.valuation, Some(280_000));
+    }
+
+    #[test]
+    fn tax_if_sold_applies_only_to_taxable_gains() {
+        let prices = {
+            let mut prices = PriceTable::default();
+            prices.insert("7203", date("2024-12-30"), 3000.0);
+            prices
+        };
+        let mut taxable = holding("特定", 100, 2000.0);
+        let mut nisa = holding("NISA成長投資枠", 100, 2000.0);
+        let mut loss = holding("特定", 100, 3500.0);
+        for holdings in [&mut taxable, &mut nisa, &mut loss] {
+            holdings.value(&prices, TAX_RATE);
+        }
+        assert_eq!(taxable.tax_if_sold, Some(20_315));
+        assert_eq!(nisa.tax_if_sold, Some(0));
+        assert_eq!(loss.tax_if_sold, Some(0));
+
+        // 合計の税額は特定口座の損益を通算してから計算する
+        let total = Holdings::get_total(&[&taxable, &nisa, &loss], TAX_RATE);
+        assert_eq!(total.unrealized_profit_and_loss, Some(150_000));
+        assert_eq!(total.tax_if_sold, Some(10_157));
+    }
+}
@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+pub mod holdings;
+pub mod positions;
+pub mod price_table;
@@ -0,0 +1,139 @@
+use super::{holdings::Holdings, price_table::PriceTable};
+use crate::error::Result;
+use crate::modules::{csv::lib::CsvTable, report::record::ReportModel, settings::Settings};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 1銘柄分の保有。複数の口座の保有は合計する
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub shares: Option<f64>, // 保有数量
+    pub cost: Option<i64>,   // 取得額
+}
+
+/// 銘柄コードごとの保有数量と取得額
+///
+/// 保有証券一覧のCSVか、`code`と省略できる`shares`・`cost`の列を持つCSVから読み込む
+#[derive(Debug, Clone, Default)]
+pub struct Positions {
+    positions: BTreeMap<String, Position>,
+}
+
+impl Positions {
+    /// `holdings_path`を指定していれば読み込む
+    pub fn from_settings(settings: &Settings) -> Result<Option<Self>> {
+        settings
+            .holdings_path
+            .as_deref()
+            .map(|holdings_path| {
+                let filepath = Path::new(holdings_path);
+                Self::load(filepath, settings).map_err(|e| e.with_path(filepath))
+            })
+            .transpose()
+    }
+
+    pub fn load(filepath: &Path, settings: &Settings) -> Result<Self> {
+        let csv = CsvTable::load(filepath, "holdings file")?;
+        let mut positions = Positions::default();
+
+        // 列名がなければ保有証券一覧として読み込む
+        let Some(code) = csv.column("code") else {
+            let records = Holdings::definition()
+                .arrange(
+                    csv.headers(),
+                    csv.rows().map(|row| row.record.clone()).collect(),
+                )
+                .map_err(|e| csv.invalid(e))?;
+            for (row, record) in csv.rows().zip(records) {
+                let mut holdings = Holdings::from_record(record).map_err(|e| row.invalid(e))?;
+                holdings.value(&PriceTable::default(), settings.tax_rate);
+                if let Some(security_code) = &holdings.security_code {
+                    positions.add(
+                        security_code,
+                        holdings.shares.map(f64::from),
+                        holdings.acquisition_cost,
+                    );
+                }
+            }
+            return Ok(positions);
+        };
+
+        let (shares, cost) = (csv.column("shares"), csv.column("cost"));
+        for row in csv.rows() {
+            let Some(security_code) = row.value(Some(code)) else {
+                continue;
+            };
+            positions.add(
+                security_code,
+                row.parse_value(shares)?,
+                row.parse_value(cost)?,
+            );
+        }
+        Ok(positions)
+    }
+
+    /// 保有を加える。同じ銘柄の保有があれば数量と取得額を合計する
+    pub fn add(&mut self, security_code: &str, shares: Option<f64>, cost: Option<i64>) {
+        let position = self
+            .positions
+            .entry(security_code.trim().to_string())
+            .or_default();
+        if let Some(shares) = shares {
+            *position.shares.get_or_insert(0.0) += shares;
+        }
+        if let Some(cost) = cost {
+            *position.cost.get_or_insert(0) += cost;
+        }
+    }
+
+    pub fn get(&self, security_code: &str) -> Option<&Position> {
+        self.positions.get(security_code.trim())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Position)> {
+        self.positions.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load(content: &str) -> Positions {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("positions.csv");
+        fs::write(&filepath, content).unwrap();
+        Positions::load(&filepath, &Settings::defaults()).unwrap()
+    }
+
+    #[test]
+    fn positions_in_several_rows_are_added_up() {
+        let positions = load("code,shares,cost\n7203,100,250000\n7203,50,\n9432,,10000\n");
+        let toyota = positions.get("7203").unwrap();
+        assert_eq!(toyota.shares, Some(150.0));
+        assert_eq!(toyota.cost, Some(250_000));
+        assert_eq!(positions.get("9432").unwrap().shares, None);
+        assert_eq!(positions.len(), 2);
+    }
+
+    #[test]
+    fn holdings_files_are_read_by_header_name() {
+        let positions = load(
+            "口座,銘柄コード,銘柄名,平均取得価額[円],保有数量[株/口]\n\
+             特定,7203,トヨタ自動車,\"2,500.0\",100\n\
+             NISA成長投資枠,7203,トヨタ自動車,\"3,000.0\",100\n",
+        );
+        let toyota = positions.get("7203").unwrap();
+        assert_eq!(toyota.shares, Some(200.0));
+        assert_eq!(toyota.cost, Some(550_000));
+    }
+}
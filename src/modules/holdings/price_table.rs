@@ -0,0 +1,92 @@
+use crate::error::Result;
+use crate::modules::csv::lib::CsvTable;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 銘柄コードごとの終値。同じ銘柄が複数の日付にあれば最も新しい日付の終値を使う
+///
+/// CSVの列名は`date`・`code`・`close`
+#[derive(Debug, Clone, Default)]
+pub struct PriceTable {
+    closes: BTreeMap<String, (NaiveDate, f64)>,
+}
+
+impl PriceTable {
+    pub fn load(filepath: &Path) -> Result<Self> {
+        let csv = CsvTable::load(filepath, "price file")?;
+        let (date, code, close) = (
+            csv.required("date")?,
+            csv.required("code")?,
+            csv.required("close")?,
+        );
+
+        let mut table = PriceTable::default();
+        for row in csv.rows() {
+            table.insert(row.get(code), row.parse(date)?, row.parse(close)?);
+        }
+        Ok(table)
+    }
+
+    /// 終値を加える。すでにより新しい日付の終値があれば加えない
+    pub fn insert(&mut self, security_code: &str, date: NaiveDate, close: f64) {
+        let entry = self
+            .closes
+            .entry(security_code.trim().to_string())
+            .or_insert((date, close));
+        if entry.0 <= date {
+            *entry = (date, close);
+        }
+    }
+
+    /// 最も新しい日付と終値を返す
+    pub fn get(&self, security_code: &str) -> Option<(NaiveDate, f64)> {
+        self.closes.get(security_code.trim()).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.closes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.closes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn the_latest_close_wins() {
+        let mut table = PriceTable::default();
+        table.insert("7203", date("2024-12-27"), 2900.0);
+        table.insert(" 7203 ", date("2024-12-30"), 3000.0);
+        table.insert("7203", date("2024-12-26"), 2800.0);
+        assert_eq!(table.get("7203"), Some((date("2024-12-30"), 3000.0)));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn load_reads_columns_by_name_and_requires_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("prices.csv");
+        fs::write(
+            &filepath,
+            "close,code,date\n\"3,000\",7203,2024-12-30\n1500,6758,2024/12/30\n",
+        )
+        .unwrap();
+        let table = PriceTable::load(&filepath).unwrap();
+        assert_eq!(table.get("7203"), Some((date("2024-12-30"), 3000.0)));
+        assert_eq!(table.get("6758"), Some((date("2024-12-30"), 1500.0)));
+
+        fs::write(&filepath, "code,close\n7203,3000\n").unwrap();
+        let error = PriceTable::load(&filepath).unwrap_err().to_string();
+        assert!(error.contains("missing the column 'date'"), "{error}");
+    }
+}
@@ -0,0 +1,566 @@
+use crate::error::Result;
+use crate::modules::{
+    csv::lib::CsvTable,
+    excel::lib::ExcelAccessor,
+    report::{
+        pipeline::{PipelineHook, Transaction},
+        record::{FieldSpec, FieldType, FieldValue},
+    },
+    summary::lib::SummaryWriter,
+    template_pattern::Report,
+};
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+
+/// 約定履歴で実現損益の明細に付け加えるフィールドと型・ラベル。`columns`で選ぶと列に出力する
+pub const LOT_FIELDS: &[(&str, FieldType, &str)] = &[
+    ("acquisition_date", FieldType::Date, "取得日"),
+    ("holding_days", FieldType::Int, "保有日数"),
+    ("fifo_cost", FieldType::Int, "取得価額(先入先出)"),
+    ("fifo_profit_and_loss", FieldType::Int, "損益(先入先出)"),
+    ("average_cost", FieldType::Int, "取得価額(移動平均)"),
+    (
+        "average_cost_profit_and_loss",
+        FieldType::Int,
+        "損益(移動平均)",
+    ),
+    ("annualized_return", FieldType::Float, "年率リターン"),
+];
+
+/// 数量の比較で丸め誤差とみなす差。小数の口数を足し引きしても0や一致を判定できるようにする
+const SHARES_EPSILON: f64 = 1e-6;
+
+/// 売買の区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,  // 買付
+    Sell, // 売却
+}
+
+impl Side {
+    /// `buy`・`sell`、または`買`・`売`を含む文字列(`現物買`など)を解釈する
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("buy") || text.contains('買') {
+            Some(Side::Buy)
+        } else if text.eq_ignore_ascii_case("sell") || text.contains('売') {
+            Some(Side::Sell)
+        } else {
+            None
+        }
+    }
+}
+
+/// 約定履歴の1件分
+#[derive(Debug, Clone)]
+pub struct Execution {
+    pub date: NaiveDate,         // 約定日
+    pub security_code: String,   // 銘柄コード
+    pub account: Option<String>, // 口座
+    pub side: Side,              // 売買の区分
+    pub shares: f64,             // 数量
+    pub price: f64,              // 約定単価
+    pub fee: f64,                // 手数料。買付は取得価額に加え、売却は売却額から引く
+}
+
+/// 売却1件を買付のロットに対応付けた結果
+#[derive(Debug, Clone)]
+pub struct ClosedTrade {
+    pub date: NaiveDate,                     // 約定日
+    pub security_code: String,               // 銘柄コード
+    pub account: Option<String>,             // 口座
+    pub shares: f64,                         // 数量
+    pub proceeds: f64,                       // 売却額(手数料を引いた額)
+    pub fifo_cost: f64,                      // 先入先出法の取得価額
+    pub average_cost: f64,                   // 移動平均法の取得価額
+    pub acquisition_date: Option<NaiveDate>, // 最初に対応付けたロットの約定日
+    pub holding_days: Option<f64>,           // 数量で加重平均した保有日数
+    pub unmatched_shares: f64,               // 対応するロットがなかった数量
+}
+
+impl ClosedTrade {
+    /// 売却した数量がすべてロットに対応付いた
+    pub fn is_matched(&self) -> bool {
+        self.unmatched_shares <= SHARES_EPSILON
+    }
+}
+
+/// 買付のロット
+#[derive(Debug, Clone)]
+struct Lot {
+    date: NaiveDate,
+    shares: f64,
+    unit_cost: f64,
+}
+
+/// 銘柄・口座ごとの保有
+#[derive(Debug, Clone, Default)]
+struct Position {
+    lots: VecDeque<Lot>,
+    shares: f64,
+    average_unit_cost: f64,
+}
+
+/// 約定履歴の売却を買付のロットに対応付け、実現損益の明細に保有期間と取得価額を付け加える
+///
+/// 分析用の先入先出法と、税務上の移動平均法の取得価額を並べて計算する。
+/// CSVの列名は`date`・`code`・`side`・`shares`・`price`と、省略できる`account`・`fee`
+#[derive(Debug, Clone, Default)]
+pub struct LotMatcher {
+    closed_trades: Vec<ClosedTrade>,
+}
+
+impl LotMatcher {
+    pub fn load(filepath: &Path) -> Result<Self> {
+        let csv = CsvTable::load(filepath, "execution history")?;
+        let (date, code, side, shares, price) = (
+            csv.required("date")?,
+            csv.required("code")?,
+            csv.required("side")?,
+            csv.required("shares")?,
+            csv.required("price")?,
+        );
+        let (account, fee) = (csv.column("account"), csv.column("fee"));
+
+        let mut executions = Vec::new();
+        for row in csv.rows() {
+            let shares = row.parse(shares)?;
+            if shares <= 0.0 {
+                return Err(row.invalid("shares must be positive."));
+            }
+            executions.push(Execution {
+                date: row.parse(date)?,
+                security_code: row.get(code).to_string(),
+                account: row.value(account).map(str::to_string),
+                side: Side::parse(row.get(side))
+                    .ok_or_else(|| row.invalid(format!("unknown side '{}'.", row.get(side))))?,
+                shares,
+                price: row.parse(price)?,
+                fee: row.parse_value(fee)?.unwrap_or_default(),
+            });
+        }
+        Ok(Self::from_executions(executions))
+    }
+
+    /// 約定日順に買付をロットに積み、売却を古いロットから対応付ける
+    pub fn from_executions(mut executions: Vec<Execution>) -> Self {
+        executions.sort_by_key(|execution| execution.date);
+        let mut positions: BTreeMap<(String, Option<String>), Position> = BTreeMap::new();
+        let mut closed_trades = Vec::new();
+
+        for execution in executions {
+            let position = positions
+                .entry((execution.security_code.clone(), execution.account.clone()))
+                .or_default();
+            match execution.side {
+                Side::Buy => {
+                    let cost = execution.shares * execution.price + execution.fee;
+                    let shares = position.shares + execution.shares;
+                    if shares > 0.0 {
+                        position.average_unit_cost =
+                            (position.average_unit_cost * position.shares + cost) / shares;
+                    }
+                    position.shares = shares;
+                    position.lots.push_back(Lot {
+                        date: execution.date,
+                        shares: execution.shares,
+                        unit_cost: cost / execution.shares,
+                    });
+                }
+                Side::Sell => {
+                    closed_trades.push(Self::close(position, &execution));
+                }
+            }
+        }
+        LotMatcher { closed_trades }
+    }
+
+    /// 売却1件分の数量を古いロットから取り崩す
+    fn close(position: &mut Position, execution: &Execution) -> ClosedTrade {
+        let mut remaining = execution.shares;
+        let mut fifo_cost = 0.0;
+        let mut weighted_days = 0.0;
+        let mut acquisition_date = None;
+        while remaining > SHARES_EPSILON {
+            let Some(lot) = position.lots.front_mut() else {
+                break;
+            };
+            let shares = remaining.min(lot.shares);
+            acquisition_date.get_or_insert(lot.date);
+            fifo_cost += shares * lot.unit_cost;
+            weighted_days += shares * (execution.date - lot.date).num_days() as f64;
+            lot.shares -= shares;
+            remaining -= shares;
+            if lot.shares <= SHARES_EPSILON {
+                position.lots.pop_front();
+            }
+        }
+
+        let matched_shares = execution.shares - remaining;
+        let average_cost = execution.shares * position.average_unit_cost;
+        position.shares -= execution.shares;
+        if position.shares <= SHARES_EPSILON {
+            position.shares = 0.0;
+        }
+        ClosedTrade {
+            date: execution.date,
+            security_code: execution.security_code.clone(),
+            account: execution.account.clone(),
+            shares: execution.shares,
+            proceeds: execution.shares * execution.price - execution.fee,
+            fifo_cost,
+            average_cost,
+            acquisition_date,
+            holding_days: (matched_shares > SHARES_EPSILON).then(|| weighted_days / matched_shares),
+            unmatched_shares: remaining.max(0.0),
+        }
+    }
+
+    pub fn closed_trades(&self) -> &[ClosedTrade] {
+        &self.closed_trades
+    }
+
+    /// 取引ごとに対応する売却を返す。1件の売却は1件の取引にだけ対応付ける
+    ///
+    /// 約定日・銘柄コード・口座が一致する売却のうち、数量が一致するものを先に対応付け、
+    /// 残りの取引には残った売却を約定日順に対応付ける。約定履歴に口座がなければ口座は比べない
+    pub fn assign<'a>(
+        &self,
+        transactions: impl IntoIterator<Item = &'a Transaction>,
+    ) -> Vec<Option<&ClosedTrade>> {
+        let candidates: Vec<(Option<f64>, Vec<usize>)> = transactions
+            .into_iter()
+            .map(|transaction| {
+                let shares = transaction
+                    .field("shares")
+                    .and_then(|shares| f64::parse(shares).ok());
+                (shares, self.candidates(transaction))
+            })
+            .collect();
+        let mut used = vec![false; self.closed_trades.len()];
+        let mut assigned = vec![None; candidates.len()];
+        // 1巡目は数量が一致する売却だけ、2巡目は残った売却を対応付ける
+        for same_shares in [true, false] {
+            for ((shares, indexes), assigned) in candidates.iter().zip(assigned.iter_mut()) {
+                if assigned.is_some() {
+                    continue;
+                }
+                let found = indexes.iter().copied().find(|&index| {
+                    !used[index]
+                        && (!same_shares
+                            || shares.is_some_and(|shares| {
+                                (self.closed_trades[index].shares - shares).abs() <= SHARES_EPSILON
+                            }))
+                });
+                if let Some(index) = found {
+                    used[index] = true;
+                    *assigned = Some(index);
+                }
+            }
+        }
+        assigned
+            .into_iter()
+            .map(|index| index.map(|index| &self.closed_trades[index]))
+            .collect()
+    }
+
+    /// 約定日・銘柄コード・口座が一致する売却の位置
+    fn candidates(&self, transaction: &Transaction) -> Vec<usize> {
+        let (Some(date), Some(security_code)) =
+            (transaction.date, transaction.security_code.as_deref())
+        else {
+            return Vec::new();
+        };
+        self.closed_trades
+            .iter()
+            .enumerate()
+            .filter(|(_, trade)| {
+                trade.date == date
+                    && trade.security_code == security_code.trim()
+                    && trade
+                        .account
+                        .as_ref()
+                        .is_none_or(|account| transaction.account.as_ref() == Some(account))
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// 対応する売却があれば保有期間・取得価額・年率のリターンを付け加える
+    ///
+    /// 売却額は明細の`proceeds`を優先し、なければ約定履歴から計算する
+    fn apply(transaction: &mut Transaction, trade: Option<&ClosedTrade>) {
+        let trade = trade.filter(|trade| trade.is_matched());
+        let proceeds = transaction
+            .field("proceeds")
+            .and_then(|proceeds| f64::parse(proceeds).ok());
+        let amount = |value: f64| Some((value.round() as i64).to_string());
+
+        let values = match trade {
+            Some(trade) => {
+                let proceeds = proceeds.unwrap_or(trade.proceeds);
+                let fifo_profit_and_loss = proceeds - trade.fifo_cost;
+                let holding_days = trade.holding_days.unwrap_or_default().round();
+                [
+                    trade.acquisition_date.map(|date| date.to_string()),
+                    Some(holding_days.to_string()),
+                    amount(trade.fifo_cost),
+                    amount(fifo_profit_and_loss),
+                    amount(trade.average_cost),
+                    amount(proceeds - trade.average_cost),
+                    annualized_return(fifo_profit_and_loss, trade.fifo_cost, holding_days)
+                        .map(|rate| ((rate * 10000.0).round() / 10000.0).to_string()),
+                ]
+            }
+            None => Default::default(),
+        };
+        transaction.holding_days =
+            trade.and_then(|trade| trade.holding_days.map(|days| days.round() as i64));
+        for ((name, _, _), value) in LOT_FIELDS.iter().zip(values) {
+            transaction.set_field(name, value);
+        }
+    }
+}
+
+/// 保有日数で割り戻した年率のリターン。保有日数が0日か取得価額がなければ`None`
+fn annualized_return(profit_and_loss: f64, cost: f64, holding_days: f64) -> Option<f64> {
+    if holding_days <= 0.0 || cost <= 0.0 {
+        return None;
+    }
+    let growth = 1.0 + profit_and_loss / cost;
+    Some(if growth <= 0.0 {
+        -1.0
+    } else {
+        growth.powf(365.0 / holding_days) - 1.0
+    })
+}
+
+impl PipelineHook for LotMatcher {
+    fn fields(&self) -> Vec<FieldSpec> {
+        LOT_FIELDS
+            .iter()
+            .map(|(name, field_type, label)| FieldSpec::new(*name, *field_type).label(*label))
+            .collect()
+    }
+
+    fn enrich(&self, transaction: &mut Transaction) -> Result<()> {
+        self.enrich_all(std::slice::from_mut(transaction))
+    }
+
+    /// 売却を取引に1件ずつ対応付けてから付け加える
+    fn enrich_all(&self, transactions: &mut [Transaction]) -> Result<()> {
+        let trades: Vec<Option<ClosedTrade>> = self
+            .assign(transactions.iter())
+            .into_iter()
+            .map(|trade| trade.cloned())
+            .collect();
+        for (transaction, trade) in transactions.iter_mut().zip(trades) {
+            Self::apply(transaction, trade.as_ref());
+        }
+        Ok(())
+    }
+
+    /// 約定履歴にない売却と、買付のロットが足りない売却を警告する
+    fn warnings(&self, report: &dyn Report) -> Vec<String> {
+        let mut missing = 0;
+        let mut unmatched = 0;
+        for trade in self.assign(report.transactions()) {
+            match trade {
+                None => missing += 1,
+                Some(trade) if !trade.is_matched() => unmatched += 1,
+                Some(_) => {}
+            }
+        }
+        let mut warnings = Vec::new();
+        if missing > 0 {
+            warnings.push(format!(
+                "{missing} sell(s) not found in the execution history."
+            ));
+        }
+        if unmatched > 0 {
+            warnings.push(format!(
+                "{unmatched} sell(s) exceed the buy lots in the execution history."
+            ));
+        }
+        warnings
+    }
+
+    /// `holding_period_sheet_titles`にレポートのシート名があれば、保有期間別のシートを書き込む
+    fn write(&self, excel_accessor: &mut ExcelAccessor, report: &dyn Report) -> Result<()> {
+        let settings = &report.context().settings;
+        if let Some(sheet_title) = settings.holding_period_sheet_titles.get(report.key()) {
+            let summary = report.summary();
+            SummaryWriter::new(settings, &summary, &[])
+                .write_holding_periods(excel_accessor, sheet_title)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn execution(date_text: &str, side: Side, shares: f64, price: f64, fee: f64) -> Execution {
+        Execution {
+            date: date(date_text),
+            security_code: "7203".to_string(),
+            account: None,
+            side,
+            shares,
+            price,
+            fee,
+        }
+    }
+
+    fn sell(date_text: &str, shares: &str) -> Transaction {
+        Transaction {
+            date: Some(date(date_text)),
+            security_code: Some("7203".to_string()),
+            account: Some("特定".to_string()),
+            fields: vec![("shares".to_string(), Some(shares.to_string()))],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fifo_and_moving_average_costs_differ() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-10", Side::Buy, 100.0, 1000.0, 0.0),
+            execution("2024-02-10", Side::Buy, 100.0, 1200.0, 0.0),
+            execution("2024-03-10", Side::Sell, 150.0, 1300.0, 0.0),
+        ]);
+        let trade = &matcher.closed_trades()[0];
+        assert!(trade.is_matched());
+        // 先入先出: 100株 × 1000円 + 50株 × 1200円
+        assert_eq!(trade.fifo_cost, 160000.0);
+        // 移動平均: 150株 × 1100円
+        assert_eq!(trade.average_cost, 165000.0);
+        assert_eq!(trade.proceeds, 195000.0);
+        assert_eq!(trade.acquisition_date, Some(date("2024-01-10")));
+    }
+
+    #[test]
+    fn fees_are_added_to_cost_and_deducted_from_proceeds() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-10", Side::Buy, 100.0, 1000.0, 500.0),
+            execution("2024-03-10", Side::Sell, 100.0, 1100.0, 300.0),
+        ]);
+        let trade = &matcher.closed_trades()[0];
+        assert_eq!(trade.fifo_cost, 100500.0);
+        assert_eq!(trade.average_cost, 100500.0);
+        assert_eq!(trade.proceeds, 109700.0);
+    }
+
+    #[test]
+    fn holding_days_are_weighted_by_shares() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-01", Side::Buy, 100.0, 1000.0, 0.0),
+            execution("2024-01-31", Side::Buy, 300.0, 1000.0, 0.0),
+            execution("2024-03-01", Side::Sell, 200.0, 1000.0, 0.0),
+        ]);
+        let trade = &matcher.closed_trades()[0];
+        // 100株 × 60日 + 100株 × 30日
+        assert_eq!(trade.holding_days, Some(45.0));
+    }
+
+    #[test]
+    fn sells_beyond_the_lots_are_unmatched() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-10", Side::Buy, 100.0, 1000.0, 0.0),
+            execution("2024-03-10", Side::Sell, 150.0, 1100.0, 0.0),
+            execution("2024-04-10", Side::Sell, 10.0, 1100.0, 0.0),
+        ]);
+        let trades = matcher.closed_trades();
+        assert_eq!(trades[0].unmatched_shares, 50.0);
+        assert!(!trades[0].is_matched());
+        assert_eq!(trades[1].holding_days, None);
+        assert!(!trades[1].is_matched());
+    }
+
+    #[test]
+    fn fractional_units_close_without_rounding_residue() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-10", Side::Buy, 0.1, 10000.0, 0.0),
+            execution("2024-01-11", Side::Buy, 0.2, 10000.0, 0.0),
+            execution("2024-03-10", Side::Sell, 0.3, 11000.0, 0.0),
+            execution("2024-04-10", Side::Buy, 1.0, 12000.0, 0.0),
+            execution("2024-05-10", Side::Sell, 1.0, 12000.0, 0.0),
+        ]);
+        let trades = matcher.closed_trades();
+        assert!(trades[0].is_matched());
+        // 最初のロットの端数が残っていれば、2回目の売却の取得日がずれる
+        assert!(trades[1].is_matched());
+        assert_eq!(trades[1].acquisition_date, Some(date("2024-04-10")));
+        assert_eq!(trades[1].average_cost, 12000.0);
+    }
+
+    #[test]
+    fn each_sell_is_assigned_to_one_transaction() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-10", Side::Buy, 300.0, 1000.0, 0.0),
+            execution("2024-03-10", Side::Sell, 100.0, 1100.0, 0.0),
+            execution("2024-03-10", Side::Sell, 200.0, 1200.0, 0.0),
+        ]);
+        let transactions = [
+            sell("2024-03-10", "200"),
+            sell("2024-03-10", "100"),
+            sell("2024-03-10", "100"),
+        ];
+        let trades = matcher.assign(&transactions);
+        assert_eq!(trades[0].map(|trade| trade.shares), Some(200.0));
+        assert_eq!(trades[1].map(|trade| trade.shares), Some(100.0));
+        // 同じ売却を2件の取引に対応付けない
+        assert!(trades[2].is_none());
+    }
+
+    #[test]
+    fn same_shares_are_preferred_before_order() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-10", Side::Buy, 300.0, 1000.0, 0.0),
+            execution("2024-03-10", Side::Sell, 100.0, 1100.0, 0.0),
+            execution("2024-03-10", Side::Sell, 200.0, 1200.0, 0.0),
+        ]);
+        // 数量の分からない取引が先にあっても、数量が一致する取引に先に対応付ける
+        let transactions = [sell("2024-03-10", ""), sell("2024-03-10", "100")];
+        let trades = matcher.assign(&transactions);
+        assert_eq!(trades[0].map(|trade| trade.shares), Some(200.0));
+        assert_eq!(trades[1].map(|trade| trade.shares), Some(100.0));
+    }
+
+    #[test]
+    fn enrich_all_adds_lot_fields() {
+        let matcher = LotMatcher::from_executions(vec![
+            execution("2024-01-01", Side::Buy, 100.0, 1000.0, 0.0),
+            execution("2024-12-31", Side::Sell, 100.0, 1100.0, 0.0),
+        ]);
+        let mut transactions = vec![sell("2024-12-31", "100"), sell("2024-12-31", "100")];
+        matcher.enrich_all(&mut transactions).unwrap();
+
+        let transaction = &transactions[0];
+        assert_eq!(transaction.holding_days, Some(365));
+        assert_eq!(transaction.field("acquisition_date"), Some("2024-01-01"));
+        assert_eq!(transaction.field("fifo_profit_and_loss"), Some("10000"));
+        assert_eq!(transaction.field("annualized_return"), Some("0.1"));
+        assert_eq!(transactions[1].holding_days, None);
+        assert_eq!(transactions[1].field("fifo_cost"), None);
+    }
+
+    #[test]
+    fn annualized_return_scales_by_holding_days() {
+        let rate = |profit_and_loss, cost, days| annualized_return(profit_and_loss, cost, days);
+        assert!((rate(10000.0, 100000.0, 365.0).unwrap() - 0.1).abs() < 1e-9);
+        // 半年で10% → 年率21%
+        assert!((rate(10000.0, 100000.0, 182.5).unwrap() - 0.21).abs() < 1e-9);
+        assert!((rate(-10000.0, 100000.0, 730.0).unwrap() - (0.9f64.sqrt() - 1.0)).abs() < 1e-9);
+        // 全額を失えば-100%
+        assert_eq!(rate(-150000.0, 100000.0, 30.0), Some(-1.0));
+        assert_eq!(rate(10000.0, 100000.0, 0.0), None);
+        assert_eq!(rate(10000.0, 0.0, 30.0), None);
+    }
+}
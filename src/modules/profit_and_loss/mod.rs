@@ -1,2 +1,2 @@
-pub mod lib;
+#[allow(clippy::module_inception)]
 pub mod profit_and_loss;
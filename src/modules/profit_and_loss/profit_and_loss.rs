@@ -1,158 +1,123 @@
-use super::super::settings::SETTINGS;
+use crate::error::Error as ConvertError;
+use crate::error::Result;
+use crate::modules::{
+    report::record::{report_record, FieldSpec},
+    settings::Settings,
+    summary::lib::SummaryChart,
+    tax,
+    template_pattern::Fields,
+};
 use chrono::NaiveDate;
-use csv::StringRecord;
-use std::error::Error;
 
-#[derive(Debug, Clone)]
-pub struct ProfitAndLoss {
-    pub trade_date: Option<NaiveDate>,               // 約定日
-    pub settlement_date: Option<NaiveDate>,          // 受渡日
-    pub security_code: Option<String>,               // 銘柄コード
-    pub security_name: Option<String>,               // 銘柄名
-    pub account: Option<String>,                     // 口座
-    pub shares: Option<i32>,                         // 数量[株]
-    pub asked_price: Option<f64>,                    // 売却/決済単価[円]
-    pub proceeds: Option<i32>,                       // 売却/決済額[円]
-    pub purchase_price: Option<f64>,                 // 平均取得価額[円]
-    pub realized_profit_and_loss: Option<i32>,       // 実現損益[円]
-    pub total_realized_profit_and_loss: Option<i32>, // 合計実現損益[円]
-    pub withholding_tax: Option<u32>,                // 源泉徴収税額
-    pub profit_and_loss: Option<i32>,                // 損益
+report_record! {
+    pub struct ProfitAndLoss {
+        trade_date: NaiveDate => column(0).label("約定日"),
+        settlement_date: NaiveDate => column(1).label("受渡日"),
+        security_code: String => column(2).label("銘柄コード"),
+        security_name: String => column(3).label("銘柄名"),
+        account: String => column(4).label("口座"),
+        shares: i32 => column(7).label("数量[株]"),
+        asked_price: f64 =>
+            column(8).label("売却/決済単価").format("yen_decimal"),   // 売却/決済単価[円]
+        proceeds: i32 =>
+            column(9).label("売却/決済額").format("yen").negative_font(), // 売却/決済額[円]
+        purchase_price: f64 =>
+            column(10).label("平均取得価額").format("yen_decimal"),   // 平均取得価額[円]
+        realized_profit_and_loss: i32 =>
+            column(11).label("実現損益").format("yen").negative_font().data_bar(), // 実現損益[円]
+        total_realized_profit_and_loss: i32 =>
+            label("合計実現損益").format("yen").negative_font().data_bar(),
+        withholding_tax: u32 => label("源泉徴収税額").format("yen"),
+        profit_and_loss: i32 => label("損益").format("yen").negative_font().data_bar(),
+    }
+
+    impl ReportModel {
+        const KEY: &'static str = "profit_and_loss";
+        const CHARTS: &'static [SummaryChart] =
+            &[SummaryChart::CumulativeLine, SummaryChart::SecurityPie];
+    }
+
+    impl ReportRecord {
+        fn date(&self) -> Option<NaiveDate> {
+            self.trade_date
+        }
+
+        fn security_code(&self) -> Option<&str> {
+            self.security_code.as_deref()
+        }
+
+        fn security_name(&self) -> Option<&str> {
+            self.security_name.as_deref()
+        }
+
+        fn account(&self) -> Option<&str> {
+            self.account.as_deref()
+        }
+
+        fn amount(&self) -> Option<i64> {
+            self.realized_profit_and_loss.map(i64::from)
+        }
+
+        /// 合計実現損益・源泉徴収税額・税引後の損益を返す
+        fn total(
+            records: &[&Self],
+            _fields: &[FieldSpec],
+            settings: &Settings,
+        ) -> Result<Fields> {
+            let total = Self::get_total(records);
+            Ok(Self::new_total_realized_profit_and_loss(total, settings.tax_rate)?.get_all_fields())
+        }
+    }
 }
 
 impl ProfitAndLoss {
-    pub fn new() -> Result<Self, &'static str> {
-        Ok(ProfitAndLoss {
-            trade_date: None,
-            settlement_date: None,
-            security_code: None,
-            security_name: None,
-            account: None,
-            shares: None,
-            asked_price: None,
-            proceeds: None,
-            purchase_price: None,
-            realized_profit_and_loss: None,
-            total_realized_profit_and_loss: None,
-            withholding_tax: None,
-            profit_and_loss: None,
-        })
-    }
+    /// 特定口座とそれ以外の口座の実現損益の合計を返す。桁あふれしないよう`i64`で合計する
+    pub fn get_total(profit_and_loss_list: &[&Self]) -> (i64, i64) {
+        let mut specific_account_total = 0;
+        let mut nisa_account_total = 0;
 
-    pub fn from_record(record: StringRecord) -> Result<Self, Box<dyn Error>> {
-        Ok(ProfitAndLoss {
-            trade_date: Self::parse_date(record.get(0))?,
-            settlement_date: Self::parse_date(record.get(1))?,
-            security_code: Self::parse_string(record.get(2))?,
-            security_name: Self::parse_string(record.get(3))?,
-            account: Self::parse_string(record.get(4))?,
-            shares: Self::parse_int(record.get(7))?,
-            asked_price: Self::parse_float(record.get(8))?,
-            proceeds: Self::parse_int(record.get(9))?,
-            purchase_price: Self::parse_float(record.get(10))?,
-            realized_profit_and_loss: Self::parse_int(record.get(11))?,
-            total_realized_profit_and_loss: None,
-            withholding_tax: None,
-            profit_and_loss: None,
-        })
+        for profit_and_loss in profit_and_loss_list {
+            if let (Some(account), Some(realized_profit_and_loss)) = (
+                profit_and_loss.account.as_deref(),
+                profit_and_loss.realized_profit_and_loss,
+            ) {
+                if tax::is_taxable_account(account) {
+                    specific_account_total += realized_profit_and_loss as i64;
+                } else {
+                    nisa_account_total += realized_profit_and_loss as i64;
+                }
+            }
+        }
+
+        (specific_account_total, nisa_account_total)
     }
 
-    pub fn get_all_fields(&self) -> Vec<(String, Option<String>)> {
-        vec![
-            (
-                "trade_date".to_string(),
-                self.trade_date.map(|d| d.to_string()),
-            ),
-            (
-                "settlement_date".to_string(),
-                self.settlement_date.map(|d| d.to_string()),
-            ),
-            ("security_code".to_string(), self.security_code.clone()),
-            ("security_name".to_string(), self.security_name.clone()),
-            ("account".to_string(), self.account.clone()),
-            ("shares".to_string(), self.shares.map(|s| s.to_string())),
-            (
-                "asked_price".to_string(),
-                self.asked_price.map(|p| p.to_string()),
-            ),
-            ("proceeds".to_string(), self.proceeds.map(|p| p.to_string())),
-            (
-                "purchase_price".to_string(),
-                self.purchase_price.map(|p| p.to_string()),
-            ),
-            (
-                "realized_profit_and_loss".to_string(),
-                self.realized_profit_and_loss.map(|p| p.to_string()),
-            ),
-            (
-                "total_realized_profit_and_loss".to_string(),
-                self.total_realized_profit_and_loss.map(|p| p.to_string()),
-            ),
-            (
-                "withholding_tax".to_string(),
-                self.withholding_tax.map(|p| p.to_string()),
-            ),
-            (
-                "profit_and_loss".to_string(),
-                self.profit_and_loss.map(|p| p.to_string()),
-            ),
-        ]
+    /// 特定口座の実現損益に対する源泉徴収税額を返す。損失の場合は0
+    pub fn withholding_tax(specific_account_total: i32, tax_rate: f64) -> u32 {
+        tax::withholding_tax(specific_account_total as i64, tax_rate) as u32
     }
 
+    /// 合計の行を返す。税率が範囲外か、合計が`i32`に収まらない場合はエラー
     pub fn new_total_realized_profit_and_loss(
-        (specific_account_total, nisa_account_total): (i32, i32),
-    ) -> Result<Self, Box<dyn Error>> {
-        let withholding_tax = if specific_account_total < 0 {
-            0
-        } else {
-            (specific_account_total as f64 * SETTINGS.tax_rate) as u32
+        (specific_account_total, nisa_account_total): (i64, i64),
+        tax_rate: f64,
+    ) -> Result<Self> {
+        tax::check_tax_rate(tax_rate)?;
+        let to_i32 = |amount: i64| {
+            i32::try_from(amount).map_err(|_| {
+                ConvertError::Tax(format!("total {amount} is out of range of the report."))
+            })
         };
-        let total = specific_account_total + nisa_account_total;
+        let specific_account_total = to_i32(specific_account_total)?;
+        let total = to_i32(specific_account_total as i64 + nisa_account_total)?;
+        let withholding_tax = Self::withholding_tax(specific_account_total, tax_rate);
+        let profit_and_loss = to_i32(total as i64 - withholding_tax as i64)?;
 
         Ok(ProfitAndLoss {
-            trade_date: None,
-            settlement_date: None,
-            security_code: None,
-            security_name: None,
-            account: None,
-            shares: None,
-            asked_price: None,
-            proceeds: None,
-            purchase_price: None,
-            realized_profit_and_loss: None,
             total_realized_profit_and_loss: Some(total),
             withholding_tax: Some(withholding_tax),
-            profit_and_loss: Some(total - withholding_tax as i32),
+            profit_and_loss: Some(profit_and_loss),
+            ..Default::default()
         })
     }
-
-    fn parse_date(date_str: Option<&str>) -> Result<Option<NaiveDate>, Box<dyn Error>> {
-        date_str.map_or(Ok(None), |s| {
-            NaiveDate::parse_from_str(&s.replace("/", "-"), "%Y-%m-%d")
-                .map(Some)
-                .map_err(|e| format!("Failed to parse date '{s}': {e}").into())
-        })
-    }
-
-    fn parse_int(num_str: Option<&str>) -> Result<Option<i32>, Box<dyn Error>> {
-        num_str.map_or(Ok(None), |s| {
-            s.replace(",", "")
-                .parse::<i32>()
-                .map(Some)
-                .map_err(|e| format!("Failed to parse integer '{s}': {e}").into())
-        })
-    }
-
-    fn parse_float(num_str: Option<&str>) -> Result<Option<f64>, Box<dyn Error>> {
-        num_str.map_or(Ok(None), |s| {
-            s.replace(",", "")
-                .parse::<f64>()
-                .map(Some)
-                .map_err(|e| format!("Failed to parse float '{s}': {e}").into())
-        })
-    }
-
-    fn parse_string(value: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
-        Ok(value.map(|s| s.to_string()))
-    }
 }
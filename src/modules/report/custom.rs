@@ -0,0 +1,204 @@
+use super::record::{parse_field, FieldRole, FieldSpec, FieldType, FieldValue, ReportRecord};
+use crate::error::Result;
+use crate::modules::template_pattern::Fields;
+use chrono::NaiveDate;
+use csv::StringRecord;
+
+/// 設定ファイルで定義したレポートの1行分
+///
+/// 値は型に従って解釈した後の文字列で持ち、役割のあるフィールドだけを別に保持する
+#[derive(Debug, Clone, Default)]
+pub struct CustomRecord {
+    fields: Fields,
+    date: Option<NaiveDate>,
+    security_code: Option<String>,
+    security_name: Option<String>,
+    account: Option<String>,
+    amount: Option<i64>,
+}
+
+impl CustomRecord {
+    /// 値のある列。空欄は値なしとする
+    fn filled_column(record: &StringRecord, field: &FieldSpec) -> Option<usize> {
+        field.column.filter(|index| {
+            record
+                .get(*index)
+                .is_some_and(|text| !text.trim().is_empty())
+        })
+    }
+
+    /// 型に従って解釈し、正規化した文字列を返す。空欄は文字列以外では値なしとする
+    fn parse_value(record: &StringRecord, field: &FieldSpec) -> Result<Option<String>> {
+        let column = match field.field_type {
+            FieldType::Text => field.column,
+            _ => Self::filled_column(record, field),
+        };
+        let name = field.name.as_str();
+        Ok(match field.field_type {
+            FieldType::Date => parse_field::<NaiveDate>(record, column, name)?.map(|v| v.to_text()),
+            FieldType::Int => parse_field::<i64>(record, column, name)?.map(|v| v.to_text()),
+            FieldType::Float => parse_field::<f64>(record, column, name)?.map(|v| v.to_text()),
+            FieldType::Text => parse_field::<String>(record, column, name)?,
+        })
+    }
+}
+
+impl ReportRecord for CustomRecord {
+    fn parse(record: StringRecord, fields: &[FieldSpec]) -> Result<Self> {
+        let mut custom_record = CustomRecord::default();
+        for field in fields {
+            let value = Self::parse_value(&record, field)?;
+            match (field.role, value.as_deref()) {
+                // 日付と金額の役割は型によらず解釈し、解釈できなければエラーにする
+                (Some(FieldRole::Date), _) => {
+                    let column = Self::filled_column(&record, field);
+                    custom_record.date = parse_field(&record, column, &field.name)?;
+                }
+                (Some(FieldRole::SecurityCode), value) => {
+                    custom_record.security_code = value.map(str::to_string);
+                }
+                (Some(FieldRole::SecurityName), value) => {
+                    custom_record.security_name = value.map(str::to_string);
+                }
+                (Some(FieldRole::Account), value) => {
+                    custom_record.account = value.map(str::to_string);
+                }
+                (Some(FieldRole::Amount), _) => {
+                    let column = Self::filled_column(&record, field);
+                    custom_record.amount =
+                        parse_field::<f64>(&record, column, &field.name)?.map(|v| v.round() as i64);
+                }
+                _ => {}
+            }
+            custom_record.fields.push((field.name.clone(), value));
+        }
+        Ok(custom_record)
+    }
+
+    fn get_all_fields(&self) -> Fields {
+        self.fields.clone()
+    }
+
+    fn date(&self) -> Option<NaiveDate> {
+        self.date
+    }
+
+    fn security_code(&self) -> Option<&str> {
+        self.security_code.as_deref()
+    }
+
+    fn security_name(&self) -> Option<&str> {
+        self.security_name.as_deref()
+    }
+
+    fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    fn amount(&self) -> Option<i64> {
+        self.amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{Converter, ReportKind};
+    use crate::error::ErrorKind;
+    use crate::modules::{context::Context, report::definition::ReportSetting, settings::Settings};
+    use std::path::Path;
+
+    const REPORT: &str = r#"{
+        "prefix": "fx_",
+        "sheet_title": "FX取引",
+        "fields": [
+            {"name": "trade_date", "type": "date", "column": 0, "label": "約定日", "role": "date"},
+            {"name": "pair", "column": 1, "label": "通貨ペア", "role": "security_code"},
+            {"name": "profit", "type": "int", "column": 2, "label": "損益", "role": "amount",
+             "aggregate": {"sum": "profit"}}
+        ]
+    }"#;
+
+    fn fields() -> Vec<FieldSpec> {
+        serde_json::from_str::<ReportSetting>(REPORT)
+            .unwrap()
+            .fields
+    }
+
+    #[test]
+    fn values_are_parsed_by_type_and_role() {
+        let record = StringRecord::from(vec!["2024/01/10", "USD/JPY", "-1,500"]);
+        let custom_record = CustomRecord::parse(record, &fields()).unwrap();
+        assert_eq!(custom_record.date(), NaiveDate::from_ymd_opt(2024, 1, 10));
+        assert_eq!(custom_record.security_code(), Some("USD/JPY"));
+        assert_eq!(custom_record.amount(), Some(-1500));
+
+        // 空欄の日付・金額は値なし
+        let record = StringRecord::from(vec!["", "USD/JPY", " "]);
+        let custom_record = CustomRecord::parse(record, &fields()).unwrap();
+        assert_eq!(custom_record.date(), None);
+        assert_eq!(custom_record.amount(), None);
+    }
+
+    #[test]
+    fn invalid_dates_and_amounts_are_errors_with_the_field_name() {
+        // 文字列の型でも、日付・金額の役割があれば解釈する
+        let mut fields = fields();
+        for field in &mut fields {
+            field.field_type = FieldType::Text;
+        }
+        let error = CustomRecord::parse(
+            StringRecord::from(vec!["2024/13/01", "USD/JPY", "100"]),
+            &fields,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Parse);
+        assert!(
+            error.to_string().contains("trade_date '2024/13/01'"),
+            "{error}"
+        );
+
+        let error = CustomRecord::parse(
+            StringRecord::from(vec!["2024/01/10", "USD/JPY", "n/a"]),
+            &fields,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("profit 'n/a'"), "{error}");
+    }
+
+    #[test]
+    fn a_report_defined_in_the_settings_is_converted() {
+        let mut settings = Settings::defaults();
+        settings
+            .reports
+            .insert("fx".to_string(), serde_json::from_str(REPORT).unwrap());
+        settings.validate().unwrap();
+        let (start_row, start_col) = (settings.start_row, settings.start_col);
+        let report = ReportKind::detect(Path::new("fx_2024.csv"), &settings).unwrap();
+        assert_eq!(report, ReportKind::Custom("fx".to_string()));
+
+        let converter = Converter::new(Context::from(settings), report);
+        let csv = "約定日,通貨ペア,損益\n2024/01/10,USD/JPY,\"1,000\"\n2024/01/20,EUR/JPY,-300\n";
+        let xlsx = converter.convert_to_vec(csv.as_bytes()).unwrap();
+        let book =
+            umya_spreadsheet::reader::xlsx::read_reader(std::io::Cursor::new(xlsx), true).unwrap();
+        let sheet = book.get_sheet_by_name("FX取引").unwrap();
+        // 見出しの行からの位置で値を読む
+        let row = |offset: u32| -> Vec<String> {
+            (start_col..start_col + 3)
+                .map(|col| sheet.get_value((col, start_row + offset)))
+                .collect()
+        };
+        assert_eq!(row(0), ["約定日", "通貨ペア", "損益"]);
+        assert_eq!(row(1)[1..], ["USD/JPY", "1000"]);
+        assert_eq!(row(2)[1..], ["EUR/JPY", "-300"]);
+        // 日付の役割があれば月ごとの小計を入れる
+        assert_eq!(row(3)[2], "700");
+
+        let error = converter
+            .convert_to_vec("約定日,通貨ペア,損益\n2024/01/10,USD/JPY,abc\n".as_bytes())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Parse);
+        assert!(error.to_string().contains("line 2"), "{error}");
+    }
+}
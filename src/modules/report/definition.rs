@@ -0,0 +1,148 @@
+use super::record::FieldSpec;
+use crate::error::{Error as ConvertError, Result};
+use crate::modules::{
+    grouping::GroupBy, settings::Settings, summary::lib::SummaryChart, template_pattern::Fields,
+};
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+
+/// 設定ファイルの`reports`で定義するレポート
+///
+/// モデルのないCSVを、列の対応・型・グループ化・集計・書式の指定だけで変換する
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSetting {
+    pub prefix: String, // CSVファイル名の接頭辞
+    #[serde(default)]
+    pub sheet_title: Option<String>, // 明細のシート名。`sheet_titles`の指定が優先される
+    #[serde(default)]
+    pub grouping: Vec<GroupBy>, // グループ化の単位。`grouping`の指定が優先される
+    #[serde(default)]
+    pub charts: Vec<SummaryChart>, // サマリーのシートに描くグラフ
+    pub fields: Vec<FieldSpec>, // フィールドの定義。並びは列の既定の並び
+}
+
+/// `ReportManager`で書き込むレポートの定義
+#[derive(Debug, Clone)]
+pub struct ReportDefinition {
+    pub key: String,                 // 設定ファイルでレポートを指定するキー
+    pub fields: Vec<FieldSpec>,      // フィールドの定義
+    pub charts: Vec<SummaryChart>,   // サマリーのシートに描くグラフ
+    pub sheet_title: Option<String>, // 明細のシート名。省略時は`sheet_title`
+    pub csv_headers: Vec<String>,    // CSVの列名。空でなければ列名で列を対応付ける
+}
+
+impl ReportDefinition {
+    pub fn from_setting(key: &str, setting: &ReportSetting) -> Self {
+        ReportDefinition {
+            key: key.to_string(),
+            fields: setting.fields.clone(),
+            charts: setting.charts.clone(),
+            sheet_title: setting.sheet_title.clone(),
+            csv_headers: Vec::new(),
+        }
+    }
+
+    /// `csv_headers`の順に列を並べ替える。列名の指定がなければそのまま返す
+    ///
+    /// 末尾の列は省略できる。省略した列より後の列があればエラーにする
+    pub fn arrange(
+        &self,
+        headers: &StringRecord,
+        rows: Vec<StringRecord>,
+    ) -> Result<Vec<StringRecord>> {
+        if self.csv_headers.is_empty() {
+            return Ok(rows);
+        }
+        let positions: Vec<Option<usize>> = self
+            .csv_headers
+            .iter()
+            .map(|name| headers.iter().position(|header| header.trim() == name))
+            .collect();
+        let len = positions
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(positions.len());
+        if positions[len..].iter().any(Option::is_some) {
+            return Err(ConvertError::Parse {
+                path: None,
+                line: Some(1),
+                field: "header".to_string(),
+                value: headers.iter().collect::<Vec<_>>().join(","),
+                message: format!("missing the column '{}'", self.csv_headers[len]),
+            });
+        }
+        let positions: Vec<usize> = positions.into_iter().flatten().collect();
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let mut arranged: StringRecord = positions
+                    .iter()
+                    .map(|index| row.get(*index).unwrap_or_default())
+                    .collect();
+                arranged.set_position(row.position().cloned());
+                arranged
+            })
+            .collect())
+    }
+
+    /// 明細のシート名。`sheet_titles`、レポートの定義、`sheet_title`の順に決める
+    pub fn sheet_title<'a>(&'a self, settings: &'a Settings) -> &'a str {
+        settings
+            .sheet_titles
+            .get(&self.key)
+            .or(self.sheet_title.as_ref())
+            .unwrap_or(&settings.sheet_title)
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldSpec> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// 全フィールドの名前。値はすべて`None`
+    pub fn blank_fields(&self) -> Fields {
+        self.fields
+            .iter()
+            .map(|field| (field.name.clone(), None))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(sheet_title: Option<&str>) -> ReportSetting {
+        ReportSetting {
+            prefix: "fx_".to_string(),
+            sheet_title: sheet_title.map(str::to_string),
+            grouping: Vec::new(),
+            charts: Vec::new(),
+            fields: vec![serde_json::from_str(r#"{"name": "pair", "column": 0}"#).unwrap()],
+        }
+    }
+
+    #[test]
+    fn the_sheet_title_prefers_sheet_titles_then_the_definition() {
+        let mut settings = Settings::defaults();
+        let definition = ReportDefinition::from_setting("fx", &setting(None));
+        assert_eq!(definition.sheet_title(&settings), settings.sheet_title);
+
+        let definition = ReportDefinition::from_setting("fx", &setting(Some("FX取引")));
+        assert_eq!(definition.sheet_title(&settings), "FX取引");
+
+        settings
+            .sheet_titles
+            .insert("fx".to_string(), "為替".to_string());
+        assert_eq!(definition.sheet_title(&settings), "為替");
+    }
+
+    #[test]
+    fn rows_are_kept_as_is_without_csv_headers() {
+        let definition = ReportDefinition::from_setting("fx", &setting(None));
+        let headers = StringRecord::from(vec!["b", "a"]);
+        let rows = vec![StringRecord::from(vec!["2", "1"])];
+        let arranged = definition.arrange(&headers, rows.clone()).unwrap();
+        assert_eq!(arranged, rows);
+        assert_eq!(definition.blank_fields(), vec![("pair".to_string(), None)]);
+    }
+}
@@ -0,0 +1,592 @@
+use super::{
+    definition::ReportDefinition,
+    pipeline::{Hooks, Normalized, PipelineHook, Transaction},
+    record::{FieldSpec, ReportRecord},
+};
+use crate::error::{Error, Result};
+use crate::modules::{
+    columns::{ColumnSelector, ColumnSetting},
+    context::Context,
+    excel::{
+        cell_style::CellStyle,
+        coordinate::Coordinate,
+        lib::ExcelAccessor,
+        template::{ExcelTemplate, TemplateRow},
+    },
+    grouping::{self, GroupBy, GroupKey, GroupRow},
+    summary::lib::SummaryWriter,
+    template_pattern::{Fields, Report, TemplateManager, TemplateStruct},
+};
+use csv::StringRecord;
+use std::{collections::BTreeMap, marker::PhantomData, path::Path, sync::Arc};
+
+/// レポートの定義に従って明細をグループ化し、ヘッダー・明細・小計・サマリーを書き込む
+///
+/// CSVの行 → 型付きのレコード → 正規化した取引 → グループ化したレポート → 出力の
+/// 各段階を`parse`・`normalize`・`group`・`Report::render`で個別に実行できる
+pub struct ReportManager<T> {
+    context: Context,
+    template_struct: TemplateStruct,
+    definition: ReportDefinition,
+    hooks: Hooks,
+    record: PhantomData<fn() -> T>,
+}
+
+/// `ReportManager::group`でグループ化したレポート
+pub struct GroupedReport<'a, T> {
+    manager: &'a ReportManager<T>,
+    groups: BTreeMap<Vec<GroupKey>, Vec<Normalized<T>>>,
+}
+
+impl<T> GroupedReport<'_, T> {
+    /// グループのキーと、グループに含まれるレコード・取引
+    pub fn groups(&self) -> &BTreeMap<Vec<GroupKey>, Vec<Normalized<T>>> {
+        &self.groups
+    }
+}
+
+impl<T: ReportRecord> ReportManager<T> {
+    pub fn new(
+        context: Context,
+        template_struct: TemplateStruct,
+        definition: ReportDefinition,
+    ) -> Self {
+        ReportManager {
+            context,
+            template_struct,
+            definition,
+            hooks: Vec::new(),
+            record: PhantomData,
+        }
+    }
+
+    /// 段階の間に処理を挟むフックを追加する
+    pub fn with_hook(mut self, hook: Arc<dyn PipelineHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks.extend(hooks);
+        self
+    }
+
+    /// CSVの行を型付きのレコードに解釈する
+    pub fn parse(&self, mut rows: Vec<StringRecord>) -> Result<Vec<T>> {
+        for hook in &self.hooks {
+            hook.rows(&mut rows)?;
+        }
+        let mut records = rows
+            .into_iter()
+            .map(|row| T::parse(row, &self.definition.fields))
+            .collect::<Result<Vec<_>, _>>()?;
+        T::complete(&mut records, &self.context.settings)?;
+        Ok(records)
+    }
+
+    /// レコードを正規化し、フックで情報を付け加えて絞り込む
+    pub fn normalize(&self, records: Vec<T>) -> Result<Vec<Normalized<T>>> {
+        let mut transactions: Vec<Transaction> = records.iter().map(Transaction::new).collect();
+        for hook in &self.hooks {
+            hook.enrich_all(&mut transactions)?;
+        }
+        let mut normalized = Vec::new();
+        'records: for (record, transaction) in records.into_iter().zip(transactions) {
+            for hook in &self.hooks {
+                if !hook.keep(&transaction)? {
+                    continue 'records;
+                }
+            }
+            normalized.push(Normalized {
+                record,
+                transaction,
+            });
+        }
+        Ok(normalized)
+    }
+
+    /// グループ化の単位でまとめる。グループのキーを決められない取引は除く
+    pub fn group(&self, normalized: Vec<Normalized<T>>) -> GroupedReport<'_, T> {
+        let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for item in normalized {
+            let transaction = &item.transaction;
+            if let Some(keys) = GroupBy::keys(
+                &self.template_struct.grouping,
+                transaction.date,
+                transaction.security_code.as_deref(),
+                transaction.account.as_deref(),
+            ) {
+                groups.entry(keys).or_default().push(item);
+            }
+        }
+        GroupedReport {
+            manager: self,
+            groups,
+        }
+    }
+
+    fn field(&self, field_name: &str) -> Option<&FieldSpec> {
+        self.definition.field(field_name)
+    }
+
+    /// 列の設定。指定がなければ全フィールドを出力し、ラベルがなければ定義のラベルを使う
+    fn column_settings(&self) -> Vec<ColumnSetting> {
+        let columns = if self.template_struct.columns.is_empty() {
+            self.definition
+                .fields
+                .iter()
+                .map(|field| ColumnSetting {
+                    field: field.name.clone(),
+                    ..Default::default()
+                })
+                .collect()
+        } else {
+            self.template_struct.columns.clone()
+        };
+        columns
+            .into_iter()
+            .map(|mut column| {
+                if column.label.is_none() {
+                    column.label = self
+                        .field(&column.field)
+                        .and_then(|field| field.label.clone());
+                }
+                column
+            })
+            .collect()
+    }
+
+    fn write_header(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+        columns: &ColumnSelector,
+    ) -> Result<()> {
+        for (col_index, (_, value)) in columns.header().iter().enumerate() {
+            let col_index = col_index as u32 + self.context.settings.start_col;
+            let background_color = self.context.settings.colors.get("header_background");
+            let coordinate_item = (col_index, *row_index).new_coordinate();
+            excel_accessor.write_cell(
+                coordinate_item,
+                value,
+                &CellStyle::new(background_color, None, None),
+            );
+        }
+        *row_index += 1;
+        Ok(())
+    }
+
+    /// 明細または小計の1行を書き込む
+    fn write_row(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+        fields: &Fields,
+        background_color: Option<&String>,
+        columns: &ColumnSelector,
+    ) -> Result<()> {
+        for (col_index, (field_name, value)) in fields.iter().enumerate() {
+            let mut cell_style = self.get_cell_style(field_name, background_color);
+            columns.apply_format(col_index, &mut cell_style);
+            let coordinate_item = (
+                col_index as u32 + self.context.settings.start_col,
+                *row_index,
+            )
+                .new_coordinate();
+            excel_accessor.write_cell(coordinate_item, value, &cell_style);
+        }
+
+        *row_index += 1;
+
+        Ok(())
+    }
+
+    /// フィールドの定義の表示形式で書式を返す
+    fn get_cell_style(&self, field_name: &str, background_color: Option<&String>) -> CellStyle {
+        let font_format = self
+            .field(field_name)
+            .and_then(|field| field.format.as_ref())
+            .map(|format| self.context.settings.formats.get(format).unwrap_or(format));
+        CellStyle::new(background_color, font_format, None)
+    }
+
+    /// 損失の色付けとデータバーを条件付き書式で設定する
+    ///
+    /// 損失の色付けは小計・合計を含む`last_row`まで、データバーは最後の明細の`last_record_row`までにする
+    fn write_conditional_formats(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        (last_record_row, last_row): (u32, u32),
+        columns: &ColumnSelector,
+    ) -> Result<()> {
+        let header_list = columns.header();
+        let realized_loss_font_color = self.context.settings.colors.get("realized_loss_font");
+        let data_bar_color = self.context.settings.colors.get("data_bar");
+
+        for (col_index, (field_name, _)) in header_list.iter().enumerate() {
+            let Some(field) = self.field(field_name) else {
+                continue;
+            };
+            let col_index = col_index as u32 + self.context.settings.start_col;
+            let first_row = self.context.settings.start_row + 1;
+            let range = |last_row: u32| {
+                (
+                    (col_index, first_row).new_coordinate(),
+                    (col_index, last_row).new_coordinate(),
+                )
+            };
+
+            if let (true, Some(color)) = (field.negative_font, realized_loss_font_color) {
+                excel_accessor.add_negative_font_rule(range(last_row), color);
+            }
+            if let (true, true, Some(color), true) = (
+                field.data_bar,
+                self.context.settings.data_bars,
+                data_bar_color,
+                last_record_row >= first_row,
+            ) {
+                excel_accessor.add_data_bar(range(last_record_row), color);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 小計の行を返す。グループが複数階層の場合は先頭の列にグループ名を入れる
+    fn get_footer_fields(
+        &self,
+        label: String,
+        normalized: &[&Normalized<T>],
+        columns: &ColumnSelector,
+    ) -> Result<Fields> {
+        let records: Vec<&T> = normalized.iter().map(|item| &item.record).collect();
+        let mut footer_fields = columns.select_footer(&T::total(
+            &records,
+            &self.definition.fields,
+            &self.context.settings,
+        )?);
+        if self.template_struct.grouping.len() > 1 {
+            if let Some((_, value @ None)) = footer_fields.first_mut() {
+                *value = Some(label);
+            }
+        }
+        Ok(footer_fields)
+    }
+
+    fn write_sheet(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        report: &GroupedReport<T>,
+        columns: &ColumnSelector,
+    ) -> Result<()> {
+        // ヘッダー書き込み
+        let mut row_index = self.context.settings.start_row;
+        self.write_header(excel_accessor, &mut row_index, columns)?;
+
+        let footer_background = self.context.settings.colors.get("footer_background");
+        // 最後の明細の行。末尾の小計・合計の行はオートフィルタとデータバーの範囲に含めない
+        let mut last_record_row = self.context.settings.start_row;
+        for row in grouping::flatten(&report.groups) {
+            match row {
+                // 明細書き込み
+                GroupRow::Records(records) => {
+                    for record in records {
+                        let fields = columns.select(&record.transaction.fields);
+                        self.write_row(excel_accessor, &mut row_index, &fields, None, columns)?;
+                    }
+                    last_record_row = row_index - 1;
+                }
+                GroupRow::Footer(label, records) => {
+                    let footer_fields = self.get_footer_fields(label, &records, columns)?;
+                    self.write_row(
+                        excel_accessor,
+                        &mut row_index,
+                        &footer_fields,
+                        footer_background,
+                        columns,
+                    )?
+                }
+            }
+        }
+
+        let len = columns.len() as u32;
+        excel_accessor.adjust_column_widths(len)?;
+        for (offset, width) in columns.widths() {
+            excel_accessor.set_column_width(self.context.settings.start_col + offset, width);
+        }
+
+        // オートフィルタ、ウィンドウ枠の固定、条件付き書式
+        let last_row = row_index - 1;
+        excel_accessor.set_auto_filter((
+            (
+                self.context.settings.start_col,
+                self.context.settings.start_row,
+            )
+                .new_coordinate(),
+            (self.context.settings.start_col + len - 1, last_record_row).new_coordinate(),
+        ));
+        excel_accessor.freeze_rows(self.context.settings.start_row);
+        self.write_conditional_formats(excel_accessor, (last_record_row, last_row), columns)?;
+
+        Ok(())
+    }
+
+    /// テンプレートの行の書式を複製して書き込む
+    fn write_template(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        report: &GroupedReport<T>,
+        template_filepath: &Path,
+        columns: &ColumnSelector,
+    ) -> Result<()> {
+        let template = ExcelTemplate::read(template_filepath, self.sheet_title())?;
+
+        let mut rows = Vec::new();
+        for row in grouping::flatten(&report.groups) {
+            match row {
+                GroupRow::Records(records) => {
+                    for record in records {
+                        // 派生列もプレースホルダーで参照できるようにする
+                        let mut fields = record.transaction.fields.clone();
+                        fields.extend(columns.select(&fields));
+                        rows.push(TemplateRow::Record(fields));
+                    }
+                }
+                GroupRow::Footer(label, records) => {
+                    let footer_fields = self.get_footer_fields(label, &records, columns)?;
+                    rows.push(TemplateRow::Footer(footer_fields));
+                }
+            }
+        }
+
+        excel_accessor.add_worksheet(template.render(self.sheet_title(), columns, &rows))?;
+        Ok(())
+    }
+
+    /// フックの設定をフィールドの定義と照らし合わせる
+    fn check(&self) -> Result<()> {
+        let all_fields = self.all_fields();
+        self.hooks
+            .iter()
+            .try_for_each(|hook| hook.check(&all_fields))
+    }
+
+    /// レポートのフィールドと、フックで付け加えるフィールドの定義
+    fn all_fields(&self) -> Vec<FieldSpec> {
+        let mut all_fields = self.definition.fields.clone();
+        for hook in &self.hooks {
+            all_fields.extend(hook.fields());
+        }
+        all_fields
+    }
+
+    /// 明細(またはテンプレート)・サマリー・フックのシートを書き込む
+    fn render(&self, report: &GroupedReport<T>, excel_accessor: &mut ExcelAccessor) -> Result<()> {
+        // フックで付け加えるフィールドも列に選べる
+        let all_fields = self.all_fields();
+        let columns =
+            ColumnSelector::new(&self.context.settings, &self.column_settings(), &all_fields)?;
+        match &self.template_struct.template_filepath {
+            Some(template_filepath) => {
+                self.write_template(excel_accessor, report, template_filepath, &columns)?
+            }
+            None => self.write_sheet(excel_accessor, report, &columns)?,
+        }
+
+        // サマリー書き込み
+        if let Some(sheet_title) = self
+            .context
+            .settings
+            .summary_sheet_titles
+            .get(&self.definition.key)
+        {
+            let summary = report.summary();
+            SummaryWriter::new(&self.context.settings, &summary, &self.definition.charts)
+                .write(excel_accessor, sheet_title)?;
+        }
+
+        for hook in &self.hooks {
+            hook.write(excel_accessor, report)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: ReportRecord> TemplateManager for ReportManager<T> {
+    fn arrange(
+        &self,
+        headers: &StringRecord,
+        rows: Vec<StringRecord>,
+    ) -> Result<Vec<StringRecord>> {
+        self.definition.arrange(headers, rows)
+    }
+
+    fn build(&self, rows: Vec<StringRecord>) -> Result<Box<dyn Report + '_>> {
+        self.check()?;
+        let records = self.parse(rows)?;
+        let normalized = self.normalize(records)?;
+        Ok(Box::new(self.group(normalized)))
+    }
+
+    fn problems(&self, rows: &[StringRecord]) -> Result<Vec<Error>> {
+        self.check()?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                self.parse(vec![row.clone()])
+                    .and_then(|records| self.normalize(records))
+                    .err()
+            })
+            .collect())
+    }
+
+    fn context(&self) -> &Context {
+        &self.context
+    }
+
+    fn sheet_title(&self) -> &str {
+        self.definition.sheet_title(&self.context.settings)
+    }
+}
+
+impl<T: ReportRecord> Report for GroupedReport<'_, T> {
+    fn key(&self) -> &str {
+        &self.manager.definition.key
+    }
+
+    fn transactions(&self) -> Vec<&Transaction> {
+        self.groups
+            .values()
+            .flatten()
+            .map(|item| &item.transaction)
+            .collect()
+    }
+
+    fn render(&self, excel_accessor: &mut ExcelAccessor) -> Result<()> {
+        self.manager.render(self, excel_accessor)
+    }
+
+    fn context(&self) -> &Context {
+        &self.manager.context
+    }
+
+    fn sheet_title(&self) -> &str {
+        self.manager.sheet_title()
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        self.manager
+            .hooks
+            .iter()
+            .flat_map(|hook| hook.warnings(self))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::converter::{Converter, ReportKind};
+    use crate::modules::{context::Context, settings::Settings};
+    use std::fs;
+    use tempfile::TempDir;
+    use umya_spreadsheet::{reader, writer, Worksheet};
+
+    const REALIZED_PL: &str = "約定日,受渡日,銘柄コード,銘柄名,口座,信用区分,売却/決済,数量[株],売却/決済単価[円],売却/決済額[円],平均取得価額[円],実現損益[円]\n\
+         2024/01/10,2024/01/12,7203,トヨタ自動車,特定,,現物,100,\"3,000.0\",\"300,000\",\"2,900.0\",\"10,000\"\n\
+         2024/01/11,2024/01/15,6758,ソニーＧ,特定,,現物,100,\"3,000.0\",\"300,000\",\"3,100.0\",\"-10,000\"\n";
+
+    /// 見出し・ヘッダー・明細・小計・集計の行を置いたテンプレート
+    fn write_template(path: &std::path::Path, sheet_title: &str) {
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        sheet.set_name(sheet_title);
+        sheet.get_cell_mut("A1").set_value("取引一覧");
+        sheet.get_style_mut("A1").get_font_mut().set_bold(true);
+        sheet.get_cell_mut("A3").set_value("{{header.trade_date}}");
+        sheet
+            .get_cell_mut("B3")
+            .set_value("{{header.security_name}}");
+        sheet
+            .get_cell_mut("C3")
+            .set_value("{{header.realized_profit_and_loss}}");
+        sheet.get_cell_mut("A4").set_value("{{row.trade_date}}");
+        sheet.get_cell_mut("B4").set_value("{{row.security_name}}");
+        sheet
+            .get_cell_mut("C4")
+            .set_value("{{row.realized_profit_and_loss}}");
+        sheet.get_style_mut("B4").set_background_color("FFFFF2CC");
+        sheet
+            .get_row_dimension_mut(&4)
+            .set_height(24.0)
+            .set_custom_height(true);
+        sheet.get_cell_mut("A5").set_value("小計");
+        sheet
+            .get_cell_mut("C5")
+            .set_value("{{footer.total_realized_profit_and_loss}}");
+        sheet.get_style_mut("A5").get_font_mut().set_bold(true);
+        sheet
+            .get_cell_mut("A7")
+            .set_value("件数: {{summary.count}} 合計: {{summary.total_realized_profit_and_loss}}");
+        writer::xlsx::write(&book, path).unwrap();
+    }
+
+    fn bold(sheet: &Worksheet, coordinate: &str) -> bool {
+        sheet
+            .get_style(coordinate)
+            .get_font()
+            .is_some_and(|font| *font.get_bold())
+    }
+
+    #[test]
+    fn write_template_keeps_the_positions_and_styles_of_the_template() {
+        let dir = TempDir::new().unwrap();
+        let settings = Settings::defaults();
+        let sheet_title = settings.sheet_title.clone();
+        let template_filepath = dir.path().join("template.xlsx");
+        write_template(&template_filepath, &sheet_title);
+        let csv_filepath = dir.path().join("realized_pl_2024.csv");
+        fs::write(&csv_filepath, REALIZED_PL).unwrap();
+        let xlsx_filepath = dir.path().join("report.xlsx");
+
+        Converter::new(Context::from(settings), ReportKind::ProfitAndLoss)
+            .with_template(Some(template_filepath))
+            .convert_file(&csv_filepath, &xlsx_filepath)
+            .unwrap();
+
+        let book = reader::xlsx::read(&xlsx_filepath).unwrap();
+        let sheet = book.get_sheet_by_name(&sheet_title).unwrap();
+        assert_eq!(sheet.get_value("A1"), "取引一覧");
+        assert!(bold(sheet, "A1"));
+        assert_eq!(
+            [
+                sheet.get_value("A3"),
+                sheet.get_value("B3"),
+                sheet.get_value("C3")
+            ],
+            ["約定日", "銘柄名", "実現損益"]
+        );
+
+        // 日ごとの小計を挟み、明細・小計・明細・小計の順に並ぶ
+        assert_eq!(sheet.get_value("B4"), "トヨタ自動車");
+        assert_eq!(sheet.get_value("C4"), "10000");
+        assert_eq!(sheet.get_value("A5"), "小計");
+        assert_eq!(sheet.get_value("C5"), "10000");
+        assert_eq!(sheet.get_value("B6"), "ソニーＧ");
+        assert_eq!(sheet.get_value("C6"), "-10000");
+        assert_eq!(sheet.get_value("A7"), "小計");
+        assert_eq!(sheet.get_value("C7"), "-10000");
+        for (record, footer) in [("B4", "A5"), ("B6", "A7")] {
+            let fill = sheet.get_style(record).get_background_color();
+            assert_eq!(fill.map(|color| color.get_argb()), Some("FFFFF2CC"));
+            assert!(bold(sheet, footer));
+        }
+        for row in [4, 6] {
+            let dimension = sheet.get_row_dimension(&row).unwrap();
+            assert_eq!(*dimension.get_height(), 24.0);
+        }
+
+        // テンプレート行より下の行は挿入した行数だけずれる
+        assert_eq!(sheet.get_value("A9"), "件数: 2 合計: 0");
+    }
+}
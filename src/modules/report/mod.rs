@@ -0,0 +1,5 @@
+pub mod custom;
+pub mod definition;
+pub mod engine;
+pub mod pipeline;
+pub mod record;
@@ -0,0 +1,135 @@
+use super::record::{FieldSpec, ReportRecord};
+use crate::error::Result;
+use crate::modules::{
+    excel::lib::ExcelAccessor,
+    summary::summary::SummaryItem,
+    template_pattern::{Fields, Report},
+};
+use chrono::NaiveDate;
+use csv::StringRecord;
+use std::sync::Arc;
+
+/// 正規化した取引。レポートの種類によらずにフィルター・付加情報・集計に使う
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    pub date: Option<NaiveDate>,       // 約定日/入金日
+    pub security_code: Option<String>, // 銘柄コード
+    pub security_name: Option<String>, // 銘柄名
+    pub account: Option<String>,       // 口座
+    pub amount: Option<i64>,           // 実現損益/配当金
+    pub shares: Option<f64>,           // 数量[株/口]
+    pub sector: Option<String>,        // 業種。銘柄マスターで付け加える
+    pub holding_days: Option<i64>,     // 保有日数。約定履歴で付け加える
+    pub fields: Fields,                // 明細のフィールド。フックで付け加えたフィールドを含む
+}
+
+impl Transaction {
+    pub fn new<T: ReportRecord>(record: &T) -> Self {
+        Transaction {
+            date: record.date(),
+            security_code: record.security_code().map(str::to_string),
+            security_name: record.security_name().map(str::to_string),
+            account: record.account().map(str::to_string),
+            amount: record.amount(),
+            shares: record.shares(),
+            sector: None,
+            holding_days: None,
+            fields: record.get_all_fields(),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// フィールドを付け加える。同じ名前のフィールドがあれば値を置き換える
+    pub fn set_field(&mut self, name: &str, value: Option<String>) {
+        match self
+            .fields
+            .iter_mut()
+            .find(|(field_name, _)| field_name == name)
+        {
+            Some((_, current)) => *current = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
+    }
+
+    /// サマリーの1件分を返す。日付か金額がなければ`None`を返す
+    pub fn to_summary_item(&self) -> Option<SummaryItem> {
+        Some(SummaryItem {
+            date: self.date?,
+            security_code: self.security_code.clone(),
+            security_name: self.security_name.clone(),
+            account: self.account.clone(),
+            sector: self.sector.clone(),
+            holding_days: self.holding_days,
+            shares: self.shares,
+            amount: self.amount?,
+        })
+    }
+}
+
+/// 型付きのレコードと、正規化した取引の組
+///
+/// 小計はレコードの型で計算し、明細の書き込み・フィルター・サマリーは取引を使う
+#[derive(Debug, Clone)]
+pub struct Normalized<T> {
+    pub record: T,
+    pub transaction: Transaction,
+}
+
+/// パイプラインの段階の間に処理を挟むフック
+///
+/// CSVの行 → 型付きのレコード → 正規化した取引 → グループ化したレポート → 出力の順に処理し、
+/// 各メソッドは対応する段階の間で呼ばれる。既定では何もしない
+pub trait PipelineHook {
+    /// `enrich`で付け加えるフィールドの定義。`columns`で列に選べるようになる
+    fn fields(&self) -> Vec<FieldSpec> {
+        Vec::new()
+    }
+
+    /// レポートとフックの全フィールドの定義を受け取り、フックの指定を確かめる。`build`の最初に呼ばれる
+    fn check(&self, _fields: &[FieldSpec]) -> Result<()> {
+        Ok(())
+    }
+
+    /// CSVの行を解釈する前に、行を並べ替え・除外する
+    fn rows(&self, _rows: &mut Vec<StringRecord>) -> Result<()> {
+        Ok(())
+    }
+
+    /// 正規化した取引に情報を付け加える
+    fn enrich(&self, _transaction: &mut Transaction) -> Result<()> {
+        Ok(())
+    }
+
+    /// 正規化した取引の全件に情報を付け加える。既定では1件ずつ`enrich`を呼ぶ
+    ///
+    /// 他の取引との対応付けが必要なフックは、こちらを実装する
+    fn enrich_all(&self, transactions: &mut [Transaction]) -> Result<()> {
+        transactions
+            .iter_mut()
+            .try_for_each(|transaction| self.enrich(transaction))
+    }
+
+    /// グループ化の前に呼ばれ、`false`を返した取引はレポートから除く
+    fn keep(&self, _transaction: &Transaction) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// グループ化したレポートについて、変換は続けるが確認が必要な点を返す
+    fn warnings(&self, _report: &dyn Report) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// 明細とサマリーのシートの後に、追加のシートを書き込む
+    fn write(&self, _excel_accessor: &mut ExcelAccessor, _report: &dyn Report) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 登録順に呼び出すフックの一覧
+pub type Hooks = Vec<Arc<dyn PipelineHook>>;
@@ -0,0 +1,422 @@
+use super::definition::ReportDefinition;
+use crate::error::Error as ConvertError;
+use crate::error::Result;
+use crate::modules::{
+    settings::Settings,
+    summary::{lib::SummaryChart, summary::SummaryItem},
+    template_pattern::Fields,
+};
+use chrono::NaiveDate;
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+
+/// フィールドの型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Date,  // 日付
+    Int,   // 整数
+    Float, // 小数
+    #[default]
+    Text, // 文字列
+}
+
+/// 小計の行の値の求め方
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregate {
+    Sum(String), // 明細のフィールドの合計
+}
+
+/// グループ化・サマリーでのフィールドの役割
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldRole {
+    Date,         // 日付
+    SecurityCode, // 銘柄コード
+    SecurityName, // 銘柄名
+    Account,      // 口座
+    Amount,       // 集計する金額
+}
+
+/// 1フィールド分の定義
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub name: String, // フィールド名
+    #[serde(rename = "type", default)]
+    pub field_type: FieldType, // 型
+    #[serde(default)]
+    pub column: Option<usize>, // CSVの列番号(0始まり)。小計だけのフィールドは`None`
+    #[serde(default)]
+    pub label: Option<String>, // ヘッダーのラベル。省略時はフィールド名
+    #[serde(default)]
+    pub format: Option<String>, // `formats`のキー、または表示形式
+    #[serde(default)]
+    pub aggregate: Option<Aggregate>, // 小計の行の値の求め方
+    #[serde(default)]
+    pub role: Option<FieldRole>, // グループ化・サマリーでの役割
+    #[serde(default)]
+    pub negative_font: bool, // 負の値を損失の文字色で表示する
+    #[serde(default)]
+    pub data_bar: bool, // データバーを表示する
+}
+
+impl FieldSpec {
+    pub fn new(name: impl Into<String>, field_type: FieldType) -> Self {
+        FieldSpec {
+            name: name.into(),
+            field_type,
+            column: None,
+            label: None,
+            format: None,
+            aggregate: None,
+            role: None,
+            negative_font: false,
+            data_bar: false,
+        }
+    }
+
+    /// CSVの`index`番目の列から読み込む
+    pub fn column(mut self, index: usize) -> Self {
+        self.column = Some(index);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.format = Some(format.into());
+        self
+    }
+
+    /// ヘッダーに表示するラベル
+    pub fn header(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    /// 小計の行に`field`の合計を表示する
+    pub fn sum_of(mut self, field: impl Into<String>) -> Self {
+        self.aggregate = Some(Aggregate::Sum(field.into()));
+        self
+    }
+
+    pub fn negative_font(mut self) -> Self {
+        self.negative_font = true;
+        self
+    }
+
+    pub fn data_bar(mut self) -> Self {
+        self.data_bar = true;
+        self
+    }
+}
+
+/// 値を解釈できなかった理由。`parse_field`で列と値を添えたエラーにする
+fn value_error(e: impl ToString) -> ConvertError {
+    ConvertError::Other(e.to_string())
+}
+
+/// フィールドに使える型。CSVの文字列との変換を定める
+pub trait FieldValue: Sized {
+    const TYPE: FieldType;
+
+    fn parse(text: &str) -> Result<Self>;
+
+    fn to_text(&self) -> String;
+}
+
+impl FieldValue for NaiveDate {
+    const TYPE: FieldType = FieldType::Date;
+
+    fn parse(text: &str) -> Result<Self> {
+        NaiveDate::parse_from_str(&text.replace("/", "-"), "%Y-%m-%d").map_err(value_error)
+    }
+
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FieldValue for i32 {
+    const TYPE: FieldType = FieldType::Int;
+
+    fn parse(text: &str) -> Result<Self> {
+        text.replace(",", "").parse().map_err(value_error)
+    }
+
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FieldValue for i64 {
+    const TYPE: FieldType = FieldType::Int;
+
+    fn parse(text: &str) -> Result<Self> {
+        text.replace(",", "").parse().map_err(value_error)
+    }
+
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FieldValue for u32 {
+    const TYPE: FieldType = FieldType::Int;
+
+    fn parse(text: &str) -> Result<Self> {
+        text.replace(",", "").parse().map_err(value_error)
+    }
+
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FieldValue for f64 {
+    const TYPE: FieldType = FieldType::Float;
+
+    fn parse(text: &str) -> Result<Self> {
+        text.replace(",", "").parse().map_err(value_error)
+    }
+
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FieldValue for String {
+    const TYPE: FieldType = FieldType::Text;
+
+    fn parse(text: &str) -> Result<Self> {
+        Ok(text.to_string())
+    }
+
+    fn to_text(&self) -> String {
+        self.clone()
+    }
+}
+
+/// CSVの`column`番目の値を読み込む。列の指定がないか、列がなければ`None`を返す
+pub fn parse_field<V: FieldValue>(
+    record: &StringRecord,
+    column: Option<usize>,
+    field: &str,
+) -> Result<Option<V>> {
+    match column.and_then(|index| record.get(index)) {
+        Some(text) => V::parse(text)
+            .map(Some)
+            .map_err(|e| ConvertError::parse(record, field, text, e)),
+        None => Ok(None),
+    }
+}
+
+/// レポートの1行分。`ReportManager`が読み込み・グループ化・集計に使う
+///
+/// 列の対応や小計の求め方は`ReportDefinition`のフィールドの定義で渡す
+pub trait ReportRecord: Sized {
+    /// CSVの1行を`fields`の定義に従って読み込む
+    fn parse(record: StringRecord, fields: &[FieldSpec]) -> Result<Self>;
+
+    fn get_all_fields(&self) -> Fields;
+
+    /// 日付でグループ化・集計するときの日付
+    fn date(&self) -> Option<NaiveDate>;
+
+    fn security_code(&self) -> Option<&str>;
+
+    fn security_name(&self) -> Option<&str>;
+
+    fn account(&self) -> Option<&str>;
+
+    /// サマリーで集計する金額
+    fn amount(&self) -> Option<i64>;
+
+    /// 数量[株/口]。配当予想で1株あたりの金額を求めるのに使う。既定では`None`
+    fn shares(&self) -> Option<f64> {
+        None
+    }
+
+    /// サマリーの1件分を返す。日付か金額がなければ`None`を返す
+    fn to_summary_item(&self) -> Option<SummaryItem> {
+        Some(SummaryItem {
+            date: self.date()?,
+            security_code: self.security_code().map(str::to_string),
+            security_name: self.security_name().map(str::to_string),
+            account: self.account().map(str::to_string),
+            sector: None,
+            holding_days: None,
+            shares: self.shares(),
+            amount: self.amount()?,
+        })
+    }
+
+    /// すべての行を解釈した後、CSVにない値を補う。既定では何もしない
+    fn complete(_records: &mut [Self], _settings: &Settings) -> Result<()> {
+        Ok(())
+    }
+
+    /// 小計の行を返す。既定では`Aggregate`の指定があるフィールドだけを集計する
+    fn total(records: &[&Self], fields: &[FieldSpec], _settings: &Settings) -> Result<Fields> {
+        let all_fields: Vec<Fields> = records
+            .iter()
+            .map(|record| record.get_all_fields())
+            .collect();
+        // 明細の`source`の値を合計する。合計できない型は`None`
+        let sum = |source: &str| {
+            let values = all_fields.iter().filter_map(|record_fields| {
+                record_fields
+                    .iter()
+                    .find(|(field_name, _)| field_name == source)
+                    .and_then(|(_, value)| value.as_deref())
+            });
+            let source_type = fields
+                .iter()
+                .find(|field| field.name == source)
+                .map(|field| field.field_type);
+            match source_type {
+                Some(FieldType::Int) => Some(
+                    values
+                        .filter_map(|value| value.parse::<i64>().ok())
+                        .sum::<i64>()
+                        .to_string(),
+                ),
+                Some(FieldType::Float) => Some(
+                    values
+                        .filter_map(|value| value.parse::<f64>().ok())
+                        .sum::<f64>()
+                        .to_string(),
+                ),
+                _ => None,
+            }
+        };
+
+        Ok(fields
+            .iter()
+            .map(|field| {
+                let value = match &field.aggregate {
+                    Some(Aggregate::Sum(source)) => sum(source),
+                    None => None,
+                };
+                (field.name.clone(), value)
+            })
+            .collect())
+    }
+}
+
+/// フィールドの定義がモデルで決まっているレポート
+pub trait ReportModel: ReportRecord {
+    /// 設定ファイルでレポートを指定するキー
+    const KEY: &'static str;
+    /// サマリーのシートに描くグラフ
+    const CHARTS: &'static [SummaryChart];
+    /// CSVの列名。指定した場合は`column(i)`を`i`番目の列名の列から読み込み、列の並びを問わない
+    const CSV_HEADERS: &'static [&'static str] = &[];
+
+    /// 全フィールドの定義。並びは列の既定の並び
+    fn fields() -> Vec<FieldSpec>;
+
+    fn definition() -> ReportDefinition {
+        ReportDefinition {
+            key: Self::KEY.to_string(),
+            fields: Self::fields(),
+            charts: Self::CHARTS.to_vec(),
+            sheet_title: None,
+            csv_headers: Self::CSV_HEADERS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+
+    fn from_record(record: StringRecord) -> Result<Self> {
+        Self::parse(record, &Self::fields())
+    }
+
+    /// 全フィールドの名前。値はすべて`None`
+    fn blank_fields() -> Fields {
+        Self::definition().blank_fields()
+    }
+}
+
+/// フィールドの定義から`ReportModel`のモデルを生成する
+///
+/// フィールドは`名前: 型 => 定義`で並べ、定義には`FieldSpec`のメソッドを`.`でつなげる。
+/// 生成する構造体のフィールドはすべて`Option`になる。続く`impl ReportModel`と
+/// `impl ReportRecord`には、フィールドから生成できない項目を書く
+macro_rules! report_record {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $(
+                $field:ident: $type:ty $(=> $($method:ident($($arg:expr),*)).+)?,
+            )*
+        }
+
+        impl ReportModel {
+            $($model:tt)*
+        }
+
+        impl ReportRecord {
+            $($record:tt)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default)]
+        pub struct $name {
+            $(pub $field: Option<$type>,)*
+        }
+
+        impl $crate::modules::report::record::ReportModel for $name {
+            fn fields() -> Vec<$crate::modules::report::record::FieldSpec> {
+                vec![$(
+                    $crate::modules::report::record::FieldSpec::new(
+                        stringify!($field),
+                        <$type as $crate::modules::report::record::FieldValue>::TYPE,
+                    )$($(.$method($($arg),*))+)?,
+                )*]
+            }
+
+            $($model)*
+        }
+
+        impl $crate::modules::report::record::ReportRecord for $name {
+            fn parse(
+                record: csv::StringRecord,
+                fields: &[$crate::modules::report::record::FieldSpec],
+            ) -> $crate::Result<Self> {
+                let column = |name: &str| {
+                    fields
+                        .iter()
+                        .find(|field| field.name == name)
+                        .and_then(|field| field.column)
+                };
+                Ok($name {
+                    $($field: $crate::modules::report::record::parse_field(
+                        &record,
+                        column(stringify!($field)),
+                        stringify!($field),
+                    )?,)*
+                })
+            }
+
+            fn get_all_fields(&self) -> $crate::modules::template_pattern::Fields {
+                vec![$(
+                    (
+                        stringify!($field).to_string(),
+                        self.$field
+                            .as_ref()
+                            .map($crate::modules::report::record::FieldValue::to_text),
+                    ),
+                )*]
+            }
+
+            $($record)*
+        }
+    };
+}
+
+pub(crate) use report_record;
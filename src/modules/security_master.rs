@@ -0,0 +1,330 @@
+use crate::error::Error as ConvertError;
+use crate::error::Result;
+use crate::modules::{
+    csv::lib::CsvTable,
+    excel::lib::ExcelAccessor,
+    report::{
+        pipeline::{PipelineHook, Transaction},
+        record::{FieldSpec, FieldType},
+    },
+    summary::lib::SummaryWriter,
+    template_pattern::Report,
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// 銘柄マスターで明細に付け加えるフィールドとラベル。`columns`で選ぶと列に出力する
+pub const SECURITY_FIELDS: &[(&str, &str)] = &[
+    ("official_name", "正式名称"),
+    ("market", "市場"),
+    ("sector", "業種"),
+    ("asset_class", "資産クラス"),
+    ("security_currency", "通貨"),
+];
+
+/// 正式名称に置き換える明細の銘柄名のフィールド
+const SECURITY_NAME_FIELD: &str = "security_name";
+
+/// 銘柄マスターの1銘柄分
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SecurityInfo {
+    #[serde(default)]
+    pub name: Option<String>, // 正式名称
+    #[serde(default)]
+    pub market: Option<String>, // 市場
+    #[serde(default)]
+    pub sector: Option<String>, // 業種(東証33業種・GICSなど)
+    #[serde(default)]
+    pub asset_class: Option<String>, // 資産クラス
+    #[serde(default)]
+    pub currency: Option<String>, // 通貨
+}
+
+/// 銘柄コードから正式名称・市場・業種・資産クラス・通貨を引く銘柄マスター
+///
+/// CSV(列名は`code`・`name`・`market`・`sector`・`asset_class`・`currency`)か、
+/// 銘柄コードをキーにしたJSONのオブジェクトから読み込む
+#[derive(Debug, Clone, Default)]
+pub struct SecurityMaster {
+    securities: BTreeMap<String, SecurityInfo>,
+}
+
+impl SecurityMaster {
+    /// 拡張子が`.json`ならJSON、それ以外はCSVとして読み込む
+    pub fn load(filepath: &Path) -> Result<Self> {
+        let is_json = filepath
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            let text =
+                fs::read_to_string(filepath).map_err(|e| ConvertError::input(filepath, e))?;
+            return Self::from_json(&text).map_err(|e| {
+                ConvertError::Config(format!(
+                    "Invalid security master '{}': {e}",
+                    filepath.display()
+                ))
+            });
+        }
+
+        let csv = CsvTable::load(filepath, "security master")?;
+        let code_column = csv.required("code")?;
+        let columns = [
+            csv.column("name"),
+            csv.column("market"),
+            csv.column("sector"),
+            csv.column("asset_class"),
+            csv.column("currency"),
+        ];
+
+        let mut securities = BTreeMap::new();
+        for row in csv.rows() {
+            let value = |column: Option<usize>| row.value(column).map(str::to_string);
+            let Some(code) = value(Some(code_column)) else {
+                continue;
+            };
+            let [name, market, sector, asset_class, currency] = columns.map(value);
+            securities.insert(
+                code,
+                SecurityInfo {
+                    name,
+                    market,
+                    sector,
+                    asset_class,
+                    currency,
+                },
+            );
+        }
+        Ok(SecurityMaster { securities })
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let securities: BTreeMap<String, SecurityInfo> =
+            serde_json::from_str(text).map_err(|e| ConvertError::Config(e.to_string()))?;
+        Ok(SecurityMaster { securities })
+    }
+
+    pub fn get(&self, security_code: &str) -> Option<&SecurityInfo> {
+        self.securities.get(security_code.trim())
+    }
+
+    pub fn len(&self) -> usize {
+        self.securities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.securities.is_empty()
+    }
+}
+
+impl PipelineHook for SecurityMaster {
+    fn fields(&self) -> Vec<FieldSpec> {
+        SECURITY_FIELDS
+            .iter()
+            .map(|(name, label)| FieldSpec::new(*name, FieldType::Text).label(*label))
+            .collect()
+    }
+
+    /// 銘柄マスターの項目を付け加え、銘柄名を正式名称にそろえる
+    ///
+    /// サマリーの銘柄名と、明細の`security_name`フィールドの両方を置き換える。
+    /// 正式名称のない銘柄は証券会社の表記のまま残す
+    fn enrich(&self, transaction: &mut Transaction) -> Result<()> {
+        let info = transaction
+            .security_code
+            .as_deref()
+            .and_then(|security_code| self.get(security_code))
+            .cloned()
+            .unwrap_or_default();
+        transaction.set_field("official_name", info.name.clone());
+        transaction.set_field("market", info.market);
+        transaction.set_field("sector", info.sector.clone());
+        transaction.set_field("asset_class", info.asset_class);
+        transaction.set_field("security_currency", info.currency);
+        if info.name.is_some() {
+            let has_name_field = transaction
+                .fields
+                .iter()
+                .any(|(name, _)| name == SECURITY_NAME_FIELD);
+            if has_name_field {
+                transaction.set_field(SECURITY_NAME_FIELD, info.name.clone());
+            }
+            transaction.security_name = info.name;
+        }
+        transaction.sector = info.sector;
+        Ok(())
+    }
+
+    /// 銘柄マスターにない銘柄コードを警告する
+    fn warnings(&self, report: &dyn Report) -> Vec<String> {
+        let unknown: BTreeSet<&str> = report
+            .transactions()
+            .into_iter()
+            .filter_map(|transaction| transaction.security_code.as_deref())
+            .filter(|security_code| self.get(security_code).is_none())
+            .collect();
+        if unknown.is_empty() {
+            return Vec::new();
+        }
+        vec![format!(
+            "{} security code(s) not in the security master: {}",
+            unknown.len(),
+            unknown.into_iter().collect::<Vec<_>>().join(", ")
+        )]
+    }
+
+    /// `sector_sheet_titles`にレポートのシート名があれば、業種別のシートを書き込む
+    fn write(&self, excel_accessor: &mut ExcelAccessor, report: &dyn Report) -> Result<()> {
+        let settings = &report.context().settings;
+        if let Some(sheet_title) = settings.sector_sheet_titles.get(report.key()) {
+            let summary = report.summary();
+            SummaryWriter::new(settings, &summary, &[])
+                .write_sectors(excel_accessor, sheet_title)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{Converter, ReportKind};
+    use crate::modules::{context::Context, settings::Settings};
+    use tempfile::TempDir;
+
+    const MASTER_CSV: &str = "code,name,market,sector,asset_class,currency\n\
+                              7203,トヨタ自動車株式会社,プライム,輸送用機器,国内株式,JPY\n\
+                              9432,日本電信電話株式会社,プライム,情報・通信業,,\n";
+
+    const REALIZED_PL: &str = "約定日,受渡日,銘柄コード,銘柄名,口座,信用区分,売却/決済,数量[株],売却/決済単価[円],売却/決済額[円],平均取得価額[円],実現損益[円]\n\
+         2024/01/10,2024/01/12,7203,トヨタ,特定,,現物,100,\"3,000.0\",\"300,000\",\"2,900.0\",\"10,000\"\n\
+         2024/02/10,2024/02/14,9432,ＮＴＴ,特定,,現物,100,180.0,\"18,000\",170.0,\"1,000\"\n\
+         2024/03/10,2024/03/12,6758,ソニーＧ,特定,,現物,100,\"3,000.0\",\"300,000\",\"3,100.0\",\"-10,000\"\n";
+
+    fn sheet_values(sheet: &umya_spreadsheet::Worksheet) -> Vec<String> {
+        sheet
+            .get_cell_collection()
+            .iter()
+            .map(|cell| cell.get_value().to_string())
+            .collect()
+    }
+
+    fn field(transaction: &Transaction, name: &str) -> Option<String> {
+        transaction
+            .fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .and_then(|(_, value)| value.clone())
+    }
+
+    #[test]
+    fn csv_and_json_masters_are_loaded() {
+        let dir = TempDir::new().unwrap();
+        let csv_filepath = dir.path().join("master.csv");
+        fs::write(&csv_filepath, MASTER_CSV).unwrap();
+        let master = SecurityMaster::load(&csv_filepath).unwrap();
+        assert_eq!(master.len(), 2);
+        let toyota = master.get(" 7203 ").unwrap();
+        assert_eq!(toyota.name.as_deref(), Some("トヨタ自動車株式会社"));
+        assert_eq!(toyota.sector.as_deref(), Some("輸送用機器"));
+        assert_eq!(master.get("9432").unwrap().asset_class, None);
+
+        let json_filepath = dir.path().join("master.JSON");
+        fs::write(
+            &json_filepath,
+            r#"{"7203": {"name": "トヨタ自動車株式会社", "sector": "輸送用機器"}}"#,
+        )
+        .unwrap();
+        let master = SecurityMaster::load(&json_filepath).unwrap();
+        assert_eq!(master.get("7203").unwrap().market, None);
+
+        fs::write(&json_filepath, r#"["7203"]"#).unwrap();
+        let error = SecurityMaster::load(&json_filepath).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid security master"));
+
+        fs::write(&csv_filepath, "name,sector\nトヨタ,輸送用機器\n").unwrap();
+        assert!(SecurityMaster::load(&csv_filepath).is_err());
+    }
+
+    #[test]
+    fn enrich_replaces_the_detail_and_summary_names() {
+        let master = SecurityMaster::from_json(
+            r#"{"7203": {"name": "トヨタ自動車株式会社", "sector": "輸送用機器"}}"#,
+        )
+        .unwrap();
+        let mut transaction = Transaction {
+            date: None,
+            security_code: Some("7203".to_string()),
+            security_name: Some("トヨタ".to_string()),
+            account: None,
+            amount: None,
+            shares: None,
+            sector: None,
+            holding_days: None,
+            fields: vec![
+                ("security_code".to_string(), Some("7203".to_string())),
+                ("security_name".to_string(), Some("トヨタ".to_string())),
+            ],
+        };
+        master.enrich(&mut transaction).unwrap();
+        assert_eq!(
+            transaction.security_name.as_deref(),
+            Some("トヨタ自動車株式会社")
+        );
+        assert_eq!(transaction.sector.as_deref(), Some("輸送用機器"));
+        assert_eq!(
+            field(&transaction, "security_name").as_deref(),
+            Some("トヨタ自動車株式会社")
+        );
+        assert_eq!(field(&transaction, "sector").as_deref(), Some("輸送用機器"));
+        assert_eq!(field(&transaction, "market"), None);
+
+        // 銘柄マスターにない銘柄は証券会社の表記のまま
+        transaction.security_code = Some("6758".to_string());
+        transaction.security_name = Some("ソニーＧ".to_string());
+        transaction.set_field("security_name", Some("ソニーＧ".to_string()));
+        master.enrich(&mut transaction).unwrap();
+        assert_eq!(transaction.security_name.as_deref(), Some("ソニーＧ"));
+        assert_eq!(
+            field(&transaction, "security_name").as_deref(),
+            Some("ソニーＧ")
+        );
+        assert_eq!(transaction.sector, None);
+    }
+
+    #[test]
+    fn unknown_codes_are_warned_and_sectors_are_summarized() {
+        let dir = TempDir::new().unwrap();
+        let master_filepath = dir.path().join("master.csv");
+        fs::write(&master_filepath, MASTER_CSV).unwrap();
+        let csv_filepath = dir.path().join("realized_pl_2024.csv");
+        fs::write(&csv_filepath, REALIZED_PL).unwrap();
+        let xlsx_filepath = dir.path().join("report.xlsx");
+
+        let mut settings = Settings::defaults();
+        settings.security_master_path = Some(master_filepath.to_string_lossy().to_string());
+        let sheet_title = settings.sheet_title.clone();
+        let sector_sheet_title =
+            settings.sector_sheet_titles[ReportKind::ProfitAndLoss.key()].clone();
+        let converter = Converter::new(Context::from(settings), ReportKind::ProfitAndLoss);
+        let warnings = converter
+            .convert_file(&csv_filepath, &xlsx_filepath)
+            .unwrap();
+        assert_eq!(
+            warnings,
+            ["1 security code(s) not in the security master: 6758"]
+        );
+
+        let book = umya_spreadsheet::reader::xlsx::read(&xlsx_filepath).unwrap();
+        let details = sheet_values(book.get_sheet_by_name(&sheet_title).unwrap());
+        assert!(details.contains(&"トヨタ自動車株式会社".to_string()));
+        assert!(!details.contains(&"トヨタ".to_string()));
+        assert!(details.contains(&"ソニーＧ".to_string()));
+
+        let sectors = sheet_values(book.get_sheet_by_name(&sector_sheet_title).unwrap());
+        for sector in ["輸送用機器", "情報・通信業"] {
+            assert!(sectors.contains(&sector.to_string()), "{sector} is missing");
+        }
+    }
+}
@@ -1,30 +1,276 @@
-use once_cell::sync::Lazy;
+use crate::error::Error as ConvertError;
+use crate::error::Result;
+use crate::modules::columns::ColumnSetting;
+use crate::modules::config_layers::{ConfigLayers, ConfigSource};
+use crate::modules::grouping::GroupBy;
+use crate::modules::report::definition::ReportSetting;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// 組み込みの既定値
+pub const DEFAULT_SETTINGS: &str = include_str!("../settings.json");
+
+/// `config init`で出力する各設定の説明
+const KEY_COMMENTS: &[(&str, &str)] = &[
+    (
+        "formats",
+        "セルの表示形式。キーは列の`format`からも参照できる",
+    ),
+    (
+        "colors",
+        "ヘッダー・小計・損失などの色。ARGBの16進数8桁で指定する",
+    ),
+    ("sheet_title", "明細を書き込むシート名"),
+    (
+        "sheet_titles",
+        "レポートごとの明細のシート名。省略したレポートは`sheet_title`に書き込む",
+    ),
+    (
+        "summary_sheet_titles",
+        "レポートごとのサマリーのシート名。省略したレポートはサマリーを書き込まない",
+    ),
+    (
+        "sector_sheet_titles",
+        "レポートごとの業種別のシート名。銘柄マスターを指定した場合に書き込む",
+    ),
+    (
+        "statistics_sheet_titles",
+        "レポートごとの取引統計(勝率・プロフィットファクター・最大ドローダウンなど)のシート名",
+    ),
+    (
+        "dividend_history_sheet_titles",
+        "レポートごとの銘柄別の配当推移(年別・累計・前年比・減配)のシート名",
+    ),
+    (
+        "dividend_forecast_sheet_titles",
+        "レポートごとの配当予想(直近1年の支払いから予想した続く12か月の銘柄別・月別の配当)のシート名",
+    ),
+    (
+        "holding_period_sheet_titles",
+        "レポートごとの保有期間別のシート名。約定履歴を指定した場合に書き込む",
+    ),
+    (
+        "combined_summary_sheet_title",
+        "実現損益と配当金を合わせた月別・年別のシート名。batchで両方を変換した場合に、出力先の名前の置き換え文字をcombinedにしたブックに書き込む",
+    ),
+    (
+        "grouping",
+        "レポートごとのグループ化の単位(day, week, month, year, security, account)",
+    ),
+    (
+        "columns",
+        "レポートごとに出力する列。空の場合は全列を出力する",
+    ),
+    (
+        "reports",
+        "設定ファイルで定義するレポート。CSVの列の対応・型・集計・書式をキーごとに指定する",
+    ),
+    ("tax_rate", "源泉徴収税率(0以上1未満)"),
+    ("data_bars", "損益の列にデータバーを表示する"),
+    ("start_row", "表を書き込む先頭の行(1始まり)"),
+    ("start_col", "表を書き込む先頭の列(1始まり)"),
+    (
+        "template_path",
+        "出力のレイアウトに使うテンプレートのXLSXファイル",
+    ),
+    (
+        "security_master_path",
+        "銘柄コードから正式名称・市場・業種・資産クラス・通貨を引く銘柄マスターのCSV/JSONファイル",
+    ),
+    (
+        "execution_history_path",
+        "実現損益の売却を買付のロットに対応付ける約定履歴のCSVファイル",
+    ),
+    (
+        "price_path",
+        "保有証券の評価に使う終値のCSVファイル(列は date, code, close)。省略時は証券会社の評価額を使う",
+    ),
+    (
+        "holdings_path",
+        "配当の取得額利回りと配当予想の数量に使う保有証券一覧、または code・shares・cost の列のCSVファイル",
+    ),
+    ("prefix_profit_and_loss", "実現損益のCSVファイル名の接頭辞"),
+    ("prefix_dividendlist", "配当金のCSVファイル名の接頭辞"),
+    ("prefix_holdings", "保有証券一覧のCSVファイル名の接頭辞"),
+];
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings {
     pub formats: std::collections::HashMap<String, String>,
     pub colors: std::collections::HashMap<String, String>,
-    pub headers: std::collections::HashMap<String, String>,
     pub sheet_title: String,
+    #[serde(default)]
+    pub sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub summary_sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub sector_sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub statistics_sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub dividend_history_sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub dividend_forecast_sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub holding_period_sheet_titles: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    pub combined_summary_sheet_title: Option<String>,
+    #[serde(default)]
+    pub grouping: std::collections::HashMap<String, Vec<GroupBy>>,
+    #[serde(default)]
+    pub columns: std::collections::HashMap<String, Vec<ColumnSetting>>,
+    #[serde(default)]
+    pub reports: BTreeMap<String, ReportSetting>,
     pub tax_rate: f64,
+    #[serde(default)]
+    pub data_bars: bool,
     pub start_row: u32,
     pub start_col: u32,
+    #[serde(default)]
+    pub template_path: Option<String>,
+    #[serde(default)]
+    pub security_master_path: Option<String>,
+    #[serde(default)]
+    pub execution_history_path: Option<String>,
+    #[serde(default)]
+    pub price_path: Option<String>,
+    #[serde(default)]
+    pub holdings_path: Option<String>,
     pub prefix_profit_and_loss: String,
     pub prefix_dividendlist: String,
+    pub prefix_holdings: String,
+    #[serde(skip)]
+    pub sources: BTreeMap<String, ConfigSource>, // 設定値ごとの読み込み元
+    #[serde(skip)]
+    pub warnings: Vec<String>, // 読み込みは続けるが確認が必要な点
 }
 
 impl Settings {
-    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn Error>> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let settings: Settings = serde_json::from_reader(reader)?;
+    /// 既定値に各層の設定ファイルと環境変数を重ねて読み込む
+    pub fn load(config_path: Option<&Path>) -> Result<Self> {
+        let layers = ConfigLayers::load(DEFAULT_SETTINGS, config_path)?;
+        // ヘッダーのラベルは列とフィールドの`label`に移ったので、古い`headers`は読み飛ばす
+        let mut warnings = Vec::new();
+        if let Some(source) = layers.source("headers") {
+            warnings.push(format!(
+                "The 'headers' setting [{source}] is deprecated and ignored; set 'label' on entries in 'columns' or 'reports' fields instead"
+            ));
+        }
+        let mut settings: Settings = serde_path_to_error::deserialize(layers.value().clone())
+            .map_err(|e| {
+                let key = e.path().to_string();
+                ConvertError::Config(match layers.source(&key) {
+                    Some(source) => format!("Invalid setting '{key}' [{source}]: {}", e.inner()),
+                    None => format!("Invalid setting '{key}': {}", e.inner()),
+                })
+            })?;
+        settings.sources = layers.into_sources();
+        settings.warnings = warnings;
         Ok(settings)
     }
+
+    /// 組み込みの既定値だけを読み込む。設定ファイルと環境変数は読まない
+    pub fn defaults() -> Self {
+        serde_json::from_str(DEFAULT_SETTINGS).expect("Failed to parse the embedded settings")
+    }
+
+    /// 既定値に各設定の説明のコメントを付けて返す
+    pub fn commented_default() -> String {
+        let mut text = String::from(
+            "// profit-and-loss-converter の設定ファイル\n\
+             // `//`から行末まではコメントとして読み飛ばす\n",
+        );
+        for line in DEFAULT_SETTINGS.lines() {
+            let key = line
+                .strip_prefix("    \"")
+                .and_then(|line| line.split('"').next());
+            if let Some(comment) = key.and_then(|key| {
+                KEY_COMMENTS
+                    .iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, comment)| comment)
+            }) {
+                text.push_str(&format!("    // {comment}\n"));
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// 設定値を`key = value (読み込み元)`の形式で返す
+    pub fn describe(&self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        let value = serde_json::to_value(self).map_err(|e| ConvertError::Config(e.to_string()))?;
+        Self::describe_value(&value, "", &self.sources, &mut lines);
+        Ok(lines)
+    }
+
+    fn describe_value(
+        value: &serde_json::Value,
+        path: &str,
+        sources: &BTreeMap<String, ConfigSource>,
+        lines: &mut Vec<String>,
+    ) {
+        match value {
+            serde_json::Value::Object(map) if !map.is_empty() => {
+                for (key, value) in map {
+                    let path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    Self::describe_value(value, &path, sources, lines);
+                }
+            }
+            value => {
+                let source = sources.get(path).unwrap_or(&ConfigSource::Default);
+                lines.push(format!("{path} = {value} ({source})"));
+            }
+        }
+    }
+}
+
+static LOADED: OnceCell<Arc<Settings>> = OnceCell::new();
+
+/// `--config`で指定した設定ファイルを重ねて読み込む。`SETTINGS`を初めて参照する前に呼び出す
+///
+/// `SETTINGS`はコマンドラインから実行するときの読み込みを1回にするためのもので、
+/// レポートの書き込みには`Context`で設定を渡す
+pub fn init(config_path: Option<&Path>) -> Result<Arc<Settings>> {
+    LOADED
+        .get_or_try_init(|| {
+            let settings = Settings::load(config_path)?;
+            settings.validate()?;
+            Ok(Arc::new(settings))
+        })
+        .cloned()
 }
 
-pub static SETTINGS: Lazy<Settings> =
-    Lazy::new(|| Settings::load_from_file("settings.json").expect("Failed to load settings"));
+pub static SETTINGS: Lazy<Arc<Settings>> =
+    Lazy::new(|| init(None).unwrap_or_else(|e| panic!("Failed to load settings: {e}")));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn the_old_headers_setting_is_ignored_with_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(
+            &path,
+            "{\n    // 以前の形式\n    \"headers\": {\"trade_date\": \"約定日\"},\n    \"tax_rate\": 0.2\n}\n",
+        )
+        .unwrap();
+        let settings = Settings::load(Some(&path)).unwrap();
+        assert_eq!(settings.tax_rate, 0.2);
+        assert_eq!(settings.warnings.len(), 1);
+        assert!(settings.warnings[0].contains("'headers'"));
+        assert!(settings.warnings[0].contains("deprecated"));
+    }
+}
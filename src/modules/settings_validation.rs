@@ -0,0 +1,767 @@
+use crate::modules::{
+    columns::ColumnSelector,
+    config_layers::ConfigSource,
+    dividend_list::dividend_list::DividendList,
+    grouping::GroupBy,
+    holdings::holdings::Holdings,
+    lot_matching::LotMatcher,
+    profit_and_loss::profit_and_loss::ProfitAndLoss,
+    report::pipeline::PipelineHook,
+    report::{
+        definition::ReportDefinition,
+        record::{Aggregate, FieldRole, FieldType, ReportModel},
+    },
+    security_master::SecurityMaster,
+    settings::Settings,
+};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// 組み込みのレポートのキー
+const REPORT_KEYS: &[&str] = &[ProfitAndLoss::KEY, DividendList::KEY, Holdings::KEY];
+/// Excelのシート名に使えない文字
+const INVALID_SHEET_CHARS: &[char] = &['[', ']', ':', '*', '?', '/', '\\'];
+
+/// 設定の1件分の問題
+#[derive(Debug)]
+pub struct SettingsIssue {
+    pub key: String,                  // 設定のキー(例: `colors.header_background`)
+    pub source: Option<ConfigSource>, // 値の読み込み元
+    pub message: String,              // 問題と直し方
+}
+
+impl fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}: {} [{source}]", self.key, self.message),
+            None => write!(f, "{}: {}", self.key, self.message),
+        }
+    }
+}
+
+/// 設定の問題の一覧
+#[derive(Debug)]
+pub struct SettingsError {
+    pub issues: Vec<SettingsIssue>,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid settings ({} issue(s)):", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "\n  - {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for SettingsError {}
+
+impl Settings {
+    /// 設定の誤りをすべて集めて返す
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut validator = Validator {
+            settings: self,
+            issues: Vec::new(),
+        };
+        validator.validate_colors();
+        validator.validate_formats();
+        validator.validate_tax_rate();
+        validator.validate_sheet_titles();
+        validator.validate_reports();
+        validator.validate_custom_reports();
+        validator.validate_layout();
+
+        if validator.issues.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError {
+                issues: validator.issues,
+            })
+        }
+    }
+}
+
+struct Validator<'a> {
+    settings: &'a Settings,
+    issues: Vec<SettingsIssue>,
+}
+
+impl Validator<'_> {
+    fn push(&mut self, key: String, message: String) {
+        let source = self.settings.sources.get(&key).cloned();
+        self.issues.push(SettingsIssue {
+            key,
+            source,
+            message,
+        });
+    }
+
+    /// 組み込みのレポートと`reports`で定義したレポート
+    fn definitions(&self) -> Vec<ReportDefinition> {
+        let mut definitions = vec![
+            ProfitAndLoss::definition(),
+            DividendList::definition(),
+            Holdings::definition(),
+        ];
+        definitions.extend(
+            self.settings
+                .reports
+                .iter()
+                .map(|(key, report)| ReportDefinition::from_setting(key, report)),
+        );
+        definitions
+    }
+
+    fn validate_colors(&mut self) {
+        let mut colors: Vec<_> = self.settings.colors.iter().collect();
+        colors.sort();
+        for (name, color) in colors {
+            let is_argb = color.len() == 8 && color.chars().all(|c| c.is_ascii_hexdigit());
+            if !is_argb {
+                self.push(
+                    format!("colors.{name}"),
+                    format!(
+                        "'{color}' is not an ARGB colour. Use 8 hex digits such as \"FFC5E0B4\"."
+                    ),
+                );
+            }
+        }
+    }
+
+    fn validate_formats(&mut self) {
+        let mut formats: Vec<_> = self.settings.formats.iter().collect();
+        formats.sort();
+        for (name, format) in formats {
+            if let Err(message) = validate_number_format(format) {
+                self.push(format!("formats.{name}"), format!("'{format}' {message}"));
+            }
+        }
+
+        // 列・フィールドの表示形式は`formats`のキーか、表示形式そのもの
+        let settings = self.settings;
+        for definition in self.definitions() {
+            let report = &definition.key;
+            for (index, column) in settings
+                .columns
+                .get(report)
+                .into_iter()
+                .flatten()
+                .enumerate()
+            {
+                if let Some(format) = &column.format {
+                    self.validate_format_reference(
+                        format!("columns.{report}.{index}.format"),
+                        format,
+                    );
+                }
+            }
+        }
+        for (report, setting) in &settings.reports {
+            for (index, field) in setting.fields.iter().enumerate() {
+                if let Some(format) = &field.format {
+                    self.validate_format_reference(
+                        format!("reports.{report}.fields.{index}.format"),
+                        format,
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_format_reference(&mut self, key: String, format: &str) {
+        if self.settings.formats.contains_key(format) {
+            return;
+        }
+        if let Err(message) = validate_number_format(format) {
+            self.push(
+                key,
+                format!("'{format}' is neither a key of \"formats\" nor a valid format: {message}"),
+            );
+        }
+    }
+
+    fn validate_tax_rate(&mut self) {
+        let tax_rate = self.settings.tax_rate;
+        if !(0.0..1.0).contains(&tax_rate) {
+            self.push(
+                "tax_rate".to_string(),
+                format!("{tax_rate} is out of range. Use a rate between 0 and 1 such as 0.20315."),
+            );
+        }
+    }
+
+    fn validate_sheet_titles(&mut self) {
+        // 明細のシート名、サマリーなどのシート名、設定ファイルで定義したレポートの明細のシート名の順に並べる
+        let mut titles = vec![("sheet_title".to_string(), &self.settings.sheet_title)];
+        let mut detail_titles: Vec<_> = self.settings.sheet_titles.iter().collect();
+        detail_titles.sort();
+        for (report, title) in detail_titles {
+            titles.push((format!("sheet_titles.{report}"), title));
+        }
+        let detail_count = titles.len();
+        let mut summary_titles: Vec<_> = self.settings.summary_sheet_titles.iter().collect();
+        summary_titles.sort();
+        for (report, title) in summary_titles {
+            titles.push((format!("summary_sheet_titles.{report}"), title));
+        }
+        let mut sector_titles: Vec<_> = self.settings.sector_sheet_titles.iter().collect();
+        sector_titles.sort();
+        for (report, title) in sector_titles {
+            titles.push((format!("sector_sheet_titles.{report}"), title));
+        }
+        let mut statistics_titles: Vec<_> = self.settings.statistics_sheet_titles.iter().collect();
+        statistics_titles.sort();
+        for (report, title) in statistics_titles {
+            titles.push((format!("statistics_sheet_titles.{report}"), title));
+        }
+        let mut dividend_history_titles: Vec<_> =
+            self.settings.dividend_history_sheet_titles.iter().collect();
+        dividend_history_titles.sort();
+        for (report, title) in dividend_history_titles {
+            titles.push((format!("dividend_history_sheet_titles.{report}"), title));
+        }
+        let mut dividend_forecast_titles: Vec<_> = self
+            .settings
+            .dividend_forecast_sheet_titles
+            .iter()
+            .collect();
+        dividend_forecast_titles.sort();
+        for (report, title) in dividend_forecast_titles {
+            titles.push((format!("dividend_forecast_sheet_titles.{report}"), title));
+        }
+        let mut holding_period_titles: Vec<_> =
+            self.settings.holding_period_sheet_titles.iter().collect();
+        holding_period_titles.sort();
+        for (report, title) in holding_period_titles {
+            titles.push((format!("holding_period_sheet_titles.{report}"), title));
+        }
+        if let Some(title) = &self.settings.combined_summary_sheet_title {
+            titles.push(("combined_summary_sheet_title".to_string(), title));
+        }
+        let summary_count = titles.len();
+        for (report, setting) in &self.settings.reports {
+            if let Some(title) = &setting.sheet_title {
+                titles.push((format!("reports.{report}.sheet_title"), title));
+            }
+        }
+
+        for (key, title) in &titles {
+            if title.is_empty() || title.chars().count() > 31 {
+                self.push(
+                    key.clone(),
+                    format!("'{title}' must be 1 to 31 characters long."),
+                );
+            } else if title.contains(INVALID_SHEET_CHARS) {
+                self.push(
+                    key.clone(),
+                    format!("'{title}' must not contain any of [ ] : * ? / \\."),
+                );
+            } else if title.contains('\'')
+                && (key.starts_with("summary_sheet_titles.")
+                    || key.starts_with("holding_period_sheet_titles."))
+            {
+                // グラフの範囲のシート名は`'`を`''`と書くが、umya-spreadsheetはそのまま探す
+                self.push(
+                    key.clone(),
+                    format!("'{title}' must not contain ' because its charts refer to the sheet by name."),
+                );
+            }
+        }
+
+        // 明細と同じ名前のシートは明細を上書きする。
+        // 実現損益と配当金を合わせたシートは両方と同じブックに書き込むため、どのシートとも重ねない
+        for (index, (key, title)) in titles
+            .iter()
+            .enumerate()
+            .take(summary_count)
+            .skip(detail_count)
+        {
+            let is_combined = key == "combined_summary_sheet_title";
+            let other = titles.iter().enumerate().find(|(other, (_, other_title))| {
+                let is_detail = *other < detail_count || *other >= summary_count;
+                *other != index && other_title == title && (is_detail || is_combined)
+            });
+            if let Some((_, (other_key, _))) = other {
+                self.push(
+                    key.clone(),
+                    format!(
+                        "'{title}' is the same as \"{other_key}\". Use a different sheet name."
+                    ),
+                );
+            }
+        }
+    }
+
+    fn validate_reports(&mut self) {
+        let settings = self.settings;
+        let keys = [
+            (
+                "sheet_titles",
+                settings.sheet_titles.keys().collect::<Vec<_>>(),
+            ),
+            (
+                "summary_sheet_titles",
+                settings.summary_sheet_titles.keys().collect(),
+            ),
+            (
+                "sector_sheet_titles",
+                settings.sector_sheet_titles.keys().collect(),
+            ),
+            (
+                "statistics_sheet_titles",
+                settings.statistics_sheet_titles.keys().collect(),
+            ),
+            (
+                "dividend_history_sheet_titles",
+                settings.dividend_history_sheet_titles.keys().collect(),
+            ),
+            (
+                "dividend_forecast_sheet_titles",
+                settings.dividend_forecast_sheet_titles.keys().collect(),
+            ),
+            (
+                "holding_period_sheet_titles",
+                settings.holding_period_sheet_titles.keys().collect(),
+            ),
+            ("grouping", settings.grouping.keys().collect()),
+            ("columns", settings.columns.keys().collect()),
+        ];
+        let definitions = self.definitions();
+        let report_keys: Vec<&str> = definitions
+            .iter()
+            .map(|definition| definition.key.as_str())
+            .collect();
+        for (name, mut reports) in keys {
+            reports.sort();
+            for report in reports {
+                if !report_keys.contains(&report.as_str()) {
+                    self.push(
+                        format!("{name}.{report}"),
+                        format!("unknown report. Use one of {}.", report_keys.join(", ")),
+                    );
+                }
+            }
+        }
+
+        for definition in &definitions {
+            if let Some(columns) = settings.columns.get(&definition.key) {
+                // 銘柄マスターのフィールドは銘柄マスターを指定した場合に選べる
+                let mut all_fields = definition.fields.clone();
+                if settings.security_master_path.is_some() {
+                    all_fields.extend(SecurityMaster::default().fields());
+                }
+                // 約定履歴のフィールドは実現損益のレポートで選べる
+                if settings.execution_history_path.is_some() && definition.key == ProfitAndLoss::KEY
+                {
+                    all_fields.extend(LotMatcher::default().fields());
+                }
+                if let Err(e) = ColumnSelector::new(settings, columns, &all_fields) {
+                    self.push(format!("columns.{}", definition.key), e.to_string());
+                }
+            }
+        }
+    }
+
+    /// `reports`で定義したレポートの接頭辞・フィールド・グループ化・グラフ
+    fn validate_custom_reports(&mut self) {
+        let settings = self.settings;
+        let builtin_prefixes = [
+            ("prefix_profit_and_loss", &settings.prefix_profit_and_loss),
+            ("prefix_dividendlist", &settings.prefix_dividendlist),
+            ("prefix_holdings", &settings.prefix_holdings),
+        ];
+        for (report, setting) in &settings.reports {
+            let key = format!("reports.{report}");
+            if REPORT_KEYS.contains(&report.as_str()) {
+                self.push(
+                    key.clone(),
+                    "conflicts with a built-in report. Use a different key.".to_string(),
+                );
+                continue;
+            }
+
+            let prefix = &setting.prefix;
+            if prefix.is_empty() {
+                self.push(format!("{key}.prefix"), "must not be empty.".to_string());
+            } else if let Some((name, builtin)) = builtin_prefixes
+                .iter()
+                .find(|(_, builtin)| prefix.starts_with(builtin.as_str()))
+            {
+                self.push(
+                    format!("{key}.prefix"),
+                    format!("'{prefix}' starts with \"{name}\" ('{builtin}'), so the built-in report is used. Use a different prefix."),
+                );
+            }
+
+            if setting.fields.is_empty() {
+                self.push(
+                    format!("{key}.fields"),
+                    "define at least one field.".to_string(),
+                );
+            }
+            let mut names = BTreeSet::new();
+            let mut roles = Vec::new();
+            for (index, field) in setting.fields.iter().enumerate() {
+                let field_key = format!("{key}.fields.{index}");
+                if !names.insert(field.name.as_str()) {
+                    self.push(
+                        format!("{field_key}.name"),
+                        format!("duplicate field '{}'.", field.name),
+                    );
+                }
+
+                if let Some(Aggregate::Sum(source)) = &field.aggregate {
+                    let source_type = setting
+                        .fields
+                        .iter()
+                        .find(|field| &field.name == source)
+                        .map(|field| field.field_type);
+                    match source_type {
+                        Some(FieldType::Int | FieldType::Float) => {}
+                        Some(_) => self.push(
+                            format!("{field_key}.aggregate"),
+                            format!("cannot sum '{source}'. Use an int or float field."),
+                        ),
+                        None => self.push(
+                            format!("{field_key}.aggregate"),
+                            format!("unknown field '{source}'."),
+                        ),
+                    }
+                }
+
+                let Some(role) = field.role else {
+                    continue;
+                };
+                if roles.contains(&role) {
+                    self.push(
+                        format!("{field_key}.role"),
+                        format!("another field already has the role {role:?}."),
+                    );
+                }
+                roles.push(role);
+                let expected = match role {
+                    FieldRole::Date => field.field_type == FieldType::Date,
+                    FieldRole::Amount => {
+                        matches!(field.field_type, FieldType::Int | FieldType::Float)
+                    }
+                    _ => true,
+                };
+                if !expected {
+                    self.push(
+                        format!("{field_key}.role"),
+                        format!(
+                            "the role {role:?} does not fit a {:?} field.",
+                            field.field_type
+                        ),
+                    );
+                }
+            }
+
+            // グループ化とグラフに必要な役割
+            let grouping = settings.grouping.get(report).unwrap_or(&setting.grouping);
+            for group_by in grouping {
+                let required = match group_by {
+                    GroupBy::Security => FieldRole::SecurityCode,
+                    GroupBy::Account => FieldRole::Account,
+                    _ => FieldRole::Date,
+                };
+                if !roles.contains(&required) {
+                    self.push(
+                        format!("{key}.grouping"),
+                        format!(
+                            "grouping by {group_by:?} needs a field with the role {required:?}."
+                        ),
+                    );
+                }
+            }
+            let summarizable =
+                roles.contains(&FieldRole::Date) && roles.contains(&FieldRole::Amount);
+            if !setting.charts.is_empty() && !summarizable {
+                self.push(
+                    format!("{key}.charts"),
+                    "charts need fields with the roles Date and Amount.".to_string(),
+                );
+            }
+        }
+    }
+
+    fn validate_layout(&mut self) {
+        if self.settings.start_row == 0 {
+            self.push("start_row".to_string(), "rows start at 1.".to_string());
+        }
+        if self.settings.start_col == 0 {
+            self.push("start_col".to_string(), "columns start at 1.".to_string());
+        }
+        if let Some(template_path) = &self.settings.template_path {
+            if !Path::new(template_path).is_file() {
+                self.push(
+                    "template_path".to_string(),
+                    format!("template '{template_path}' not found."),
+                );
+            }
+        }
+        if let Some(security_master_path) = &self.settings.security_master_path {
+            if !Path::new(security_master_path).is_file() {
+                self.push(
+                    "security_master_path".to_string(),
+                    format!("security master '{security_master_path}' not found."),
+                );
+            }
+        }
+        if let Some(holdings_path) = &self.settings.holdings_path {
+            if !Path::new(holdings_path).is_file() {
+                self.push(
+                    "holdings_path".to_string(),
+                    format!("holdings file '{holdings_path}' not found."),
+                );
+            }
+        }
+        if let Some(price_path) = &self.settings.price_path {
+            if !Path::new(price_path).is_file() {
+                self.push(
+                    "price_path".to_string(),
+                    format!("price file '{price_path}' not found."),
+                );
+            }
+        }
+        if let Some(execution_history_path) = &self.settings.execution_history_path {
+            if !Path::new(execution_history_path).is_file() {
+                self.push(
+                    "execution_history_path".to_string(),
+                    format!("execution history '{execution_history_path}' not found."),
+                );
+            }
+        }
+    }
+}
+
+/// Excelの表示形式として解釈できるか確認する
+///
+/// 引用符・角括弧の対応、セクション数(4つまで)、角括弧内の指定を確認する
+fn validate_number_format(format: &str) -> Result<(), String> {
+    if format.is_empty() {
+        return Err("is empty.".to_string());
+    }
+
+    let mut sections = 1;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                chars
+                    .by_ref()
+                    .find(|c| *c == '"')
+                    .ok_or("has an unterminated quoted string.")?;
+            }
+            '\\' => {
+                chars.next().ok_or("ends with an escape character.")?;
+            }
+            '[' => {
+                let mut content = String::new();
+                let mut is_closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        is_closed = true;
+                        break;
+                    }
+                    content.push(c);
+                }
+                if !is_closed {
+                    return Err("has an unterminated '['.".to_string());
+                }
+                if !is_valid_bracket(&content) {
+                    return Err(format!("has an unknown bracket '[{content}]'."));
+                }
+            }
+            ']' => return Err("has an unmatched ']'.".to_string()),
+            ';' => sections += 1,
+            _ => {}
+        }
+    }
+
+    if sections > 4 {
+        return Err(format!("has {sections} sections. Excel allows up to 4."));
+    }
+    Ok(())
+}
+
+/// 色・条件・通貨/ロケール・経過時間の指定
+fn is_valid_bracket(content: &str) -> bool {
+    const COLORS: &[&str] = &[
+        "black", "blue", "cyan", "green", "magenta", "red", "white", "yellow",
+    ];
+    let lower = content.to_ascii_lowercase();
+
+    COLORS.contains(&lower.as_str())
+        || lower
+            .strip_prefix("color")
+            .is_some_and(|n| n.parse::<u32>().is_ok_and(|n| (1..=56).contains(&n)))
+        || content.starts_with(['<', '>', '='])
+        || content.starts_with('$')
+        || lower.starts_with("dbnum")
+        || (!lower.is_empty() && lower.chars().all(|c| matches!(c, 'h' | 'm' | 's')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::report::definition::ReportSetting;
+
+    /// 問題のキーとメッセージ
+    fn issues(settings: &Settings) -> Vec<(String, String)> {
+        match settings.validate() {
+            Ok(()) => Vec::new(),
+            Err(e) => e
+                .issues
+                .into_iter()
+                .map(|issue| (issue.key, issue.message))
+                .collect(),
+        }
+    }
+
+    fn custom_report(json: &str) -> ReportSetting {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn the_default_settings_are_valid() {
+        assert!(issues(&Settings::defaults()).is_empty());
+    }
+
+    #[test]
+    fn settings_for_unknown_reports_are_reported() {
+        let mut settings = Settings::defaults();
+        settings
+            .summary_sheet_titles
+            .insert("dividends".to_string(), "配当".to_string());
+        settings
+            .grouping
+            .insert("realized".to_string(), vec![GroupBy::Month]);
+        let issues = issues(&settings);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].0, "summary_sheet_titles.dividends");
+        assert!(issues[0].1.starts_with("unknown report."));
+        assert_eq!(issues[1].0, "grouping.realized");
+    }
+
+    #[test]
+    fn a_summary_sheet_must_not_overwrite_a_detail_sheet() {
+        let mut settings = Settings::defaults();
+        settings
+            .summary_sheet_titles
+            .insert("profit_and_loss".to_string(), "配当金".to_string());
+        assert_eq!(
+            issues(&settings),
+            vec![(
+                "summary_sheet_titles.profit_and_loss".to_string(),
+                "'配当金' is the same as \"sheet_titles.dividend_list\". Use a different sheet name."
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn the_combined_sheet_must_differ_from_every_other_sheet() {
+        let mut settings = Settings::defaults();
+        settings.combined_summary_sheet_title = Some("配当サマリー".to_string());
+        assert_eq!(
+            issues(&settings),
+            vec![(
+                "combined_summary_sheet_title".to_string(),
+                "'配当サマリー' is the same as \"summary_sheet_titles.dividend_list\". Use a different sheet name."
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_sheet_titles_are_reported() {
+        let mut settings = Settings::defaults();
+        settings.sheet_title = "株取引/2024".to_string();
+        settings
+            .sector_sheet_titles
+            .insert("profit_and_loss".to_string(), String::new());
+        let keys: Vec<_> = issues(&settings).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["sheet_title", "sector_sheet_titles.profit_and_loss"]);
+    }
+
+    #[test]
+    fn invalid_formats_are_reported() {
+        let mut settings = Settings::defaults();
+        settings
+            .formats
+            .insert("broken".to_string(), "#,##0;[Purple]-#,##0".to_string());
+        settings
+            .formats
+            .insert("quoted".to_string(), "0\"円".to_string());
+        let issues = issues(&settings);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].0, "formats.broken");
+        assert!(issues[0].1.contains("unknown bracket '[Purple]'"));
+        assert_eq!(issues[1].0, "formats.quoted");
+        assert!(issues[1].1.contains("unterminated quoted string"));
+    }
+
+    #[test]
+    fn number_formats_are_checked_section_by_section() {
+        assert!(validate_number_format("#,##0;[Red]-#,##0").is_ok());
+        assert!(validate_number_format("[$-ja-JP]yyyy/mm/dd").is_ok());
+        assert!(validate_number_format("[h]:mm:ss").is_ok());
+        assert!(validate_number_format("\"[\"0\"]\"").is_ok());
+        assert!(validate_number_format("").is_err());
+        assert!(validate_number_format("0]").is_err());
+        assert!(validate_number_format("0;0;0;@;0").is_err());
+    }
+
+    #[test]
+    fn colors_and_the_tax_rate_are_checked() {
+        let mut settings = Settings::defaults();
+        settings
+            .colors
+            .insert("header_background".to_string(), "#C5E0B4".to_string());
+        settings.tax_rate = 20.315;
+        let keys: Vec<_> = issues(&settings).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["colors.header_background", "tax_rate"]);
+    }
+
+    #[test]
+    fn grouping_needs_a_field_with_the_matching_role() {
+        let mut settings = Settings::defaults();
+        settings.reports.insert(
+            "fx".to_string(),
+            custom_report(
+                r#"{
+                    "prefix": "fx_",
+                    "grouping": ["month", "security"],
+                    "fields": [
+                        {"name": "date", "type": "date", "column": 0, "role": "date"},
+                        {"name": "pair", "column": 1},
+                        {"name": "amount", "type": "int", "column": 2, "role": "amount"}
+                    ]
+                }"#,
+            ),
+        );
+        assert_eq!(
+            issues(&settings),
+            vec![(
+                "reports.fx.grouping".to_string(),
+                "grouping by Security needs a field with the role SecurityCode.".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn unknown_grouping_values_are_rejected_when_loading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        std::fs::write(&path, r#"{"grouping": {"profit_and_loss": ["quarter"]}}"#).unwrap();
+        let error = Settings::load(Some(&path)).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid setting 'grouping.profit_and_loss[0]'"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use super::summary::SummaryItem;
+use crate::modules::holdings::positions::Positions;
+use chrono::{Datelike, NaiveDate};
+use std::collections::BTreeMap;
+
+/// 1銘柄分の配当予想
+#[derive(Debug, Clone, Default)]
+pub struct SecurityForecast {
+    pub security_name: Option<String>, // 銘柄名
+    pub shares: Option<f64>,           // 予想に使った数量[株/口]
+    pub monthly: [i64; 12],            // 予想する月ごとの金額。`DividendForecast::months`の順
+    pub total: i64,                    // 12か月の合計
+}
+
+/// 直近1年の支払いから予想した、続く12か月の銘柄別・月別の配当
+///
+/// 直近1年に支払いのあった月を支払月とし、その月の支払いごとの1株あたりの金額の合計に
+/// 今の数量を掛ける。数量が分からない支払いのある月は、同じ金額が続くものとする
+#[derive(Debug, Clone, Default)]
+pub struct DividendForecast {
+    pub months: Vec<(i32, u32)>,                        // 予想する年月
+    pub actual: [i64; 12],                              // 予想する月の前年同月の実績
+    pub securities: BTreeMap<String, SecurityForecast>, // 銘柄コードごとの予想
+}
+
+/// 1回分の支払い。同じ日の明細は口座が異なっても1回の支払いとしてまとめる
+#[derive(Debug, Clone)]
+struct Payment {
+    date: NaiveDate,     // 支払日
+    amount: i64,         // 金額
+    shares: Option<f64>, // 数量。分からない明細があれば`None`
+}
+
+impl Payment {
+    /// 1株あたりの金額。数量が分からなければ`None`
+    fn per_share(&self) -> Option<f64> {
+        self.shares
+            .filter(|shares| *shares > 0.0)
+            .map(|shares| self.amount as f64 / shares)
+    }
+}
+
+impl DividendForecast {
+    /// 明細の最後の月の翌月から12か月分を予想する
+    ///
+    /// 今の数量は`positions`があればその数量、なければ最後の支払いの数量を使う。
+    /// `positions`にない銘柄は売却したものとして予想しない
+    pub fn new<'a>(
+        items: impl IntoIterator<Item = &'a SummaryItem>,
+        positions: Option<&Positions>,
+    ) -> Self {
+        let items: Vec<&SummaryItem> = items.into_iter().collect();
+        let Some(latest) = items.iter().map(|item| item.date).max() else {
+            return DividendForecast::default();
+        };
+        // 年月を通し番号にする
+        let month_index = |year: i32, month0: u32| year * 12 + month0 as i32;
+        let latest_index = month_index(latest.year(), latest.month0());
+        let first_index = latest_index - 11;
+
+        let mut forecast = DividendForecast {
+            months: (1..=12)
+                .map(|offset| {
+                    let index = latest_index + offset;
+                    (index.div_euclid(12), index.rem_euclid(12) as u32 + 1)
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        // 銘柄ごとに直近1年の支払いを月ごとにまとめる
+        let mut payments: BTreeMap<String, (Option<String>, [Vec<Payment>; 12])> = BTreeMap::new();
+        for item in items {
+            let index = month_index(item.date.year(), item.date.month0());
+            if index < first_index {
+                continue;
+            }
+            let slot = (index - first_index) as usize;
+            forecast.actual[slot] += item.amount;
+
+            let (security_name, months) = payments
+                .entry(item.security_code.clone().unwrap_or_default())
+                .or_default();
+            if security_name.is_none() {
+                *security_name = item.security_name.clone();
+            }
+            let month = &mut months[slot];
+            match month.iter_mut().find(|payment| payment.date == item.date) {
+                Some(payment) => {
+                    payment.amount += item.amount;
+                    payment.shares = payment
+                        .shares
+                        .zip(item.shares)
+                        .map(|(sum, shares)| sum + shares);
+                }
+                None => month.push(Payment {
+                    date: item.date,
+                    amount: item.amount,
+                    shares: item.shares,
+                }),
+            }
+        }
+
+        for (security_code, (security_name, months)) in payments {
+            let latest_shares = months
+                .iter()
+                .flatten()
+                .max_by_key(|payment| payment.date)
+                .and_then(|payment| payment.shares);
+            let shares = match positions {
+                Some(positions) => match positions.get(&security_code) {
+                    Some(position) => position.shares.or(latest_shares),
+                    None => continue,
+                },
+                None => latest_shares,
+            };
+            if shares.is_some_and(|shares| shares <= 0.0) {
+                continue;
+            }
+
+            let mut security = SecurityForecast {
+                security_name,
+                shares,
+                ..Default::default()
+            };
+            for (slot, payments) in months.iter().enumerate() {
+                if payments.is_empty() {
+                    continue;
+                }
+                let amount: i64 = payments.iter().map(|payment| payment.amount).sum();
+                let per_share: Option<f64> = payments.iter().map(Payment::per_share).sum();
+                security.monthly[slot] = match per_share.zip(shares) {
+                    Some((per_share, shares)) => (per_share * shares).round() as i64,
+                    None => amount,
+                };
+            }
+            security.total = security.monthly.iter().sum();
+            forecast.securities.insert(security_code, security);
+        }
+        forecast
+    }
+
+    /// 予想する月ごとの全銘柄の合計
+    pub fn monthly_totals(&self) -> [i64; 12] {
+        let mut totals = [0; 12];
+        for security in self.securities.values() {
+            for (total, amount) in totals.iter_mut().zip(security.monthly) {
+                *total += amount;
+            }
+        }
+        totals
+    }
+
+    /// 12か月の全銘柄の合計
+    pub fn total(&self) -> i64 {
+        self.securities
+            .values()
+            .map(|security| security.total)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dividend(date: &str, code: &str, amount: i64, shares: Option<f64>) -> SummaryItem {
+        SummaryItem {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            security_code: Some(code.to_string()),
+            security_name: Some(format!("銘柄{code}")),
+            account: Some("特定".to_string()),
+            sector: None,
+            holding_days: None,
+            shares,
+            amount,
+        }
+    }
+
+    #[test]
+    fn months_follow_the_latest_month_across_the_year_boundary() {
+        let items = [
+            dividend("2023-12-01", "7203", 3000, Some(100.0)),
+            dividend("2024-03-15", "7203", 3500, Some(100.0)),
+        ];
+        let forecast = DividendForecast::new(&items, None);
+        assert_eq!(forecast.months.len(), 12);
+        assert_eq!(forecast.months[0], (2024, 4));
+        assert_eq!(forecast.months[8], (2024, 12));
+        assert_eq!(forecast.months[9], (2025, 1));
+        assert_eq!(forecast.months[11], (2025, 3));
+
+        // 2023年12月は予想する2024年12月の前年同月
+        let security = &forecast.securities["7203"];
+        assert_eq!(security.monthly[8], 3000);
+        assert_eq!(security.monthly[11], 3500);
+        assert_eq!(forecast.actual[8], 3000);
+        assert_eq!(forecast.actual[11], 3500);
+        assert_eq!(forecast.total(), 6500);
+    }
+
+    #[test]
+    fn payments_older_than_a_year_are_ignored() {
+        let items = [
+            dividend("2023-03-15", "7203", 2000, Some(100.0)),
+            dividend("2024-03-15", "7203", 3500, Some(100.0)),
+        ];
+        let forecast = DividendForecast::new(&items, None);
+        assert_eq!(forecast.total(), 3500);
+        assert_eq!(forecast.actual.iter().sum::<i64>(), 3500);
+    }
+
+    #[test]
+    fn per_share_amounts_are_multiplied_by_current_shares() {
+        let items = [
+            dividend("2024-03-15", "7203", 3000, Some(100.0)),
+            dividend("2024-03-15", "7203", 1500, Some(50.0)),
+        ];
+        let mut positions = Positions::default();
+        positions.add("7203", Some(200.0), None);
+        let forecast = DividendForecast::new(&items, Some(&positions));
+
+        let security = &forecast.securities["7203"];
+        assert_eq!(security.shares, Some(200.0));
+        // 1株30円 × 200株
+        assert_eq!(security.monthly[11], 6000);
+        assert_eq!(forecast.monthly_totals()[11], 6000);
+    }
+
+    #[test]
+    fn per_share_amounts_are_summed_per_payment_in_the_same_month() {
+        // 月初の100株への30円と、買い増した後の200株(2口座)への10円の2回の支払い
+        let items = [
+            dividend("2024-03-05", "1343", 3000, Some(100.0)),
+            dividend("2024-03-25", "1343", 1500, Some(150.0)),
+            dividend("2024-03-25", "1343", 500, Some(50.0)),
+        ];
+        let forecast = DividendForecast::new(&items, None);
+
+        // 数量は最後の支払いの数量を使い、(30円 + 10円) × 200株
+        let security = &forecast.securities["1343"];
+        assert_eq!(security.shares, Some(200.0));
+        assert_eq!(security.monthly[11], 8000);
+        assert_eq!(forecast.actual[11], 5000);
+
+        let mut positions = Positions::default();
+        positions.add("1343", Some(300.0), None);
+        let forecast = DividendForecast::new(&items, Some(&positions));
+        assert_eq!(forecast.securities["1343"].monthly[11], 12000);
+    }
+
+    #[test]
+    fn latest_shares_are_used_without_positions() {
+        let items = [
+            dividend("2023-09-15", "7203", 3000, Some(100.0)),
+            dividend("2024-03-15", "7203", 6000, Some(200.0)),
+        ];
+        let forecast = DividendForecast::new(&items, None);
+        let security = &forecast.securities["7203"];
+        assert_eq!(security.shares, Some(200.0));
+        assert_eq!(security.monthly[5], 6000);
+        assert_eq!(security.monthly[11], 6000);
+    }
+
+    #[test]
+    fn securities_missing_from_positions_are_dropped() {
+        let items = [
+            dividend("2024-03-15", "7203", 3000, Some(100.0)),
+            dividend("2024-03-15", "6758", 2000, Some(100.0)),
+        ];
+        let mut positions = Positions::default();
+        positions.add("7203", Some(100.0), None);
+        positions.add("9432", Some(0.0), None);
+        let forecast = DividendForecast::new(&items, Some(&positions));
+
+        assert!(forecast.securities.contains_key("7203"));
+        assert!(!forecast.securities.contains_key("6758"));
+        // 実績は売却した銘柄も含める
+        assert_eq!(forecast.actual[11], 5000);
+        assert_eq!(forecast.total(), 3000);
+    }
+
+    #[test]
+    fn sold_out_positions_are_dropped() {
+        let items = [dividend("2024-03-15", "7203", 3000, Some(100.0))];
+        let mut positions = Positions::default();
+        positions.add("7203", Some(0.0), None);
+        let forecast = DividendForecast::new(&items, Some(&positions));
+        assert!(forecast.securities.is_empty());
+    }
+
+    #[test]
+    fn payments_with_unknown_shares_repeat_the_amount() {
+        let items = [
+            dividend("2024-03-15", "7203", 3000, Some(100.0)),
+            dividend("2024-03-20", "7203", 500, None),
+        ];
+        let mut positions = Positions::default();
+        positions.add("7203", Some(200.0), None);
+        let forecast = DividendForecast::new(&items, Some(&positions));
+
+        // 数量が分からない明細を含む月は1株あたりの金額が分からない
+        let security = &forecast.securities["7203"];
+        assert_eq!(security.shares, Some(200.0));
+        assert_eq!(security.monthly[11], 3500);
+    }
+
+    #[test]
+    fn empty_items_forecast_nothing() {
+        let forecast = DividendForecast::new(&[], None);
+        assert!(forecast.months.is_empty());
+        assert_eq!(forecast.total(), 0);
+    }
+}
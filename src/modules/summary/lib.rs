@@ -0,0 +1,949 @@
+use super::{
+    statistics::{StatisticKind, TradeStatistics},
+    summary::{CombinedMonth, Summary},
+};
+use crate::error::Result;
+use crate::modules::{
+    excel::{
+        cell_style::CellStyle, chart_item::ChartItem, coordinate::Coordinate, lib::ExcelAccessor,
+    },
+    holdings::positions::Positions,
+    settings::Settings,
+};
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use umya_spreadsheet::ChartType;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryChart {
+    CumulativeLine, // 累計の折れ線グラフ
+    MonthlyColumn,  // 月別合計の縦棒グラフ
+    SecurityPie,    // 銘柄別合計の円グラフ。負の合計があれば縦棒グラフにする
+    SecurityColumn, // 銘柄別合計の縦棒グラフ
+}
+
+pub struct SummaryWriter<'a> {
+    settings: &'a Settings,
+    summary: &'a Summary,
+    charts: &'a [SummaryChart],
+}
+
+/// グラフが参照する表の行範囲
+struct SummaryLayout {
+    security_rows: (u32, u32),
+    monthly_rows: (u32, u32),
+}
+
+const CHART_WIDTH: u32 = 8;
+const CHART_HEIGHT: u32 = 16;
+
+/// サマリーの見出しのキーとラベル
+const LABELS: &[(&str, &str)] = &[
+    ("summary_monthly", "月別・年別"),
+    ("summary_security", "銘柄別"),
+    ("summary_account", "口座別"),
+    ("summary_year_to_date", "年初来累計"),
+    ("year", "年"),
+    ("month", "月"),
+    ("year_month", "年月"),
+    ("security_code", "銘柄コード"),
+    ("security_name", "銘柄名"),
+    ("account", "口座"),
+    ("shares", "数量[株]"),
+    ("count", "件数"),
+    ("total", "合計"),
+    ("year_to_date", "年初来累計"),
+    ("cumulative_total", "累計"),
+    ("chart_cumulative", "累計推移"),
+    ("chart_monthly", "月別合計"),
+    ("chart_security", "銘柄別合計"),
+    ("summary_sector", "業種別"),
+    ("summary_sector_security", "業種・銘柄別"),
+    ("sector", "業種"),
+    ("unclassified", "未分類"),
+    ("summary_statistics_year", "年別の取引統計"),
+    ("summary_statistics_account", "口座別の取引統計"),
+    ("statistic", "項目"),
+    ("all_periods", "全期間"),
+    ("summary_holding_period", "保有期間別"),
+    ("holding_period", "保有期間"),
+    ("chart_holding_period", "保有期間の分布"),
+    ("summary_dividend_history", "銘柄別・年別"),
+    ("summary_dividend_growth", "前年比"),
+    ("acquisition_cost", "取得額"),
+    ("yield_on_cost", "取得額利回り"),
+    ("dividend_cut", "減配"),
+    ("trailing_twelve_months", "直近12か月"),
+    ("summary_dividend_forecast", "配当予想カレンダー"),
+    ("forecast_actual", "前年同月の実績"),
+    ("summary_combined", "実現損益・配当金の月別・年別"),
+    ("realized_profit_and_loss", "実現損益"),
+    ("dividends", "配当金"),
+];
+
+/// サマリーの見出しのラベル。定義のないキーはそのまま返す
+pub fn label(key: &str) -> &str {
+    LABELS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map_or(key, |(_, label)| label)
+}
+
+impl<'a> SummaryWriter<'a> {
+    pub fn new(settings: &'a Settings, summary: &'a Summary, charts: &'a [SummaryChart]) -> Self {
+        SummaryWriter {
+            settings,
+            summary,
+            charts,
+        }
+    }
+
+    pub fn write(&self, excel_accessor: &mut ExcelAccessor, sheet_title: &str) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        // 月別・年別
+        let len = self.write_monthly_pivot(excel_accessor, &mut row_index)?;
+        row_index += 1;
+        // 銘柄別
+        let security_rows = self.write_security_totals(excel_accessor, &mut row_index)?;
+        row_index += 1;
+        // 口座別
+        self.write_account_totals(excel_accessor, &mut row_index)?;
+        row_index += 1;
+        // 年初来累計
+        let monthly_rows = self.write_monthly_totals(excel_accessor, &mut row_index)?;
+
+        let len = len.max(5);
+        excel_accessor.adjust_column_widths(len)?;
+
+        // グラフ書き込み
+        let layout = SummaryLayout {
+            security_rows,
+            monthly_rows,
+        };
+        self.write_charts(excel_accessor, &layout, self.settings.start_col + len + 1);
+        Ok(())
+    }
+
+    /// 実現損益のサマリーに`dividends`の配当金を合わせ、月別・年別の合計を書き込む
+    ///
+    /// 年ごとに実現損益・配当金・合計の3列を並べる
+    pub fn write_combined(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        sheet_title: &str,
+        dividends: &Summary,
+    ) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        let pivot = Summary::combined_pivot(self.summary, dividends);
+        self.write_title(excel_accessor, &mut row_index, "summary_combined");
+
+        let mut header = vec![self.header("month")];
+        for year in pivot.keys() {
+            for key in ["realized_profit_and_loss", "dividends", "total"] {
+                header.push(Some(format!("{year} {}", label(key))));
+            }
+        }
+        self.write_header(excel_accessor, &mut row_index, &header);
+
+        let first_row = row_index;
+        for month in 0..12 {
+            let mut row = vec![Some((month + 1).to_string())];
+            for months in pivot.values() {
+                let combined = months[month];
+                row.push(Some(combined.profit_and_loss.to_string()));
+                row.push(Some(combined.dividends.to_string()));
+                row.push(Some(combined.total().to_string()));
+            }
+            self.write_row(excel_accessor, &mut row_index, &row, 1, None);
+        }
+
+        let mut footer = vec![self.header("total")];
+        for months in pivot.values() {
+            footer.push(Some(
+                months
+                    .iter()
+                    .map(|month| month.profit_and_loss)
+                    .sum::<i64>()
+                    .to_string(),
+            ));
+            footer.push(Some(
+                months
+                    .iter()
+                    .map(|month| month.dividends)
+                    .sum::<i64>()
+                    .to_string(),
+            ));
+            footer.push(Some(
+                months
+                    .iter()
+                    .map(CombinedMonth::total)
+                    .sum::<i64>()
+                    .to_string(),
+            ));
+        }
+        let background_color = self.settings.colors.get("footer_background");
+        self.write_row(excel_accessor, &mut row_index, &footer, 1, background_color);
+        self.add_loss_rule(excel_accessor, (first_row, row_index - 1), 1, header.len());
+
+        excel_accessor.adjust_column_widths(header.len() as u32)?;
+        Ok(())
+    }
+
+    /// 業種別・業種と銘柄別の合計を書き込む
+    pub fn write_sectors(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        sheet_title: &str,
+    ) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        let sector_label = |sector: String| match sector.is_empty() {
+            true => self.header("unclassified"),
+            false => Some(sector),
+        };
+
+        // 業種別。年ごとの合計を列に並べる
+        let sectors = self.summary.by_sector();
+        let years: BTreeSet<i32> = sectors
+            .values()
+            .flat_map(|total| total.yearly.keys().copied())
+            .collect();
+        self.write_title(excel_accessor, &mut row_index, "summary_sector");
+        let mut header = vec![self.header("sector"), self.header("count")];
+        header.extend(years.iter().map(|year| Some(year.to_string())));
+        header.push(self.header("total"));
+        self.write_header(excel_accessor, &mut row_index, &header);
+        let first_row = row_index;
+        for (sector, total) in sectors {
+            let mut row = vec![sector_label(sector), Some(total.count.to_string())];
+            row.extend(
+                years
+                    .iter()
+                    .map(|year| Some(total.yearly.get(year).copied().unwrap_or(0).to_string())),
+            );
+            row.push(Some(total.total.to_string()));
+            self.write_row(excel_accessor, &mut row_index, &row, 2, None);
+        }
+        let mut footer = vec![self.header("total"), None];
+        let monthly_pivot = self.summary.monthly_pivot();
+        footer.extend(years.iter().map(|year| {
+            let months = monthly_pivot.get(year).copied().unwrap_or_default();
+            Some(months.iter().sum::<i64>().to_string())
+        }));
+        footer.push(Some(self.summary.total().to_string()));
+        let background_color = self.settings.colors.get("footer_background");
+        self.write_row(excel_accessor, &mut row_index, &footer, 2, background_color);
+        self.add_loss_rule(excel_accessor, (first_row, row_index - 1), 2, header.len());
+        row_index += 1;
+
+        // 業種・銘柄別
+        self.write_title(excel_accessor, &mut row_index, "summary_sector_security");
+        self.write_header(
+            excel_accessor,
+            &mut row_index,
+            &[
+                self.header("sector"),
+                self.header("security_code"),
+                self.header("security_name"),
+                self.header("count"),
+                self.header("total"),
+            ],
+        );
+        let first_row = row_index;
+        for ((sector, security_code), total) in self.summary.by_sector_security() {
+            self.write_row(
+                excel_accessor,
+                &mut row_index,
+                &[
+                    sector_label(sector),
+                    Some(security_code),
+                    total.security_name,
+                    Some(total.count.to_string()),
+                    Some(total.total.to_string()),
+                ],
+                4,
+                None,
+            );
+        }
+        self.add_loss_rule(excel_accessor, (first_row, row_index - 1), 4, 5);
+
+        let len = (header.len() as u32).max(5);
+        excel_accessor.adjust_column_widths(len)?;
+        Ok(())
+    }
+
+    /// 年別・口座別の取引統計を書き込む。列に年・口座と全期間を並べる
+    pub fn write_statistics(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        sheet_title: &str,
+    ) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        let all_periods = self.summary.statistics();
+        let yearly: Vec<(Option<String>, TradeStatistics)> = self
+            .summary
+            .statistics_by_year()
+            .into_iter()
+            .map(|(year, statistics)| (Some(year.to_string()), statistics))
+            .collect();
+        let len = self.write_statistics_table(
+            excel_accessor,
+            &mut row_index,
+            "summary_statistics_year",
+            &yearly,
+            &all_periods,
+        );
+        row_index += 1;
+        let by_account: Vec<(Option<String>, TradeStatistics)> = self
+            .summary
+            .statistics_by_account()
+            .into_iter()
+            .map(|(account, statistics)| (Some(account), statistics))
+            .collect();
+        let len = len.max(self.write_statistics_table(
+            excel_accessor,
+            &mut row_index,
+            "summary_statistics_account",
+            &by_account,
+            &all_periods,
+        ));
+
+        excel_accessor.adjust_column_widths(len)?;
+        Ok(())
+    }
+
+    /// 項目を行、`columns`と全期間を列にした取引統計の表を書き込み、列数を返す
+    fn write_statistics_table(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+        title: &str,
+        columns: &[(Option<String>, TradeStatistics)],
+        all_periods: &TradeStatistics,
+    ) -> u32 {
+        self.write_title(excel_accessor, row_index, title);
+        let mut header = vec![self.header("statistic")];
+        header.extend(columns.iter().map(|(label, _)| label.clone()));
+        header.push(self.header("all_periods"));
+        self.write_header(excel_accessor, row_index, &header);
+
+        let values: Vec<_> = columns
+            .iter()
+            .map(|(_, statistics)| statistics)
+            .chain([all_periods])
+            .map(TradeStatistics::statistics)
+            .collect();
+        let first_row = *row_index;
+        for (index, statistic) in all_periods.statistics().iter().enumerate() {
+            let format = match statistic.kind {
+                StatisticKind::Count => None,
+                StatisticKind::Amount => self.settings.formats.get("yen"),
+                StatisticKind::Percent => self.settings.formats.get("percent"),
+                StatisticKind::Ratio => self.settings.formats.get("ratio"),
+            };
+            let coordinate_item = (self.settings.start_col, *row_index).new_coordinate();
+            excel_accessor.write_cell(
+                coordinate_item,
+                &Some(statistic.label.to_string()),
+                &CellStyle::new(None, None, None),
+            );
+            for (col_index, statistics) in values.iter().enumerate() {
+                let value = statistics[index].value.map(|value| match statistic.kind {
+                    StatisticKind::Amount => (value.round() as i64).to_string(),
+                    _ => value.to_string(),
+                });
+                let coordinate_item =
+                    (self.settings.start_col + col_index as u32 + 1, *row_index).new_coordinate();
+                excel_accessor.write_cell(
+                    coordinate_item,
+                    &value,
+                    &CellStyle::new(None, format, None),
+                );
+            }
+            *row_index += 1;
+        }
+        self.add_loss_rule(excel_accessor, (first_row, *row_index - 1), 1, header.len());
+        header.len() as u32
+    }
+
+    /// 保有期間の区分ごとの件数と合計を書き込み、件数のヒストグラムを添える
+    pub fn write_holding_periods(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        sheet_title: &str,
+    ) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        self.write_title(excel_accessor, &mut row_index, "summary_holding_period");
+        let header = [
+            self.header("holding_period"),
+            self.header("count"),
+            self.header("total"),
+        ];
+        self.write_header(excel_accessor, &mut row_index, &header);
+        let first_row = row_index;
+        for total in self.summary.by_holding_period() {
+            // 件数は金額の書式にしない
+            let coordinate_item = (self.settings.start_col + 1, row_index).new_coordinate();
+            self.write_row(
+                excel_accessor,
+                &mut row_index,
+                &[
+                    Some(total.label.to_string()),
+                    None,
+                    Some(total.total.to_string()),
+                ],
+                2,
+                None,
+            );
+            excel_accessor.write_cell(
+                coordinate_item,
+                &Some(total.count.to_string()),
+                &CellStyle::new(None, None, None),
+            );
+        }
+        let last_row = row_index - 1;
+
+        let len = header.len() as u32;
+        excel_accessor.adjust_column_widths(len)?;
+        self.add_loss_rule(excel_accessor, (first_row, last_row), 2, header.len());
+
+        let start_col = self.settings.start_col;
+        let col = start_col + len + 1;
+        excel_accessor.add_chart(&ChartItem::new(
+            ChartType::BarChart,
+            Some(&label("chart_holding_period").to_string()),
+            (
+                (col, self.settings.start_row).new_coordinate(),
+                (col + CHART_WIDTH, self.settings.start_row + CHART_HEIGHT).new_coordinate(),
+            ),
+            (
+                (start_col + 1, first_row).new_coordinate(),
+                (start_col + 1, last_row).new_coordinate(),
+            ),
+            (
+                (start_col, first_row).new_coordinate(),
+                (start_col, last_row).new_coordinate(),
+            ),
+        ));
+        Ok(())
+    }
+
+    /// 銘柄別・年別の合計と累計、前年比を書き込む
+    ///
+    /// 直近12か月の1株あたりの配当がその前の12か月より減った銘柄に減配の印を付ける。
+    /// `positions`があれば、取得額と直近12か月の配当の取得額に対する利回りを並べる。
+    /// 前年比は年の途中で比べないよう、12月まで明細のある年と直近12か月について書き込む
+    pub fn write_dividend_history(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        sheet_title: &str,
+        positions: Option<&Positions>,
+    ) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        let histories = self.summary.by_security_year();
+        let years: Vec<i32> = histories
+            .values()
+            .flat_map(|history| history.yearly.keys().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        // 最後の明細が12月でなければ、最後の年は途中まで
+        let complete_years: Vec<i32> = match self.summary.latest_date() {
+            Some(latest) if latest.month() != 12 => years
+                .iter()
+                .copied()
+                .filter(|year| *year < latest.year())
+                .collect(),
+            _ => years.clone(),
+        };
+        let percent_format = self.settings.formats.get("percent");
+
+        // 銘柄別・年別
+        self.write_title(excel_accessor, &mut row_index, "summary_dividend_history");
+        let mut header = vec![self.header("security_code"), self.header("security_name")];
+        header.extend(years.iter().map(|year| Some(year.to_string())));
+        header.push(self.header("cumulative_total"));
+        if positions.is_some() {
+            header.push(self.header("acquisition_cost"));
+            header.push(self.header("yield_on_cost"));
+        }
+        header.push(self.header("dividend_cut"));
+        self.write_header(excel_accessor, &mut row_index, &header);
+        let first_row = row_index;
+        for (security_code, history) in &histories {
+            let mut row = vec![Some(security_code.clone()), history.security_name.clone()];
+            row.extend(years.iter().map(|year| {
+                history
+                    .yearly
+                    .get(year)
+                    .map(|dividend| dividend.amount.to_string())
+            }));
+            row.push(Some(history.total.to_string()));
+            let mut percent_col = None;
+            if let Some(positions) = positions {
+                let position = positions.get(security_code);
+                row.push(
+                    position
+                        .and_then(|position| position.cost)
+                        .filter(|cost| *cost > 0)
+                        .map(|cost| cost.to_string()),
+                );
+                percent_col = Some(row.len());
+                row.push(
+                    position
+                        .and_then(|position| history.yield_on_cost(position))
+                        .map(|value| value.to_string()),
+                );
+            }
+            row.push(
+                history
+                    .is_cut()
+                    .then(|| self.header("dividend_cut"))
+                    .flatten(),
+            );
+            let current_row = row_index;
+            self.write_row(excel_accessor, &mut row_index, &row, 2, None);
+
+            // 利回りは割合の書式にする
+            if let Some(col) = percent_col {
+                excel_accessor.write_cell(
+                    (self.settings.start_col + col as u32, current_row).new_coordinate(),
+                    &row[col],
+                    &CellStyle::new(None, percent_format, None),
+                );
+            }
+        }
+        let mut footer = vec![self.header("total"), None];
+        let monthly_pivot = self.summary.monthly_pivot();
+        footer.extend(years.iter().map(|year| {
+            let months = monthly_pivot.get(year).copied().unwrap_or_default();
+            Some(months.iter().sum::<i64>().to_string())
+        }));
+        footer.push(Some(self.summary.total().to_string()));
+        let background_color = self.settings.colors.get("footer_background");
+        self.write_row(excel_accessor, &mut row_index, &footer, 2, background_color);
+        // 減配の印の列は除く
+        self.add_loss_rule(
+            excel_accessor,
+            (first_row, row_index - 1),
+            2,
+            header.len() - 1,
+        );
+        row_index += 1;
+
+        // 前年比
+        self.write_title(excel_accessor, &mut row_index, "summary_dividend_growth");
+        let mut growth_header = vec![self.header("security_code"), self.header("security_name")];
+        let growth_years: Vec<i32> = complete_years.iter().copied().skip(1).collect();
+        growth_header.extend(growth_years.iter().map(|year| Some(year.to_string())));
+        growth_header.push(self.header("trailing_twelve_months"));
+        self.write_header(excel_accessor, &mut row_index, &growth_header);
+        let first_row = row_index;
+        for (security_code, history) in &histories {
+            let label_style = CellStyle::new(None, None, None);
+            let label_cols = [Some(security_code.clone()), history.security_name.clone()];
+            for (col_index, value) in label_cols.iter().enumerate() {
+                excel_accessor.write_cell(
+                    (self.settings.start_col + col_index as u32, row_index).new_coordinate(),
+                    value,
+                    &label_style,
+                );
+            }
+            let growths = growth_years
+                .iter()
+                .map(|year| history.growth(*year))
+                .chain([history.trailing_growth()]);
+            for (col_index, growth) in growths.enumerate() {
+                excel_accessor.write_cell(
+                    (self.settings.start_col + col_index as u32 + 2, row_index).new_coordinate(),
+                    &growth.map(|growth| growth.to_string()),
+                    &CellStyle::new(None, percent_format, None),
+                );
+            }
+            row_index += 1;
+        }
+        self.add_loss_rule(
+            excel_accessor,
+            (first_row, row_index - 1),
+            2,
+            growth_header.len(),
+        );
+
+        let len = (header.len() as u32).max(5);
+        excel_accessor.adjust_column_widths(len)?;
+        Ok(())
+    }
+
+    /// 直近1年の支払いから予想した、続く12か月の銘柄別・月別の配当をカレンダー形式で書き込む
+    ///
+    /// 予想の合計の下に、同じ月の前年の実績の合計を並べる
+    pub fn write_dividend_forecast(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        sheet_title: &str,
+        positions: Option<&Positions>,
+    ) -> Result<()> {
+        excel_accessor.new_sheet(sheet_title)?;
+
+        let mut row_index = self.settings.start_row;
+        let forecast = self.summary.dividend_forecast(positions);
+
+        self.write_title(excel_accessor, &mut row_index, "summary_dividend_forecast");
+        let mut header = vec![
+            self.header("security_code"),
+            self.header("security_name"),
+            self.header("shares"),
+        ];
+        header.extend(
+            forecast
+                .months
+                .iter()
+                .map(|(year, month)| Some(format!("{year}/{month:02}"))),
+        );
+        header.push(self.header("total"));
+        self.write_header(excel_accessor, &mut row_index, &header);
+
+        let months_len = forecast.months.len();
+        let first_row = row_index;
+        for (security_code, security) in &forecast.securities {
+            let mut row = vec![
+                Some(security_code.clone()),
+                security.security_name.clone(),
+                security.shares.map(|shares| shares.to_string()),
+            ];
+            row.extend(
+                security.monthly[..months_len]
+                    .iter()
+                    .map(|amount| (*amount != 0).then(|| amount.to_string())),
+            );
+            row.push(Some(security.total.to_string()));
+            // 数量は金額の書式にしない
+            self.write_row(excel_accessor, &mut row_index, &row, 3, None);
+        }
+
+        // 予想の合計と前年同月の実績
+        let background_color = self.settings.colors.get("footer_background");
+        let totals = [
+            (
+                self.header("total"),
+                forecast.monthly_totals(),
+                forecast.total(),
+            ),
+            (
+                self.header("forecast_actual"),
+                forecast.actual,
+                forecast.actual.iter().sum(),
+            ),
+        ];
+        for (label, monthly, total) in totals {
+            let mut footer = vec![label, None, None];
+            footer.extend(
+                monthly[..months_len]
+                    .iter()
+                    .map(|amount| Some(amount.to_string())),
+            );
+            footer.push(Some(total.to_string()));
+            self.write_row(excel_accessor, &mut row_index, &footer, 3, background_color);
+        }
+        self.add_loss_rule(excel_accessor, (first_row, row_index - 1), 3, header.len());
+
+        let len = (header.len() as u32).max(5);
+        excel_accessor.adjust_column_widths(len)?;
+        Ok(())
+    }
+
+    fn write_charts(&self, excel_accessor: &mut ExcelAccessor, layout: &SummaryLayout, col: u32) {
+        let start_col = self.settings.start_col;
+        let mut row = self.settings.start_row;
+        let has_loss = self
+            .summary
+            .by_security()
+            .values()
+            .any(|total| total.total < 0);
+
+        for chart in self.charts {
+            // 値の列、項目名の列の範囲、行の範囲
+            let (chart_type, title, value_col, category_cols, (first_row, last_row)) = match chart {
+                SummaryChart::CumulativeLine => (
+                    ChartType::LineChart,
+                    "chart_cumulative",
+                    start_col + 3,
+                    (start_col, start_col),
+                    layout.monthly_rows,
+                ),
+                SummaryChart::MonthlyColumn => (
+                    ChartType::BarChart,
+                    "chart_monthly",
+                    start_col + 1,
+                    (start_col, start_col),
+                    layout.monthly_rows,
+                ),
+                // 円グラフは損失を表せないため、損失のある銘柄があれば縦棒グラフにする
+                SummaryChart::SecurityPie | SummaryChart::SecurityColumn => (
+                    match chart {
+                        SummaryChart::SecurityPie if !has_loss => ChartType::PieChart,
+                        _ => ChartType::BarChart,
+                    },
+                    "chart_security",
+                    start_col + 3,
+                    (start_col + 1, start_col + 1),
+                    layout.security_rows,
+                ),
+            };
+
+            // データのない表はグラフにしない
+            if first_row > last_row {
+                continue;
+            }
+
+            excel_accessor.add_chart(&ChartItem::new(
+                chart_type,
+                Some(&label(title).to_string()),
+                (
+                    (col, row).new_coordinate(),
+                    (col + CHART_WIDTH, row + CHART_HEIGHT).new_coordinate(),
+                ),
+                (
+                    (value_col, first_row).new_coordinate(),
+                    (value_col, last_row).new_coordinate(),
+                ),
+                (
+                    (category_cols.0, first_row).new_coordinate(),
+                    (category_cols.1, last_row).new_coordinate(),
+                ),
+            ));
+            row += CHART_HEIGHT + 1;
+        }
+    }
+
+    fn write_monthly_pivot(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+    ) -> Result<u32> {
+        let pivot = self.summary.monthly_pivot();
+
+        self.write_title(excel_accessor, row_index, "summary_monthly");
+
+        let mut header = vec![self.header("month")];
+        header.extend(pivot.keys().map(|year| Some(year.to_string())));
+        self.write_header(excel_accessor, row_index, &header);
+
+        let first_row = *row_index;
+        for month in 0..12 {
+            let mut row = vec![Some((month + 1).to_string())];
+            row.extend(pivot.values().map(|months| Some(months[month].to_string())));
+            self.write_row(excel_accessor, row_index, &row, 1, None);
+        }
+
+        let mut footer = vec![self.header("total")];
+        footer.extend(
+            pivot
+                .values()
+                .map(|months| Some(months.iter().sum::<i64>().to_string())),
+        );
+        let background_color = self.settings.colors.get("footer_background");
+        self.write_row(excel_accessor, row_index, &footer, 1, background_color);
+        self.add_loss_rule(excel_accessor, (first_row, *row_index - 1), 1, header.len());
+
+        Ok(header.len() as u32)
+    }
+
+    fn write_security_totals(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+    ) -> Result<(u32, u32)> {
+        self.write_title(excel_accessor, row_index, "summary_security");
+        self.write_header(
+            excel_accessor,
+            row_index,
+            &[
+                self.header("security_code"),
+                self.header("security_name"),
+                self.header("count"),
+                self.header("total"),
+            ],
+        );
+
+        let first_row = *row_index;
+        for (security_code, total) in self.summary.by_security() {
+            self.write_row(
+                excel_accessor,
+                row_index,
+                &[
+                    Some(security_code),
+                    total.security_name,
+                    Some(total.count.to_string()),
+                    Some(total.total.to_string()),
+                ],
+                3,
+                None,
+            );
+        }
+        self.add_loss_rule(excel_accessor, (first_row, *row_index - 1), 3, 4);
+        Ok((first_row, *row_index - 1))
+    }
+
+    fn write_account_totals(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+    ) -> Result<()> {
+        self.write_title(excel_accessor, row_index, "summary_account");
+        self.write_header(
+            excel_accessor,
+            row_index,
+            &[
+                self.header("account"),
+                self.header("count"),
+                self.header("total"),
+            ],
+        );
+
+        let first_row = *row_index;
+        for (account, total) in self.summary.by_account() {
+            self.write_row(
+                excel_accessor,
+                row_index,
+                &[
+                    Some(account),
+                    Some(total.count.to_string()),
+                    Some(total.total.to_string()),
+                ],
+                2,
+                None,
+            );
+        }
+        self.add_loss_rule(excel_accessor, (first_row, *row_index - 1), 2, 3);
+        Ok(())
+    }
+
+    fn write_monthly_totals(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+    ) -> Result<(u32, u32)> {
+        self.write_title(excel_accessor, row_index, "summary_year_to_date");
+        self.write_header(
+            excel_accessor,
+            row_index,
+            &[
+                self.header("year_month"),
+                self.header("total"),
+                self.header("year_to_date"),
+                self.header("cumulative_total"),
+            ],
+        );
+
+        let first_row = *row_index;
+        for monthly_total in self.summary.monthly_totals() {
+            self.write_row(
+                excel_accessor,
+                row_index,
+                &[
+                    Some(format!("{}/{:02}", monthly_total.year, monthly_total.month)),
+                    Some(monthly_total.total.to_string()),
+                    Some(monthly_total.year_to_date.to_string()),
+                    Some(monthly_total.cumulative.to_string()),
+                ],
+                1,
+                None,
+            );
+        }
+        self.add_loss_rule(excel_accessor, (first_row, *row_index - 1), 1, 4);
+        Ok((first_row, *row_index - 1))
+    }
+
+    fn header(&self, key: &str) -> Option<String> {
+        Some(label(key).to_string())
+    }
+
+    fn write_title(&self, excel_accessor: &mut ExcelAccessor, row_index: &mut u32, key: &str) {
+        let coordinate_item = (self.settings.start_col, *row_index).new_coordinate();
+        excel_accessor.write_cell(
+            coordinate_item,
+            &self.header(key),
+            &CellStyle::new(None, None, None),
+        );
+        *row_index += 1;
+    }
+
+    fn write_header(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+        values: &[Option<String>],
+    ) {
+        let background_color = self.settings.colors.get("header_background");
+        for (col_index, value) in values.iter().enumerate() {
+            let coordinate_item =
+                (col_index as u32 + self.settings.start_col, *row_index).new_coordinate();
+            excel_accessor.write_cell(
+                coordinate_item,
+                value,
+                &CellStyle::new(background_color, None, None),
+            );
+        }
+        *row_index += 1;
+    }
+
+    /// 先頭`label_len`列をラベル、残りの列を金額として書き込む
+    fn write_row(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        row_index: &mut u32,
+        values: &[Option<String>],
+        label_len: usize,
+        background_color: Option<&String>,
+    ) {
+        let yen_format = self.settings.formats.get("yen");
+        for (col_index, value) in values.iter().enumerate() {
+            let coordinate_item =
+                (col_index as u32 + self.settings.start_col, *row_index).new_coordinate();
+            let cell_style = if col_index < label_len {
+                CellStyle::new(background_color, None, None)
+            } else {
+                CellStyle::new(background_color, yen_format, None)
+            };
+            excel_accessor.write_cell(coordinate_item, value, &cell_style);
+        }
+        *row_index += 1;
+    }
+
+    /// 表の`first_row`から`last_row`まで、`label_len`列目から`len`列目までの金額の列で損失を色付けする
+    fn add_loss_rule(
+        &self,
+        excel_accessor: &mut ExcelAccessor,
+        (first_row, last_row): (u32, u32),
+        label_len: usize,
+        len: usize,
+    ) {
+        if first_row > last_row || label_len >= len {
+            return;
+        }
+        if let Some(realized_loss_font_color) = self.settings.colors.get("realized_loss_font") {
+            let start_col = self.settings.start_col;
+            excel_accessor.add_negative_font_rule(
+                (
+                    (start_col + label_len as u32, first_row).new_coordinate(),
+                    (start_col + len as u32 - 1, last_row).new_coordinate(),
+                ),
+                realized_loss_font_color,
+            );
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod forecast;
+pub mod lib;
+pub mod sheets;
+pub mod statistics;
+#[allow(clippy::module_inception)]
+pub mod summary;
@@ -0,0 +1,58 @@
+use super::lib::SummaryWriter;
+use crate::error::Result;
+use crate::modules::{
+    excel::lib::ExcelAccessor, holdings::positions::Positions, report::pipeline::PipelineHook,
+    settings::Settings, template_pattern::Report,
+};
+
+/// `statistics_sheet_titles`にレポートのシート名があれば、取引統計のシートを書き込むフック
+#[derive(Debug, Clone, Default)]
+pub struct StatisticsSheet;
+
+impl PipelineHook for StatisticsSheet {
+    fn write(&self, excel_accessor: &mut ExcelAccessor, report: &dyn Report) -> Result<()> {
+        let settings = &report.context().settings;
+        if let Some(sheet_title) = settings.statistics_sheet_titles.get(report.key()) {
+            let summary = report.summary();
+            SummaryWriter::new(settings, &summary, &[])
+                .write_statistics(excel_accessor, sheet_title)?;
+        }
+        Ok(())
+    }
+}
+
+/// 銘柄別の配当推移と配当予想のシートを書き込むフック
+///
+/// 保有証券一覧は作るときに一度だけ読み込み、取得額と今の数量に使う
+#[derive(Debug, Clone, Default)]
+pub struct DividendSheets {
+    positions: Option<Positions>,
+}
+
+impl DividendSheets {
+    pub fn new(positions: Option<Positions>) -> Self {
+        DividendSheets { positions }
+    }
+
+    /// `holdings_path`を指定していれば保有証券一覧を読み込む
+    pub fn from_settings(settings: &Settings) -> Result<Self> {
+        Ok(DividendSheets::new(Positions::from_settings(settings)?))
+    }
+}
+
+impl PipelineHook for DividendSheets {
+    /// `dividend_history_sheet_titles`・`dividend_forecast_sheet_titles`にレポートのシート名があれば、
+    /// 配当推移・配当予想のシートを書き込む
+    fn write(&self, excel_accessor: &mut ExcelAccessor, report: &dyn Report) -> Result<()> {
+        let settings = &report.context().settings;
+        let summary = report.summary();
+        let writer = SummaryWriter::new(settings, &summary, &[]);
+        if let Some(sheet_title) = settings.dividend_history_sheet_titles.get(report.key()) {
+            writer.write_dividend_history(excel_accessor, sheet_title, self.positions.as_ref())?;
+        }
+        if let Some(sheet_title) = settings.dividend_forecast_sheet_titles.get(report.key()) {
+            writer.write_dividend_forecast(excel_accessor, sheet_title, self.positions.as_ref())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+/// 取引統計の値の種類。表示形式を選ぶのに使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatisticKind {
+    Count,   // 件数・回数
+    Amount,  // 金額
+    Percent, // 割合
+    Ratio,   // 比率
+}
+
+/// 取引統計の1項目
+#[derive(Debug, Clone)]
+pub struct Statistic {
+    pub key: &'static str,   // 項目のキー
+    pub label: &'static str, // 項目のラベル
+    pub kind: StatisticKind, // 値の種類
+    pub value: Option<f64>,  // 値。計算できなければ`None`
+}
+
+/// 実現損益の1件を1回の取引とした成績の統計
+///
+/// 損益が0の取引は勝ちにも負けにも数えず、連勝・連敗を途切れさせる
+#[derive(Debug, Clone, Default)]
+pub struct TradeStatistics {
+    pub trades: usize,              // 取引数
+    pub winning_trades: usize,      // 勝ちトレード数
+    pub losing_trades: usize,       // 負けトレード数
+    pub gross_profit: i64,          // 利益の合計
+    pub gross_loss: i64,            // 損失の合計(負の値)
+    pub average_gain: Option<f64>,  // 平均利益
+    pub median_gain: Option<f64>,   // 利益の中央値
+    pub average_loss: Option<f64>,  // 平均損失(負の値)
+    pub median_loss: Option<f64>,   // 損失の中央値(負の値)
+    pub largest_win: Option<i64>,   // 最大利益
+    pub largest_loss: Option<i64>,  // 最大損失(負の値)
+    pub longest_win_streak: usize,  // 最大連勝数
+    pub longest_loss_streak: usize, // 最大連敗数
+    pub max_drawdown: i64,          // 累計損益の高値からの最大の下落幅
+}
+
+impl TradeStatistics {
+    /// 時系列に並べた実現損益から統計を計算する
+    pub fn from_amounts(amounts: impl IntoIterator<Item = i64>) -> Self {
+        let mut statistics = TradeStatistics::default();
+        let mut gains = Vec::new();
+        let mut losses = Vec::new();
+        let (mut win_streak, mut loss_streak) = (0, 0);
+        let (mut cumulative, mut peak) = (0, 0);
+
+        for amount in amounts {
+            statistics.trades += 1;
+            if amount > 0 {
+                gains.push(amount);
+                win_streak += 1;
+                loss_streak = 0;
+            } else if amount < 0 {
+                losses.push(amount);
+                win_streak = 0;
+                loss_streak += 1;
+            } else {
+                win_streak = 0;
+                loss_streak = 0;
+            }
+            statistics.longest_win_streak = statistics.longest_win_streak.max(win_streak);
+            statistics.longest_loss_streak = statistics.longest_loss_streak.max(loss_streak);
+
+            cumulative += amount;
+            peak = peak.max(cumulative);
+            statistics.max_drawdown = statistics.max_drawdown.max(peak - cumulative);
+        }
+
+        statistics.winning_trades = gains.len();
+        statistics.losing_trades = losses.len();
+        statistics.gross_profit = gains.iter().sum();
+        statistics.gross_loss = losses.iter().sum();
+        statistics.average_gain = average(&gains);
+        statistics.average_loss = average(&losses);
+        statistics.largest_win = gains.iter().max().copied();
+        statistics.largest_loss = losses.iter().min().copied();
+        statistics.median_gain = median(&mut gains);
+        statistics.median_loss = median(&mut losses);
+        statistics
+    }
+
+    /// 勝ちトレード数 / 取引数
+    pub fn win_rate(&self) -> Option<f64> {
+        (self.trades > 0).then(|| self.winning_trades as f64 / self.trades as f64)
+    }
+
+    /// 利益の合計 / 損失の合計の絶対値。損失がなければ`None`
+    pub fn profit_factor(&self) -> Option<f64> {
+        (self.gross_loss < 0).then(|| self.gross_profit as f64 / -self.gross_loss as f64)
+    }
+
+    /// シート・表に並べる順の項目
+    pub fn statistics(&self) -> Vec<Statistic> {
+        let count = |value: usize| Some(value as f64);
+        let amount = |value: Option<i64>| value.map(|value| value as f64);
+        [
+            ("trades", "取引数", StatisticKind::Count, count(self.trades)),
+            (
+                "winning_trades",
+                "勝ちトレード数",
+                StatisticKind::Count,
+                count(self.winning_trades),
+            ),
+            (
+                "losing_trades",
+                "負けトレード数",
+                StatisticKind::Count,
+                count(self.losing_trades),
+            ),
+            ("win_rate", "勝率", StatisticKind::Percent, self.win_rate()),
+            (
+                "profit_factor",
+                "プロフィットファクター",
+                StatisticKind::Ratio,
+                self.profit_factor(),
+            ),
+            (
+                "average_gain",
+                "平均利益",
+                StatisticKind::Amount,
+                self.average_gain,
+            ),
+            (
+                "median_gain",
+                "利益の中央値",
+                StatisticKind::Amount,
+                self.median_gain,
+            ),
+            (
+                "average_loss",
+                "平均損失",
+                StatisticKind::Amount,
+                self.average_loss,
+            ),
+            (
+                "median_loss",
+                "損失の中央値",
+                StatisticKind::Amount,
+                self.median_loss,
+            ),
+            (
+                "largest_win",
+                "最大利益",
+                StatisticKind::Amount,
+                amount(self.largest_win),
+            ),
+            (
+                "largest_loss",
+                "最大損失",
+                StatisticKind::Amount,
+                amount(self.largest_loss),
+            ),
+            (
+                "longest_win_streak",
+                "最大連勝数",
+                StatisticKind::Count,
+                count(self.longest_win_streak),
+            ),
+            (
+                "longest_loss_streak",
+                "最大連敗数",
+                StatisticKind::Count,
+                count(self.longest_loss_streak),
+            ),
+            (
+                "max_drawdown",
+                "最大ドローダウン",
+                StatisticKind::Amount,
+                amount(Some(self.max_drawdown)),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, label, kind, value)| Statistic {
+            key,
+            label,
+            kind,
+            value,
+        })
+        .collect()
+    }
+}
+
+fn average(amounts: &[i64]) -> Option<f64> {
+    (!amounts.is_empty()).then(|| amounts.iter().sum::<i64>() as f64 / amounts.len() as f64)
+}
+
+fn median(amounts: &mut [i64]) -> Option<f64> {
+    if amounts.is_empty() {
+        return None;
+    }
+    amounts.sort_unstable();
+    let middle = amounts.len() / 2;
+    Some(if amounts.len().is_multiple_of(2) {
+        (amounts[middle - 1] + amounts[middle]) as f64 / 2.0
+    } else {
+        amounts[middle] as f64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn counts_gains_and_losses_and_skips_zero() {
+        let statistics = TradeStatistics::from_amounts([1000, -500, 0, 3000, -1500]);
+        assert_eq!(statistics.trades, 5);
+        assert_eq!(statistics.winning_trades, 2);
+        assert_eq!(statistics.losing_trades, 2);
+        assert_eq!(statistics.gross_profit, 4000);
+        assert_eq!(statistics.gross_loss, -2000);
+        assert_eq!(statistics.win_rate(), Some(0.4));
+        assert_eq!(statistics.profit_factor(), Some(2.0));
+        assert_eq!(statistics.largest_win, Some(3000));
+        assert_eq!(statistics.largest_loss, Some(-1500));
+        assert_eq!(statistics.average_gain, Some(2000.0));
+        assert_eq!(statistics.average_loss, Some(-1000.0));
+    }
+
+    #[test]
+    fn zero_breaks_streaks() {
+        let statistics =
+            TradeStatistics::from_amounts([100, 200, 0, 300, -100, -200, -300, 0, -400]);
+        assert_eq!(statistics.longest_win_streak, 2);
+        assert_eq!(statistics.longest_loss_streak, 3);
+    }
+
+    #[test]
+    fn median_of_odd_and_even_counts() {
+        let statistics = TradeStatistics::from_amounts([500, -100, 100, -300, 300]);
+        assert_eq!(statistics.median_gain, Some(300.0));
+        assert_eq!(statistics.median_loss, Some(-200.0));
+    }
+
+    #[test]
+    fn drawdown_is_measured_from_the_cumulative_peak() {
+        // 累計: 1000, 1500, 700, 200, 1200, 900
+        let statistics = TradeStatistics::from_amounts([1000, 500, -800, -500, 1000, -300]);
+        assert_eq!(statistics.max_drawdown, 1300);
+
+        // 最初から損失が続く場合は0からの下落幅
+        let statistics = TradeStatistics::from_amounts([-200, -300, 100]);
+        assert_eq!(statistics.max_drawdown, 500);
+    }
+
+    #[test]
+    fn no_trades_have_no_ratios() {
+        let statistics = TradeStatistics::from_amounts([]);
+        assert_eq!(statistics.trades, 0);
+        assert_eq!(statistics.win_rate(), None);
+        assert_eq!(statistics.profit_factor(), None);
+        assert_eq!(statistics.median_gain, None);
+        assert_eq!(statistics.max_drawdown, 0);
+
+        // 損失がなければプロフィットファクターは計算しない
+        let statistics = TradeStatistics::from_amounts([100, 0]);
+        assert_eq!(statistics.profit_factor(), None);
+        assert_eq!(statistics.win_rate(), Some(0.5));
+    }
+
+    #[test]
+    fn statistics_have_unique_keys_and_labels() {
+        let statistics = TradeStatistics::default().statistics();
+        assert_eq!(statistics.len(), 14);
+        assert_eq!(
+            statistics.first().map(|statistic| statistic.key),
+            Some("trades")
+        );
+        assert_eq!(
+            statistics.last().map(|statistic| statistic.label),
+            Some("最大ドローダウン")
+        );
+        let labels: BTreeSet<&str> = statistics.iter().map(|statistic| statistic.label).collect();
+        assert_eq!(labels.len(), statistics.len());
+    }
+}
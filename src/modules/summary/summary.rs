@@ -0,0 +1,580 @@
+use super::{forecast::DividendForecast, statistics::TradeStatistics};
+use crate::modules::holdings::positions::{Position, Positions};
+use chrono::{Datelike, Months, NaiveDate};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct SummaryItem {
+    pub date: NaiveDate,               // 約定日/入金日
+    pub security_code: Option<String>, // 銘柄コード
+    pub security_name: Option<String>, // 銘柄名
+    pub account: Option<String>,       // 口座
+    pub sector: Option<String>,        // 業種
+    pub holding_days: Option<i64>,     // 保有日数
+    pub shares: Option<f64>,           // 数量[株/口]
+    pub amount: i64,                   // 実現損益/配当金
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SecurityTotal {
+    pub security_name: Option<String>, // 銘柄名
+    pub count: usize,                  // 件数
+    pub total: i64,                    // 合計
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AccountTotal {
+    pub count: usize, // 件数
+    pub total: i64,   // 合計
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SectorTotal {
+    pub count: usize,               // 件数
+    pub yearly: BTreeMap<i32, i64>, // 年ごとの合計
+    pub total: i64,                 // 合計
+}
+
+/// 期間中の配当の合計
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeriodDividend {
+    pub count: usize,           // 支払いの回数
+    pub amount: i64,            // 合計
+    pub per_share: Option<f64>, // 1株あたりの合計。数量の分からない支払いがあれば`None`
+}
+
+impl PeriodDividend {
+    fn add(&mut self, item: &SummaryItem) {
+        let per_share = item
+            .shares
+            .filter(|shares| *shares > 0.0)
+            .map(|shares| item.amount as f64 / shares);
+        self.per_share = match self.count {
+            0 => per_share,
+            _ => self
+                .per_share
+                .zip(per_share)
+                .map(|(sum, value)| sum + value),
+        };
+        self.count += 1;
+        self.amount += item.amount;
+    }
+
+    /// `previous`に対する増減率
+    ///
+    /// 保有数の増減に左右されないよう1株あたりで比べ、数量が分からなければ金額で比べる。
+    /// どちらかの期間に支払いがなければ`None`
+    pub fn growth(&self, previous: &PeriodDividend) -> Option<f64> {
+        if self.count == 0 || previous.count == 0 {
+            return None;
+        }
+        let (current, previous) = match (self.per_share, previous.per_share) {
+            (Some(current), Some(previous)) => (current, previous),
+            _ => (self.amount as f64, previous.amount as f64),
+        };
+        (previous > 0.0).then(|| (current - previous) / previous)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHistory {
+    pub security_name: Option<String>,         // 銘柄名
+    pub yearly: BTreeMap<i32, PeriodDividend>, // 年ごとの合計
+    pub total: i64,                            // 累計
+    pub trailing: PeriodDividend,              // 明細の最後の日までの12か月の合計
+    pub previous: PeriodDividend,              // `trailing`の前の12か月の合計
+}
+
+impl SecurityHistory {
+    /// `year`の合計の前年比。前年か`year`に支払いがなければ`None`
+    pub fn growth(&self, year: i32) -> Option<f64> {
+        self.yearly
+            .get(&year)?
+            .growth(self.yearly.get(&(year - 1))?)
+    }
+
+    /// 直近12か月の合計の、その前の12か月に対する増減率
+    pub fn trailing_growth(&self) -> Option<f64> {
+        self.trailing.growth(&self.previous)
+    }
+
+    /// 直近12か月の1株あたりの配当が、その前の12か月より減った
+    ///
+    /// 直近12か月に支払いがない銘柄は、売却と区別できないため減配としない
+    pub fn is_cut(&self) -> bool {
+        self.trailing_growth().is_some_and(|growth| growth < 0.0)
+    }
+
+    /// 直近12か月の配当の取得額に対する利回り
+    ///
+    /// 1株あたりの配当が分かれば今の保有数量を掛け、分からなければ受け取った金額を使う
+    pub fn yield_on_cost(&self, position: &Position) -> Option<f64> {
+        let cost = position.cost.filter(|cost| *cost > 0)?;
+        if self.trailing.count == 0 {
+            return None;
+        }
+        let annual = match (self.trailing.per_share, position.shares) {
+            (Some(per_share), Some(shares)) => per_share * shares,
+            _ => self.trailing.amount as f64,
+        };
+        Some(annual / cost as f64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HoldingPeriodTotal {
+    pub label: &'static str,   // 区分のラベル
+    pub max_days: Option<i64>, // 保有日数の上限。`None`は上限なし
+    pub count: usize,          // 件数
+    pub total: i64,            // 合計
+}
+
+/// 保有期間の区分。(ラベル, 保有日数の上限)
+pub const HOLDING_PERIODS: &[(&str, Option<i64>)] = &[
+    ("1週間以内", Some(7)),
+    ("1か月以内", Some(30)),
+    ("3か月以内", Some(90)),
+    ("6か月以内", Some(180)),
+    ("1年以内", Some(365)),
+    ("2年以内", Some(730)),
+    ("2年超", None),
+];
+
+#[derive(Debug, Clone)]
+pub struct MonthlyTotal {
+    pub year: i32,         // 年
+    pub month: u32,        // 月
+    pub total: i64,        // 月間合計
+    pub year_to_date: i64, // 年初来累計
+    pub cumulative: i64,   // 累計
+}
+
+/// 実現損益と配当金の月間合計
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CombinedMonth {
+    pub profit_and_loss: i64, // 実現損益
+    pub dividends: i64,       // 配当金
+}
+
+impl CombinedMonth {
+    /// 実現損益と配当金の合計
+    pub fn total(&self) -> i64 {
+        self.profit_and_loss + self.dividends
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    items: Vec<SummaryItem>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Summary { items: Vec::new() }
+    }
+
+    pub fn push(&mut self, item: SummaryItem) {
+        self.items.push(item);
+    }
+
+    /// `other`の明細を加える
+    pub fn merge(&mut self, other: Summary) {
+        self.items.extend(other.items);
+    }
+
+    /// 全期間の合計を返す
+    pub fn total(&self) -> i64 {
+        self.items.iter().map(|item| item.amount).sum()
+    }
+
+    /// 年ごとに1〜12月の合計を並べたピボットを返す
+    pub fn monthly_pivot(&self) -> BTreeMap<i32, [i64; 12]> {
+        let mut pivot = BTreeMap::new();
+        for item in &self.items {
+            let months = pivot.entry(item.date.year()).or_insert([0; 12]);
+            months[item.date.month0() as usize] += item.amount;
+        }
+        pivot
+    }
+
+    /// 実現損益と配当金を合わせ、年ごとに1〜12月の合計を並べたピボットを返す
+    pub fn combined_pivot(
+        profit_and_loss: &Summary,
+        dividends: &Summary,
+    ) -> BTreeMap<i32, [CombinedMonth; 12]> {
+        let mut pivot: BTreeMap<i32, [CombinedMonth; 12]> = BTreeMap::new();
+        for (year, months) in profit_and_loss.monthly_pivot() {
+            let combined = pivot.entry(year).or_default();
+            for (month, amount) in months.into_iter().enumerate() {
+                combined[month].profit_and_loss = amount;
+            }
+        }
+        for (year, months) in dividends.monthly_pivot() {
+            let combined = pivot.entry(year).or_default();
+            for (month, amount) in months.into_iter().enumerate() {
+                combined[month].dividends = amount;
+            }
+        }
+        pivot
+    }
+
+    /// 銘柄コードごとの件数と合計を返す
+    pub fn by_security(&self) -> BTreeMap<String, SecurityTotal> {
+        let mut totals: BTreeMap<String, SecurityTotal> = BTreeMap::new();
+        for item in &self.items {
+            let key = item.security_code.clone().unwrap_or_default();
+            let total = totals.entry(key).or_default();
+            if total.security_name.is_none() {
+                total.security_name = item.security_name.clone();
+            }
+            total.count += 1;
+            total.total += item.amount;
+        }
+        totals
+    }
+
+    /// 口座ごとの件数と合計を返す
+    pub fn by_account(&self) -> BTreeMap<String, AccountTotal> {
+        let mut totals: BTreeMap<String, AccountTotal> = BTreeMap::new();
+        for item in &self.items {
+            let key = item.account.clone().unwrap_or_default();
+            let total = totals.entry(key).or_default();
+            total.count += 1;
+            total.total += item.amount;
+        }
+        totals
+    }
+
+    /// 最後の明細の日付
+    pub fn latest_date(&self) -> Option<NaiveDate> {
+        self.items.iter().map(|item| item.date).max()
+    }
+
+    /// 銘柄コードごとの年ごとの合計と累計、直近12か月とその前の12か月の合計を返す
+    ///
+    /// 直近12か月は、すべての銘柄で最後の明細の日付を終わりにする
+    pub fn by_security_year(&self) -> BTreeMap<String, SecurityHistory> {
+        let latest = self.latest_date();
+        let trailing_start = latest.and_then(|date| date.checked_sub_months(Months::new(12)));
+        let previous_start = latest.and_then(|date| date.checked_sub_months(Months::new(24)));
+
+        let mut histories: BTreeMap<String, SecurityHistory> = BTreeMap::new();
+        for item in &self.items {
+            let key = item.security_code.clone().unwrap_or_default();
+            let history = histories.entry(key).or_default();
+            if history.security_name.is_none() {
+                history.security_name = item.security_name.clone();
+            }
+            history
+                .yearly
+                .entry(item.date.year())
+                .or_default()
+                .add(item);
+            history.total += item.amount;
+            if trailing_start.is_some_and(|start| item.date > start) {
+                history.trailing.add(item);
+            } else if previous_start.is_some_and(|start| item.date > start) {
+                history.previous.add(item);
+            }
+        }
+        histories
+    }
+
+    /// 直近1年の支払いから、続く12か月の銘柄別・月別の配当を予想する
+    pub fn dividend_forecast(&self, positions: Option<&Positions>) -> DividendForecast {
+        DividendForecast::new(&self.items, positions)
+    }
+
+    /// 業種ごとの件数と年ごとの合計を返す。業種のない明細のキーは空文字列
+    pub fn by_sector(&self) -> BTreeMap<String, SectorTotal> {
+        let mut totals: BTreeMap<String, SectorTotal> = BTreeMap::new();
+        for item in &self.items {
+            let key = item.sector.clone().unwrap_or_default();
+            let total = totals.entry(key).or_default();
+            total.count += 1;
+            *total.yearly.entry(item.date.year()).or_default() += item.amount;
+            total.total += item.amount;
+        }
+        totals
+    }
+
+    /// 業種・銘柄コードごとの件数と合計を返す
+    pub fn by_sector_security(&self) -> BTreeMap<(String, String), SecurityTotal> {
+        let mut totals: BTreeMap<(String, String), SecurityTotal> = BTreeMap::new();
+        for item in &self.items {
+            let key = (
+                item.sector.clone().unwrap_or_default(),
+                item.security_code.clone().unwrap_or_default(),
+            );
+            let total = totals.entry(key).or_default();
+            if total.security_name.is_none() {
+                total.security_name = item.security_name.clone();
+            }
+            total.count += 1;
+            total.total += item.amount;
+        }
+        totals
+    }
+
+    /// 保有期間の区分ごとの件数と合計を返す。保有日数のない明細は数えない
+    pub fn by_holding_period(&self) -> Vec<HoldingPeriodTotal> {
+        let mut totals: Vec<HoldingPeriodTotal> = HOLDING_PERIODS
+            .iter()
+            .map(|&(label, max_days)| HoldingPeriodTotal {
+                label,
+                max_days,
+                count: 0,
+                total: 0,
+            })
+            .collect();
+        for item in &self.items {
+            let Some(holding_days) = item.holding_days else {
+                continue;
+            };
+            if let Some(total) = totals.iter_mut().find(|total| {
+                total
+                    .max_days
+                    .is_none_or(|max_days| holding_days <= max_days)
+            }) {
+                total.count += 1;
+                total.total += item.amount;
+            }
+        }
+        totals
+    }
+
+    /// 全期間の取引統計を返す
+    pub fn statistics(&self) -> TradeStatistics {
+        TradeStatistics::from_amounts(self.chronological().map(|item| item.amount))
+    }
+
+    /// 年ごとの取引統計を返す
+    pub fn statistics_by_year(&self) -> BTreeMap<i32, TradeStatistics> {
+        self.statistics_by(|item| item.date.year())
+    }
+
+    /// 口座ごとの取引統計を返す
+    pub fn statistics_by_account(&self) -> BTreeMap<String, TradeStatistics> {
+        self.statistics_by(|item| item.account.clone().unwrap_or_default())
+    }
+
+    fn statistics_by<K: Ord>(
+        &self,
+        key: impl Fn(&SummaryItem) -> K,
+    ) -> BTreeMap<K, TradeStatistics> {
+        let mut amounts: BTreeMap<K, Vec<i64>> = BTreeMap::new();
+        for item in self.chronological() {
+            amounts.entry(key(item)).or_default().push(item.amount);
+        }
+        amounts
+            .into_iter()
+            .map(|(key, amounts)| (key, TradeStatistics::from_amounts(amounts)))
+            .collect()
+    }
+
+    /// 日付順の明細。同じ日付の明細は元の順に並べる
+    fn chronological(&self) -> impl Iterator<Item = &SummaryItem> {
+        let mut items: Vec<&SummaryItem> = self.items.iter().collect();
+        items.sort_by_key(|item| item.date);
+        items.into_iter()
+    }
+
+    /// 月ごとの合計と年初来累計、全期間の累計を時系列で返す
+    pub fn monthly_totals(&self) -> Vec<MonthlyTotal> {
+        let mut monthly: BTreeMap<(i32, u32), i64> = BTreeMap::new();
+        for item in &self.items {
+            *monthly
+                .entry((item.date.year(), item.date.month()))
+                .or_default() += item.amount;
+        }
+
+        let mut result = Vec::new();
+        let mut current_year = None;
+        let mut year_to_date = 0;
+        let mut cumulative = 0;
+        for ((year, month), total) in monthly {
+            if current_year != Some(year) {
+                current_year = Some(year);
+                year_to_date = 0;
+            }
+            year_to_date += total;
+            cumulative += total;
+            result.push(MonthlyTotal {
+                year,
+                month,
+                total,
+                year_to_date,
+                cumulative,
+            });
+        }
+        result
+    }
+}
+
+impl FromIterator<SummaryItem> for Summary {
+    fn from_iter<I: IntoIterator<Item = SummaryItem>>(iter: I) -> Self {
+        Summary {
+            items: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dividend(date: &str, per_share: f64, shares: Option<f64>) -> SummaryItem {
+        SummaryItem {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            security_code: Some("7203".to_string()),
+            security_name: Some("トヨタ自動車".to_string()),
+            account: Some("特定".to_string()),
+            sector: None,
+            holding_days: None,
+            shares,
+            amount: (per_share * shares.unwrap_or(100.0)) as i64,
+        }
+    }
+
+    fn history(items: Vec<SummaryItem>) -> SecurityHistory {
+        let summary: Summary = items.into_iter().collect();
+        summary.by_security_year().remove("7203").unwrap()
+    }
+
+    #[test]
+    fn combined_pivot_adds_dividends_to_realized_profit_and_loss() {
+        let profit_and_loss: Summary = vec![
+            SummaryItem {
+                amount: -5000,
+                ..dividend("2023-12-05", 0.0, None)
+            },
+            SummaryItem {
+                amount: 12000,
+                ..dividend("2024-06-10", 0.0, None)
+            },
+        ]
+        .into_iter()
+        .collect();
+        let dividends: Summary = vec![
+            dividend("2024-06-20", 30.0, Some(100.0)),
+            dividend("2025-03-20", 30.0, Some(100.0)),
+        ]
+        .into_iter()
+        .collect();
+
+        let pivot = Summary::combined_pivot(&profit_and_loss, &dividends);
+        assert_eq!(
+            pivot.keys().copied().collect::<Vec<_>>(),
+            [2023, 2024, 2025]
+        );
+        assert_eq!(
+            pivot[&2023][11],
+            CombinedMonth {
+                profit_and_loss: -5000,
+                dividends: 0,
+            }
+        );
+        assert_eq!(pivot[&2024][5].total(), 15000);
+        assert_eq!(pivot[&2025][2].total(), 3000);
+        assert_eq!(pivot[&2025][5], CombinedMonth::default());
+    }
+
+    #[test]
+    fn a_raise_in_a_partial_year_is_not_a_cut() {
+        let history = history(vec![
+            dividend("2023-06-20", 30.0, Some(100.0)),
+            dividend("2023-12-20", 30.0, Some(100.0)),
+            dividend("2024-06-20", 35.0, Some(100.0)),
+        ]);
+        // 2024年は6月まで。直近12か月(2023年7月〜)は30円+35円、その前は30円
+        assert_eq!(history.trailing.per_share, Some(65.0));
+        assert_eq!(history.previous.per_share, Some(30.0));
+        assert!(history.trailing_growth().unwrap() > 0.0);
+        assert!(!history.is_cut());
+    }
+
+    #[test]
+    fn a_smaller_holding_is_not_a_cut() {
+        let history = history(vec![
+            dividend("2023-06-20", 30.0, Some(100.0)),
+            dividend("2023-12-20", 30.0, Some(100.0)),
+            dividend("2024-06-20", 30.0, Some(50.0)),
+            dividend("2024-12-20", 30.0, Some(50.0)),
+        ]);
+        assert_eq!(history.yearly[&2024].amount, 3000);
+        assert_eq!(history.growth(2024), Some(0.0));
+        assert_eq!(history.trailing_growth(), Some(0.0));
+        assert!(!history.is_cut());
+    }
+
+    #[test]
+    fn a_lower_dividend_per_share_is_a_cut() {
+        let history = history(vec![
+            dividend("2023-06-20", 30.0, Some(100.0)),
+            dividend("2023-12-20", 30.0, Some(100.0)),
+            dividend("2024-06-20", 30.0, Some(200.0)),
+            dividend("2024-12-20", 20.0, Some(200.0)),
+        ]);
+        // 金額は増えたが、1株あたりは60円から50円に減った
+        assert!(history.trailing.amount > history.previous.amount);
+        let growth = history.trailing_growth().unwrap();
+        assert!((growth - (50.0 - 60.0) / 60.0).abs() < 1e-9);
+        assert!(history.is_cut());
+        assert_eq!(history.growth(2024), Some(growth));
+    }
+
+    #[test]
+    fn growth_falls_back_to_amounts_without_shares() {
+        let history = history(vec![
+            dividend("2023-06-20", 30.0, Some(100.0)),
+            dividend("2024-06-20", 25.0, None),
+        ]);
+        assert_eq!(history.trailing.per_share, None);
+        let growth = history.trailing_growth().unwrap();
+        assert!((growth - (2500.0 - 3000.0) / 3000.0).abs() < 1e-9);
+        assert!(history.is_cut());
+    }
+
+    #[test]
+    fn no_payment_in_the_trailing_year_is_not_a_cut() {
+        let items = vec![
+            dividend("2022-06-20", 30.0, Some(100.0)),
+            SummaryItem {
+                security_code: Some("9432".to_string()),
+                ..dividend("2024-06-20", 5.0, Some(100.0))
+            },
+        ];
+        let summary: Summary = items.into_iter().collect();
+        let history = &summary.by_security_year()["7203"];
+        assert_eq!(history.trailing.count, 0);
+        assert_eq!(history.trailing_growth(), None);
+        assert!(!history.is_cut());
+    }
+
+    #[test]
+    fn yield_on_cost_uses_the_trailing_dividend_per_share_and_current_shares() {
+        let history = history(vec![
+            dividend("2023-06-20", 30.0, Some(100.0)),
+            dividend("2023-12-20", 30.0, Some(100.0)),
+            dividend("2024-06-20", 35.0, Some(100.0)),
+        ]);
+        let position = Position {
+            shares: Some(200.0),
+            cost: Some(520_000),
+        };
+        // (30円 + 35円) × 200株 / 520,000円
+        assert_eq!(history.yield_on_cost(&position), Some(0.025));
+
+        let without_shares = Position {
+            shares: None,
+            cost: Some(260_000),
+        };
+        assert_eq!(history.yield_on_cost(&without_shares), Some(0.025));
+
+        let without_cost = Position {
+            shares: Some(200.0),
+            cost: None,
+        };
+        assert_eq!(history.yield_on_cost(&without_cost), None);
+    }
+}
@@ -0,0 +1,209 @@
+use unicode_width::UnicodeWidthStr;
+
+/// 損失の文字色(赤)
+const LOSS_COLOR: &str = "\x1b[31m";
+/// 小計の強調(太字)
+const FOOTER_STYLE: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// 列の寄せ方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// 行の種類。ヘッダーの後に区切り線を入れ、小計は強調する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowKind {
+    Header, // ヘッダー
+    Record, // 明細
+    Footer, // 小計・合計
+}
+
+/// 表の1セル
+#[derive(Debug, Clone)]
+pub struct Cell {
+    pub text: String,  // 表示する文字列
+    pub align: Align,  // 寄せ方
+    pub is_loss: bool, // 損失(負の値)
+}
+
+impl Cell {
+    pub fn left(text: impl Into<String>) -> Self {
+        Cell {
+            text: text.into(),
+            align: Align::Left,
+            is_loss: false,
+        }
+    }
+
+    pub fn right(text: impl Into<String>) -> Self {
+        Cell {
+            text: text.into(),
+            align: Align::Right,
+            is_loss: false,
+        }
+    }
+
+    /// 3桁区切りの金額。負の値は損失として扱う
+    pub fn amount(amount: i64) -> Self {
+        Cell {
+            is_loss: amount < 0,
+            ..Cell::right(format_amount(amount))
+        }
+    }
+}
+
+/// 端末に表示する表。全角文字は2桁として揃える
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    rows: Vec<(RowKind, Vec<Cell>)>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Table { rows: Vec::new() }
+    }
+
+    pub fn push(&mut self, kind: RowKind, cells: Vec<Cell>) {
+        self.rows.push((kind, cells));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// 装飾なしで出力する
+    pub fn render(&self) -> String {
+        self.render_with(false)
+    }
+
+    /// `color`がtrueの場合、損失を赤、小計を太字で出力する
+    pub fn render_with(&self, color: bool) -> String {
+        let widths = self.widths();
+        let mut lines = Vec::new();
+        for (kind, cells) in &self.rows {
+            lines.push(Self::render_row(*kind, cells, &widths, color));
+            if *kind == RowKind::Header {
+                let separator: Vec<String> =
+                    widths.iter().map(|width| "-".repeat(*width)).collect();
+                lines.push(separator.join("  "));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = Vec::new();
+        for (_, cells) in &self.rows {
+            if widths.len() < cells.len() {
+                widths.resize(cells.len(), 0);
+            }
+            for (width, cell) in widths.iter_mut().zip(cells) {
+                *width = (*width).max(cell.text.width());
+            }
+        }
+        widths
+    }
+
+    fn render_row(kind: RowKind, cells: &[Cell], widths: &[usize], color: bool) -> String {
+        let texts: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(index, width)| {
+                let Some(cell) = cells.get(index) else {
+                    return " ".repeat(*width);
+                };
+                let text = pad(&cell.text, *width, cell.align);
+                if color && cell.is_loss {
+                    format!("{LOSS_COLOR}{text}{RESET}")
+                } else {
+                    text
+                }
+            })
+            .collect();
+        let line = texts.join("  ").trim_end().to_string();
+        if color && kind == RowKind::Footer {
+            // 損失の色の後も太字を続ける
+            let line = line.replace(RESET, &format!("{RESET}{FOOTER_STYLE}"));
+            format!("{FOOTER_STYLE}{line}{RESET}")
+        } else {
+            line
+        }
+    }
+}
+
+/// 表示幅が`width`になるよう空白で埋める
+pub fn pad(text: &str, width: usize, align: Align) -> String {
+    let padding = " ".repeat(width.saturating_sub(text.width()));
+    match align {
+        Align::Left => format!("{text}{padding}"),
+        Align::Right => format!("{padding}{text}"),
+    }
+}
+
+/// 3桁ごとにカンマで区切る
+pub fn format_amount(amount: i64) -> String {
+    let digits = amount.unsigned_abs().to_string();
+    let mut result = String::new();
+    for (index, c) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(c);
+    }
+    if amount < 0 {
+        format!("-{result}")
+    } else {
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pad_counts_full_width_characters_as_two_columns() {
+        assert_eq!(pad("損益", 6, Align::Left), "損益  ");
+        assert_eq!(pad("損益", 6, Align::Right), "  損益");
+        assert_eq!(pad("ｱｲ", 4, Align::Left), "ｱｲ  ");
+        assert_eq!(pad("7203 トヨタ", 12, Align::Left), "7203 トヨタ ");
+        // 幅を超える文字列は切り詰めない
+        assert_eq!(pad("実現損益", 4, Align::Right), "実現損益");
+    }
+
+    #[test]
+    fn format_amount_separates_thousands() {
+        assert_eq!(format_amount(0), "0");
+        assert_eq!(format_amount(999), "999");
+        assert_eq!(format_amount(1000), "1,000");
+        assert_eq!(format_amount(-123456), "-123,456");
+        assert_eq!(format_amount(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn render_aligns_columns_by_display_width() {
+        let mut table = Table::new();
+        table.push(
+            RowKind::Header,
+            vec![Cell::left("銘柄名"), Cell::right("損益")],
+        );
+        table.push(
+            RowKind::Record,
+            vec![Cell::left("ABC"), Cell::amount(-1500)],
+        );
+        table.push(
+            RowKind::Footer,
+            vec![Cell::left("合計"), Cell::amount(20000)],
+        );
+        assert_eq!(
+            table.render(),
+            "銘柄名    損益\n------  ------\nABC     -1,500\n合計    20,000"
+        );
+        assert!(table
+            .render_with(true)
+            .contains(&format!("{LOSS_COLOR}-1,500{RESET}")));
+    }
+}
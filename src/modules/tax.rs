@@ -0,0 +1,26 @@
+use crate::error::Error as ConvertError;
+
+/// 源泉徴収の対象の口座か。特定口座だけを対象とし、NISA・一般口座は対象外とする
+pub fn is_taxable_account(account: &str) -> bool {
+    account.contains("特定")
+}
+
+/// 税率が0以上1未満か確認する
+pub fn check_tax_rate(tax_rate: f64) -> Result<(), ConvertError> {
+    if (0.0..1.0).contains(&tax_rate) {
+        Ok(())
+    } else {
+        Err(ConvertError::Tax(format!(
+            "tax rate {tax_rate} is out of range. Use a rate between 0 and 1."
+        )))
+    }
+}
+
+/// 源泉徴収の対象の損益に対する源泉徴収税額を返す。損失の場合は0。1円未満は切り捨てる
+pub fn withholding_tax(taxable_total: i64, tax_rate: f64) -> i64 {
+    if taxable_total < 0 {
+        0
+    } else {
+        (taxable_total as f64 * tax_rate) as i64
+    }
+}
@@ -1,30 +1,125 @@
+use crate::error::{Error, Result};
+use crate::modules::columns::ColumnSetting;
+use crate::modules::context::Context;
 use crate::modules::csv::lib::CSVAccessor;
+use crate::modules::excel::lib::ExcelAccessor;
+use crate::modules::grouping::GroupBy;
+use crate::modules::report::pipeline::Transaction;
+use crate::modules::summary::summary::Summary;
 use csv::StringRecord;
-use std::error::Error;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// フィールド名と値の一覧
+pub type Fields = Vec<(String, Option<String>)>;
 
 pub struct TemplateStruct {
-    pub xlsx_filepath: PathBuf,
+    pub template_filepath: Option<PathBuf>,
+    pub grouping: Vec<GroupBy>,
+    pub columns: Vec<ColumnSetting>,
 }
 
 impl TemplateStruct {
-    pub fn new(xlsx_filepath: PathBuf) -> TemplateStruct {
-        TemplateStruct { xlsx_filepath }
+    pub fn new(
+        template_filepath: Option<PathBuf>,
+        grouping: Vec<GroupBy>,
+        columns: Vec<ColumnSetting>,
+    ) -> TemplateStruct {
+        TemplateStruct {
+            template_filepath,
+            grouping,
+            columns,
+        }
     }
 }
 
+/// レポートの種類ごとのパイプライン
+///
+/// 状態を持たず、各段階は前の段階の値を受け取って次の段階の値を返す
 pub trait TemplateManager {
-    fn execute(&self, csv_filepath: PathBuf) -> Result<(), Box<dyn Error>> {
-        let records = self.get(&csv_filepath)?;
-        self.set(records)?;
-        self.write()?;
-        Ok(())
+    /// CSVファイルを変換してブックに書き込み、警告を返す
+    fn execute(&self, csv_filepath: &Path, xlsx_filepath: &Path) -> Result<Vec<String>> {
+        let rows = self.get(csv_filepath)?;
+        let report = self.build(rows).map_err(|e| e.with_path(csv_filepath))?;
+        report.write(xlsx_filepath)?;
+        Ok(report.warnings())
+    }
+
+    /// CSVファイルの行を読み込む
+    fn get(&self, csv_filepath: &Path) -> Result<Vec<StringRecord>> {
+        let (headers, rows) = CSVAccessor::read_with_headers(csv_filepath)?;
+        self.arrange(&headers, rows)
+            .map_err(|e| e.with_path(csv_filepath))
+    }
+
+    /// 任意の入力からCSVの行を読み込む
+    fn read(&self, reader: &mut dyn Read) -> Result<Vec<StringRecord>> {
+        let (headers, rows) = CSVAccessor::read_from_with_headers(reader)?;
+        self.arrange(&headers, rows)
+    }
+
+    /// ヘッダーに合わせて行の列を並べ替える。既定ではそのまま返す
+    fn arrange(
+        &self,
+        _headers: &StringRecord,
+        rows: Vec<StringRecord>,
+    ) -> Result<Vec<StringRecord>> {
+        Ok(rows)
+    }
+
+    /// 行を解釈・正規化し、グループ化したレポートを返す
+    fn build(&self, rows: Vec<StringRecord>) -> Result<Box<dyn Report + '_>>;
+
+    /// 1行ずつ解釈・正規化し、問題のあった行のエラーを返す
+    fn problems(&self, rows: &[StringRecord]) -> Result<Vec<Error>>;
+
+    fn context(&self) -> &Context;
+
+    /// 明細を書き込むシート名
+    fn sheet_title(&self) -> &str {
+        &self.context().settings.sheet_title
     }
+}
+
+/// グループ化したレポート。明細とサマリーのシートに書き込む
+pub trait Report {
+    /// 設定ファイルでレポートを指定するキー
+    fn key(&self) -> &str;
+
+    /// グループ化した順の取引
+    fn transactions(&self) -> Vec<&Transaction>;
+
+    /// 明細とサマリーのシートを書き込む
+    fn render(&self, excel_accessor: &mut ExcelAccessor) -> Result<()>;
+
+    fn context(&self) -> &Context;
 
-    fn get(&self, csv_filepath: &PathBuf) -> Result<Vec<StringRecord>, Box<dyn Error>> {
-        CSVAccessor::read(csv_filepath)
+    /// 明細を書き込むシート名
+    fn sheet_title(&self) -> &str;
+
+    /// 変換は続けるが確認が必要な点
+    fn warnings(&self) -> Vec<String>;
+
+    /// 取引を月別・銘柄別・口座別に集計する
+    fn summary(&self) -> Summary {
+        self.transactions()
+            .into_iter()
+            .filter_map(Transaction::to_summary_item)
+            .collect()
     }
 
-    fn set(&self, records: Vec<StringRecord>) -> Result<(), Box<dyn Error>>;
-    fn write(&self) -> Result<(), Box<dyn Error>>;
+    /// 既存のブックに書き込んで保存する
+    fn write(&self, xlsx_filepath: &Path) -> Result<()> {
+        let mut excel_accessor =
+            ExcelAccessor::read_book(self.context(), self.sheet_title(), xlsx_filepath)?;
+        self.render(&mut excel_accessor)?;
+        excel_accessor.save_book()
+    }
+
+    /// 新しいブックに書き込んで`writer`に出力する
+    fn write_to(&self, writer: &mut dyn Write) -> Result<()> {
+        let mut excel_accessor = ExcelAccessor::new_book(self.context(), self.sheet_title())?;
+        self.render(&mut excel_accessor)?;
+        excel_accessor.write_to(writer)
+    }
 }
@@ -0,0 +1,528 @@
+use crate::batch::{csv_files, is_csv, Batch, BatchReport};
+use crate::error::{Error, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// 処理済みのCSVを移すサブディレクトリの既定の名前
+pub const DEFAULT_ARCHIVE_DIR: &str = "archive";
+/// 同じ名前のCSVを置いたときに、置き換えられた方を移すアーカイブのサブディレクトリ
+pub const REPLACED_DIR: &str = "replaced";
+/// ファイルの変更が止まってから変換するまでの既定の待ち時間
+pub const DEFAULT_SETTLE: Duration = Duration::from_secs(2);
+/// 通知がない間にファイルの状態を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 1ファイル分の処理の結果
+#[derive(Debug)]
+pub struct WatchEvent {
+    pub csv_filepath: PathBuf,     // 処理したCSVファイル
+    pub report: BatchReport,       // 変換の結果。`converted`には処理したCSVだけを含める
+    pub archived: Option<PathBuf>, // 変換後に移した先。変換できなかった場合は`None`
+    pub error: Option<Error>,      // 変換やアーカイブへの移動に失敗した理由
+}
+
+/// ファイルの大きさと更新日時。変わらなくなったら書き込みが終わったとみなす
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// 監視中のファイルの状態
+#[derive(Debug, Default)]
+pub struct PendingFiles {
+    changing: HashMap<PathBuf, (FileStamp, Instant)>, // 最後に変化を見た時刻
+    finished: HashMap<PathBuf, FileStamp>,            // 変換できず、その後変わっていないファイル
+}
+
+impl PendingFiles {
+    /// 変更の通知を受けたファイルを追加する
+    pub fn touch(&mut self, path: &Path, now: Instant) {
+        if !is_csv(path) {
+            return;
+        }
+        match FileStamp::read(path) {
+            Some(stamp) => {
+                if self.finished.get(path) == Some(&stamp) {
+                    return;
+                }
+                self.finished.remove(path);
+                self.changing.insert(path.to_path_buf(), (stamp, now));
+            }
+            None => {
+                // 削除・移動された
+                self.changing.remove(path);
+                self.finished.remove(path);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changing.is_empty()
+    }
+
+    /// `settle`の間変化のないファイルを取り出す。変化があれば待ち時間を数え直す
+    pub fn take_settled(&mut self, settle: Duration, now: Instant) -> Vec<PathBuf> {
+        let mut settled = Vec::new();
+        self.changing.retain(|path, (stamp, changed_at)| {
+            let Some(current) = FileStamp::read(path) else {
+                return false;
+            };
+            if current != *stamp {
+                *stamp = current;
+                *changed_at = now;
+                true
+            } else if now.duration_since(*changed_at) >= settle {
+                settled.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+        settled.sort();
+        settled
+    }
+}
+
+/// 入力のディレクトリを監視し、置かれたCSVを変換してアーカイブに移す
+///
+/// 変換は`Batch`で行うため、出力先の名前・テンプレート・グループ化は`Batch`の設定に従う
+pub struct Watch {
+    batch: Batch,
+    input_dir: PathBuf,
+    archive_dir: PathBuf,
+    settle: Duration,
+}
+
+impl Watch {
+    pub fn new(batch: Batch, input_dir: impl Into<PathBuf>) -> Self {
+        let input_dir = input_dir.into();
+        Watch {
+            batch,
+            archive_dir: input_dir.join(DEFAULT_ARCHIVE_DIR),
+            input_dir,
+            settle: DEFAULT_SETTLE,
+        }
+    }
+
+    /// 処理済みのCSVを移すディレクトリ。省略時は入力のディレクトリの archive
+    pub fn with_archive_dir(mut self, archive_dir: Option<PathBuf>) -> Self {
+        if let Some(archive_dir) = archive_dir {
+            self.archive_dir = archive_dir;
+        }
+        self
+    }
+
+    /// ファイルの変更が止まってから変換するまでの待ち時間
+    pub fn with_settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// 監視を始める前から置かれているCSVを追加する
+    pub fn scan(&self, pending: &mut PendingFiles, now: Instant) -> Result<()> {
+        for csv_filepath in csv_files(&self.input_dir)? {
+            pending.touch(&csv_filepath, now);
+        }
+        Ok(())
+    }
+
+    /// 変更が止まったファイルを変換し、変換できたものをアーカイブに移す
+    ///
+    /// 1ファイルの失敗は`WatchEvent`に記録し、残りのファイルの処理を続ける。
+    /// 通知を使わずに呼び出せるため、一時ディレクトリでの確認にも使える
+    pub fn process(&self, pending: &mut PendingFiles, now: Instant) -> Vec<WatchEvent> {
+        let mut events = Vec::new();
+        for csv_filepath in pending.take_settled(self.settle, now) {
+            let mut event = WatchEvent {
+                csv_filepath: csv_filepath.clone(),
+                report: BatchReport::default(),
+                archived: None,
+                error: None,
+            };
+            match self.convert(&csv_filepath) {
+                Ok(report) => event.report = report,
+                Err(e) => event.error = Some(e),
+            }
+            if !event.report.converted.is_empty() {
+                match self.archive(&csv_filepath) {
+                    Ok(archived) => event.archived = Some(archived),
+                    Err(e) => event.error = Some(e),
+                }
+            }
+            // 変換・移動できなかったファイルは、次に変更されるまで処理しない
+            if event.archived.is_none() {
+                if let Some(stamp) = FileStamp::read(&csv_filepath) {
+                    pending.finished.insert(csv_filepath, stamp);
+                }
+            }
+            events.push(event);
+        }
+        events
+    }
+
+    /// CSVを、同じブックに書き込むアーカイブ済みのCSVと合わせて変換する
+    ///
+    /// ブックのシートは作り直すため、それまでに変換したCSVの明細も書き込み直す
+    fn convert(&self, csv_filepath: &Path) -> Result<BatchReport> {
+        let mut csv_filepaths = self.archived_with_same_workbook(csv_filepath)?;
+        csv_filepaths.push(csv_filepath.to_path_buf());
+        let mut report = self.batch.convert_files(&csv_filepaths, &self.input_dir)?;
+        // アーカイブから読み直したCSVは結果に含めない
+        report
+            .converted
+            .retain(|file| file.csv_filepath == csv_filepath);
+        Ok(report)
+    }
+
+    /// アーカイブ済みのCSVのうち、`csv_filepath`と同じブックに書き込むもの
+    ///
+    /// 同じ名前のCSVは`csv_filepath`で置き換えるため含めない
+    fn archived_with_same_workbook(&self, csv_filepath: &Path) -> Result<Vec<PathBuf>> {
+        let Some(xlsx_filepath) = self.batch.xlsx_filepath(csv_filepath, &self.input_dir) else {
+            return Ok(Vec::new());
+        };
+        if !self.archive_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        Ok(csv_files(&self.archive_dir)?
+            .into_iter()
+            .filter(|archived| archived.file_name() != csv_filepath.file_name())
+            .filter(|archived| {
+                self.batch.xlsx_filepath(archived, &self.input_dir).as_ref() == Some(&xlsx_filepath)
+            })
+            .collect())
+    }
+
+    /// 監視を続け、1ファイル処理するごとに`on_event`を呼ぶ
+    pub fn run(&self, on_event: impl FnMut(WatchEvent)) -> Result<()> {
+        self.run_until(|| false, on_event)
+    }
+
+    /// `should_stop`がtrueを返すまで監視を続ける
+    pub fn run_until(
+        &self,
+        mut should_stop: impl FnMut() -> bool,
+        mut on_event: impl FnMut(WatchEvent),
+    ) -> Result<()> {
+        let watch_error = |e: notify::Error| Error::Input {
+            path: Some(self.input_dir.clone()),
+            source: std::io::Error::other(e),
+        };
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+        watcher
+            .watch(&self.input_dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+
+        let mut pending = PendingFiles::default();
+        self.scan(&mut pending, Instant::now())?;
+        while !should_stop() {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(event)) => {
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) {
+                        for path in &event.paths {
+                            pending.touch(path, Instant::now());
+                        }
+                    }
+                }
+                Ok(Err(e)) => return Err(watch_error(e)),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            for event in self.process(&mut pending, Instant::now()) {
+                on_event(event);
+            }
+        }
+        Ok(())
+    }
+
+    /// アーカイブに移す
+    ///
+    /// 同じ名前のファイルがあれば、置き換えられた方を`replaced`に日時を付けて移す。
+    /// アーカイブの直下には、ファイル名ごとに最後に置いたCSVだけが残る
+    pub fn archive(&self, csv_filepath: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(&self.archive_dir).map_err(|e| Error::output(&self.archive_dir, e))?;
+        let file_name = csv_filepath.file_name().unwrap_or_default();
+        let archived = self.archive_dir.join(file_name);
+        if archived.exists() {
+            let replaced_dir = self.archive_dir.join(REPLACED_DIR);
+            fs::create_dir_all(&replaced_dir).map_err(|e| Error::output(&replaced_dir, e))?;
+            let stem = csv_filepath
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let timestamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+            let mut replaced = replaced_dir.join(format!("{stem}_{timestamp}.csv"));
+            let mut count = 1;
+            while replaced.exists() {
+                count += 1;
+                replaced = replaced_dir.join(format!("{stem}_{timestamp}_{count}.csv"));
+            }
+            fs::rename(&archived, &replaced).map_err(|e| Error::output(&replaced, e))?;
+        }
+        fs::rename(csv_filepath, &archived).map_err(|e| Error::output(&archived, e))?;
+        Ok(archived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::modules::{context::Context, settings::Settings};
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    const HEADER: &str = "約定日,受渡日,銘柄コード,銘柄名,口座,信用区分,売却/決済,数量[株],売却/決済単価[円],売却/決済額[円],平均取得価額[円],実現損益[円]";
+
+    fn write_csv(path: &Path, rows: &[&str]) {
+        let mut text = format!("{HEADER}\n");
+        for row in rows {
+            text.push_str(row);
+            text.push('\n');
+        }
+        fs::write(path, text).unwrap();
+    }
+
+    fn toyota(path: &Path) {
+        write_csv(
+            path,
+            &["2024/01/10,2024/01/12,7203,トヨタ自動車,特定,,現物,100,\"3,000.0\",\"300,000\",\"2,900.0\",\"10,000\""],
+        );
+    }
+
+    fn sony(path: &Path) {
+        write_csv(
+            path,
+            &["2024/02/05,2024/02/07,6758,ソニーグループ,特定,,現物,100,\"13,000.0\",\"1,300,000\",\"12,000.0\",\"100,000\""],
+        );
+    }
+
+    fn watch(input_dir: &Path) -> Watch {
+        let batch = Batch::new(Context::from(Settings::defaults()));
+        Watch::new(batch, input_dir).with_settle(Duration::from_secs(2))
+    }
+
+    /// 明細のシートに書き込まれた銘柄コード
+    fn security_codes(xlsx_filepath: &Path) -> Vec<String> {
+        let book = umya_spreadsheet::reader::xlsx::read(xlsx_filepath).unwrap();
+        let sheet = book
+            .get_sheet_by_name(&Settings::defaults().sheet_title)
+            .unwrap();
+        let mut codes: Vec<String> = sheet
+            .get_cell_collection()
+            .into_iter()
+            .map(|cell| cell.get_value().to_string())
+            .filter(|value| value == "7203" || value == "6758")
+            .collect();
+        codes.sort();
+        codes
+    }
+
+    #[test]
+    fn touch_ignores_other_files_and_settles_after_the_wait() {
+        let dir = TempDir::new().unwrap();
+        let csv_filepath = dir.path().join("realized_pl_a.csv");
+        let text_filepath = dir.path().join("memo.txt");
+        toyota(&csv_filepath);
+        fs::write(&text_filepath, "memo").unwrap();
+
+        let start = Instant::now();
+        let mut pending = PendingFiles::default();
+        pending.touch(&csv_filepath, start);
+        pending.touch(&text_filepath, start);
+        assert!(!pending.is_empty());
+
+        let settle = Duration::from_secs(2);
+        assert!(pending
+            .take_settled(settle, start + Duration::from_secs(1))
+            .is_empty());
+        assert_eq!(
+            pending.take_settled(settle, start + settle),
+            vec![csv_filepath]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn take_settled_restarts_the_wait_when_the_file_changes() {
+        let dir = TempDir::new().unwrap();
+        let csv_filepath = dir.path().join("realized_pl_a.csv");
+        toyota(&csv_filepath);
+
+        let start = Instant::now();
+        let settle = Duration::from_secs(2);
+        let mut pending = PendingFiles::default();
+        pending.touch(&csv_filepath, start);
+
+        // 書き込みが続いている
+        sony(&csv_filepath);
+        let changed = start + Duration::from_secs(3);
+        assert!(pending.take_settled(settle, changed).is_empty());
+        assert!(pending
+            .take_settled(settle, changed + Duration::from_secs(1))
+            .is_empty());
+        assert_eq!(
+            pending.take_settled(settle, changed + settle),
+            vec![csv_filepath]
+        );
+    }
+
+    #[test]
+    fn touch_forgets_removed_files() {
+        let dir = TempDir::new().unwrap();
+        let csv_filepath = dir.path().join("realized_pl_a.csv");
+        toyota(&csv_filepath);
+
+        let mut pending = PendingFiles::default();
+        pending.touch(&csv_filepath, Instant::now());
+        fs::remove_file(&csv_filepath).unwrap();
+        pending.touch(&csv_filepath, Instant::now());
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn process_keeps_rows_of_archived_files_in_the_workbook() {
+        let dir = TempDir::new().unwrap();
+        let watch = watch(dir.path());
+        let start = Instant::now();
+        let later = start + Duration::from_secs(2);
+        let mut pending = PendingFiles::default();
+
+        toyota(&dir.path().join("realized_pl_a.csv"));
+        watch.scan(&mut pending, start).unwrap();
+        let events = watch.process(&mut pending, later);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].error.is_none());
+        assert_eq!(
+            events[0].archived,
+            Some(
+                dir.path()
+                    .join(DEFAULT_ARCHIVE_DIR)
+                    .join("realized_pl_a.csv")
+            )
+        );
+
+        sony(&dir.path().join("realized_pl_b.csv"));
+        watch.scan(&mut pending, later).unwrap();
+        let events = watch.process(&mut pending, later + Duration::from_secs(2));
+        assert_eq!(events.len(), 1);
+        // 結果には置いたCSVだけを含める
+        assert_eq!(events[0].report.converted.len(), 1);
+        assert_eq!(
+            events[0].report.converted[0].csv_filepath,
+            dir.path().join("realized_pl_b.csv")
+        );
+
+        let xlsx_filepath = dir.path().join("profit_and_loss.xlsx");
+        assert_eq!(security_codes(&xlsx_filepath), vec!["6758", "7203"]);
+    }
+
+    #[test]
+    fn archive_moves_the_replaced_file_aside() {
+        let dir = TempDir::new().unwrap();
+        let watch = watch(dir.path());
+        let csv_filepath = dir.path().join("realized_pl_a.csv");
+
+        toyota(&csv_filepath);
+        let archived = watch.archive(&csv_filepath).unwrap();
+        sony(&csv_filepath);
+        assert_eq!(watch.archive(&csv_filepath).unwrap(), archived);
+        toyota(&csv_filepath);
+        assert_eq!(watch.archive(&csv_filepath).unwrap(), archived);
+
+        assert!(!csv_filepath.exists());
+        let replaced = csv_files(&dir.path().join(DEFAULT_ARCHIVE_DIR).join(REPLACED_DIR)).unwrap();
+        assert_eq!(replaced.len(), 2);
+        assert_ne!(replaced[0], replaced[1]);
+        // アーカイブの直下には最後に置いたCSVが残る
+        assert!(fs::read_to_string(&archived).unwrap().contains("7203"));
+    }
+
+    #[test]
+    fn process_replaces_the_rows_of_a_file_dropped_again() {
+        let dir = TempDir::new().unwrap();
+        let watch = watch(dir.path());
+        let start = Instant::now();
+        let mut pending = PendingFiles::default();
+        let csv_filepath = dir.path().join("realized_pl_a.csv");
+
+        toyota(&csv_filepath);
+        watch.scan(&mut pending, start).unwrap();
+        watch.process(&mut pending, start + Duration::from_secs(2));
+
+        // 同じ名前で置き直したCSVは前の明細を置き換える
+        sony(&csv_filepath);
+        watch.scan(&mut pending, start).unwrap();
+        let events = watch.process(&mut pending, start + Duration::from_secs(4));
+        assert!(events[0].archived.is_some());
+        let xlsx_filepath = dir.path().join("profit_and_loss.xlsx");
+        assert_eq!(security_codes(&xlsx_filepath), vec!["6758"]);
+    }
+
+    #[test]
+    fn process_records_failures_and_keeps_the_file() {
+        let dir = TempDir::new().unwrap();
+        let watch = watch(dir.path());
+        let start = Instant::now();
+        let mut pending = PendingFiles::default();
+        let csv_filepath = dir.path().join("realized_pl_bad.csv");
+        write_csv(
+            &csv_filepath,
+            &["not a date,,7203,トヨタ自動車,特定,,現物,100,1,1,1,1"],
+        );
+
+        watch.scan(&mut pending, start).unwrap();
+        let events = watch.process(&mut pending, start + Duration::from_secs(2));
+        assert_eq!(events.len(), 1);
+        assert!(events[0].archived.is_none());
+        assert_eq!(events[0].report.errors.len(), 1);
+        assert!(csv_filepath.exists());
+
+        // 変更されるまでは処理し直さない
+        watch.scan(&mut pending, start).unwrap();
+        assert!(watch
+            .process(&mut pending, start + Duration::from_secs(4))
+            .is_empty());
+    }
+
+    #[test]
+    fn process_records_an_archive_failure_in_the_event() {
+        let dir = TempDir::new().unwrap();
+        // アーカイブのディレクトリを作れないようにファイルを置く
+        let archive_dir = dir.path().join("blocked");
+        fs::write(&archive_dir, "").unwrap();
+        let watch = watch(dir.path()).with_archive_dir(Some(archive_dir));
+        let start = Instant::now();
+        let mut pending = PendingFiles::default();
+        let csv_filepath = dir.path().join("realized_pl_a.csv");
+        toyota(&csv_filepath);
+
+        watch.scan(&mut pending, start).unwrap();
+        let events = watch.process(&mut pending, start + Duration::from_secs(2));
+        assert_eq!(events.len(), 1);
+        assert!(events[0].archived.is_none());
+        let error = events[0].error.as_ref().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Output);
+        assert_eq!(error.exit_code(), 9);
+        assert!(csv_filepath.exists());
+    }
+}